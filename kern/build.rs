@@ -10,6 +10,8 @@ fn main() {
     let base_address: usize = {
         match arch {
             "riscv32" => 0x8040_0000,
+            "riscv64" if std::env::var_os("CARGO_FEATURE_SV57").is_some() => 0xFF00_0000_8020_0000,
+            "riscv64" if std::env::var_os("CARGO_FEATURE_SV48").is_some() => 0xFFFF_8000_8020_0000,
             "riscv64" => 0xFFFF_FFC0_8020_0000,
             other_arch => panic!("Unsupported arch: {}", other_arch),
         }
@@ -20,13 +20,17 @@ impl PhysAddr {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Address)]
 pub struct VirtAddr(usize);
 
+// a deeper layout enabled along with the default one of the target takes precedence over it
 impl VirtAddr {
     #[cfg(feature = "pt_level_2")]
     pub fn indexes(self) -> [usize; 2] {
         [self.0 >> 22 & 0x3ff, self.0 >> 12 & 0x3ff]
     }
 
-    #[cfg(feature = "pt_level_3")]
+    #[cfg(all(
+        feature = "pt_level_3",
+        not(any(feature = "pt_level_4", feature = "pt_level_5"))
+    ))]
     pub fn indexes(self) -> [usize; 3] {
         [
             self.0 >> 30 & 0x1ff,
@@ -35,6 +39,27 @@ impl VirtAddr {
         ]
     }
 
+    #[cfg(all(feature = "pt_level_4", not(feature = "pt_level_5")))]
+    pub fn indexes(self) -> [usize; 4] {
+        [
+            self.0 >> 39 & 0x1ff,
+            self.0 >> 30 & 0x1ff,
+            self.0 >> 21 & 0x1ff,
+            self.0 >> 12 & 0x1ff,
+        ]
+    }
+
+    #[cfg(feature = "pt_level_5")]
+    pub fn indexes(self) -> [usize; 5] {
+        [
+            self.0 >> 48 & 0x1ff,
+            self.0 >> 39 & 0x1ff,
+            self.0 >> 30 & 0x1ff,
+            self.0 >> 21 & 0x1ff,
+            self.0 >> 12 & 0x1ff,
+        ]
+    }
+
     pub fn as_array_base<T>(self) -> &'static mut [T] {
         unsafe {
             from_raw_parts_mut(
@@ -6,6 +6,10 @@ pub const PHYS_MEM_BASE: usize = 0x8000_0000;
 
 cfg_if! {
     if #[cfg(target_arch = "riscv32")] {
+        #[cfg(any(feature = "sv48", feature = "sv57"))]
+        compile_error!("Sv48 and Sv57 are only available on riscv64");
+
+        pub const PT_LEVELS: usize = 2;
         pub const PHYS_MEM_LIMIT: usize = 0xC000_0000;
         pub const REMAP_HUGE_PAGE_SIZE: usize = 1024 * crate::PAGE_SIZE;
         pub const REMAP_MEM_OFFSET: usize = 0x0000_0000;
@@ -24,10 +28,45 @@ cfg_if! {
             addr: 0xE000_0000,
             len: 0xF000_0000 - 0xE000_0000,
         };
+        pub const USER_MMAP_REGION: VirtMemRegion = VirtMemRegion {
+            addr: 0x4000_0000,
+            len: 0x7000_0000 - 0x4000_0000,
+        };
+        pub const USER_STACK_TOP: usize = 0x8000_0000;
     } else if #[cfg(target_arch = "riscv64")] {
-        pub const PHYS_MEM_LIMIT: usize = 0x0000_0020_0000_0000;
+        // the kernel half of each paging mode remaps as much memory as it has room for, below the
+        // executor stacks and device registers which stay at the top
+        cfg_if! {
+            if #[cfg(feature = "sv57")] {
+                pub const PT_LEVELS: usize = 5;
+                pub const PHYS_MEM_LIMIT: usize = 0x0000_0400_0000_0000;
+                pub const REMAP_MEM_OFFSET: usize = 0xFF00_0000_0000_0000;
+                pub const USER_MMAP_REGION: VirtMemRegion = VirtMemRegion {
+                    addr: 0x0080_0000_0000_0000,
+                    len: 0x00C0_0000_0000_0000 - 0x0080_0000_0000_0000,
+                };
+                pub const USER_STACK_TOP: usize = 0x0100_0000_0000_0000;
+            } else if #[cfg(feature = "sv48")] {
+                pub const PT_LEVELS: usize = 4;
+                pub const PHYS_MEM_LIMIT: usize = 0x0000_0080_0000_0000;
+                pub const REMAP_MEM_OFFSET: usize = 0xFFFF_8000_0000_0000;
+                pub const USER_MMAP_REGION: VirtMemRegion = VirtMemRegion {
+                    addr: 0x0000_4000_0000_0000,
+                    len: 0x0000_6000_0000_0000 - 0x0000_4000_0000_0000,
+                };
+                pub const USER_STACK_TOP: usize = 0x0000_8000_0000_0000;
+            } else {
+                pub const PT_LEVELS: usize = 3;
+                pub const PHYS_MEM_LIMIT: usize = 0x0000_0020_0000_0000;
+                pub const REMAP_MEM_OFFSET: usize = 0xFFFF_FFC0_0000_0000;
+                pub const USER_MMAP_REGION: VirtMemRegion = VirtMemRegion {
+                    addr: 0x0000_0020_0000_0000,
+                    len: 0x0000_0030_0000_0000 - 0x0000_0020_0000_0000,
+                };
+                pub const USER_STACK_TOP: usize = 0x0000_0040_0000_0000;
+            }
+        }
         pub const REMAP_HUGE_PAGE_SIZE: usize = 512 * 512 * crate::PAGE_SIZE;
-        pub const REMAP_MEM_OFFSET: usize = 0xFFFF_FFC0_0000_0000;
         pub const REMAP_MEM_REGIONS: &[RemapMemRegion] = &[
             RemapMemRegion {
                 virt_addr: PHYS_MEM_BASE,
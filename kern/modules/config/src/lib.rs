@@ -16,8 +16,15 @@ pub struct VirtMemRegion {
 
 pub const PAGE_SIZE: usize = 4096;
 pub const KSTACK_SIZE: usize = PAGE_SIZE * 8;
+pub const USTACK_SIZE: usize = PAGE_SIZE * 8;
 
 pub const HEAP_ORDER: usize = 32;
 pub const KHEAP_SIZE: usize = PAGE_SIZE * 8;
+/// The heap grows by taking at least this much from the frame allocator.
+pub const HEAP_GROW_SIZE: usize = PAGE_SIZE * 256;
+/// Frames held back for the heap once ordinary allocations run out of them.
+pub const HEAP_RESERVE_SIZE: usize = PAGE_SIZE * 64;
 
 pub const EXECUTOR_STACK_SIZE: usize = PAGE_SIZE * 1024;
+/// Frames set aside for coherent DMA buffers, before they are taken from the frame allocator.
+pub const DMA_POOL_SIZE: usize = PAGE_SIZE * 256;
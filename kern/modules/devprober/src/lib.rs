@@ -75,6 +75,18 @@ pub fn probe_all_device(fdt: &Fdt) -> Result<()> {
     Ok(())
 }
 
+/// Returns whether a kernel driver probes `node`, in which case the device belongs to the kernel.
+pub fn has_prober(node: &FdtNode) -> bool {
+    devprober_iter().any(|devprober| match devprober.ident {
+        DevIdent::DeviceType(device_type) => node
+            .property("device_type")
+            .is_some_and(|prop| prop.as_str().is_some_and(|ty| ty == device_type)),
+        DevIdent::Compatible(compatible) => node
+            .compatible()
+            .is_some_and(|cp| cp.all().any(|c| c == compatible)),
+    })
+}
+
 fn devprober_iter() -> impl Iterator<Item = &'static DevProber> {
     (jrinx_layout::_sdev()..jrinx_layout::_edev())
         .step_by(core::mem::size_of::<&DevProber>())
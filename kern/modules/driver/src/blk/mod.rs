@@ -0,0 +1,29 @@
+pub mod virtio_blk;
+
+use alloc::{sync::Arc, vec::Vec};
+
+use spin::RwLock;
+
+use crate::BlockDevice;
+
+static BLOCK_DEVICES: RwLock<Vec<Arc<dyn BlockDevice>>> = RwLock::new(Vec::new());
+
+pub(crate) fn register(dev: Arc<dyn BlockDevice>) {
+    info!(
+        "block device #{} registered, {} blocks of {} bytes",
+        BLOCK_DEVICES.read().len(),
+        dev.num_blocks(),
+        dev.block_size(),
+    );
+    BLOCK_DEVICES.write().push(dev);
+}
+
+/// Returns the first block device probed, if any.
+pub fn first() -> Option<Arc<dyn BlockDevice>> {
+    BLOCK_DEVICES.read().first().cloned()
+}
+
+/// Returns block device #`index`, counting in the order they are probed.
+pub fn get(index: usize) -> Option<Arc<dyn BlockDevice>> {
+    BLOCK_DEVICES.read().get(index).cloned()
+}
@@ -0,0 +1,49 @@
+use jrinx_error::{InternalError, Result};
+use spin::Mutex;
+use virtio_drivers::{
+    device::blk::{VirtIOBlk, SECTOR_SIZE},
+    transport::mmio::MmioTransport,
+};
+
+use crate::{bus::virtio::VirtioHal, BlockDevice};
+
+/// A virtio block device, driven by polling as requests are few and small.
+pub struct VirtIoBlk {
+    inner: Mutex<VirtIOBlk<VirtioHal, MmioTransport>>,
+}
+
+unsafe impl Send for VirtIoBlk {}
+unsafe impl Sync for VirtIoBlk {}
+
+impl VirtIoBlk {
+    pub fn new(transport: MmioTransport) -> Result<Self> {
+        let inner = VirtIOBlk::new(transport).map_err(|_| InternalError::DevProbeError)?;
+        Ok(Self {
+            inner: Mutex::new(inner),
+        })
+    }
+}
+
+impl BlockDevice for VirtIoBlk {
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn num_blocks(&self) -> usize {
+        self.inner.lock().capacity() as usize
+    }
+
+    fn read_blocks(&self, block: usize, buf: &mut [u8]) -> Result<()> {
+        self.inner
+            .lock()
+            .read_blocks(block, buf)
+            .map_err(|_| InternalError::DevReadError)
+    }
+
+    fn write_blocks(&self, block: usize, buf: &[u8]) -> Result<()> {
+        self.inner
+            .lock()
+            .write_blocks(block, buf)
+            .map_err(|_| InternalError::DevWriteError)
+    }
+}
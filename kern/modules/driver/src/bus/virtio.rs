@@ -1,11 +1,20 @@
-use core::{
-    alloc::{Allocator, Layout},
-    ptr::NonNull,
-};
+use core::ptr::NonNull;
 
-use alloc::alloc::Global;
-use jrinx_config::{PAGE_SIZE, REMAP_MEM_OFFSET};
-use virtio_drivers::Hal;
+use jrinx_addr::PhysAddr;
+use jrinx_config::REMAP_MEM_OFFSET;
+use virtio_drivers::{BufferDirection, Hal};
+
+use crate::dma::{self, Direction};
+
+impl From<BufferDirection> for Direction {
+    fn from(direction: BufferDirection) -> Self {
+        match direction {
+            BufferDirection::DriverToDevice => Direction::ToDevice,
+            BufferDirection::DeviceToDriver => Direction::FromDevice,
+            BufferDirection::Both => Direction::Bidirectional,
+        }
+    }
+}
 
 pub struct VirtioHal;
 unsafe impl Hal for VirtioHal {
@@ -13,28 +22,19 @@ unsafe impl Hal for VirtioHal {
         pages: usize,
         _direction: virtio_drivers::BufferDirection,
     ) -> (virtio_drivers::PhysAddr, core::ptr::NonNull<u8>) {
-        let layout = Layout::from_size_align(pages * PAGE_SIZE, PAGE_SIZE).unwrap();
-        let ptr = Global.allocate_zeroed(layout);
-        let ptr = ptr.unwrap();
-        let addr = ptr.cast::<usize>().as_ptr() as usize;
-        // unsafe{
-        //     for i in 0..pages{
-        //         BootPageTable.map(
-        //             VirtAddr::new(addr + i * PAGE_SIZE),
-        //             PhysAddr::new(addr - REMAP_MEM_OFFSET  + i * PAGE_SIZE),
-        //         );
-        //     }
-        // }
-        (addr - REMAP_MEM_OFFSET, ptr.cast::<u8>())
+        // virtio-drivers fails the request with a DMA error if the physical address is 0
+        match dma::alloc_coherent(pages) {
+            Ok((paddr, vaddr)) => (paddr.as_usize(), vaddr),
+            Err(_) => (0, NonNull::dangling()),
+        }
     }
 
     unsafe fn dma_dealloc(
-        _paddr: virtio_drivers::PhysAddr,
-        vaddr: core::ptr::NonNull<u8>,
+        paddr: virtio_drivers::PhysAddr,
+        _vaddr: core::ptr::NonNull<u8>,
         pages: usize,
     ) -> i32 {
-        let layout = Layout::from_size_align(pages * PAGE_SIZE, PAGE_SIZE).unwrap();
-        Global.deallocate(vaddr, layout);
+        dma::dealloc_coherent(PhysAddr::new(paddr), pages);
         0
     }
     unsafe fn mmio_phys_to_virt(
@@ -46,16 +46,26 @@ unsafe impl Hal for VirtioHal {
 
     unsafe fn share(
         buffer: core::ptr::NonNull<[u8]>,
-        _direction: virtio_drivers::BufferDirection,
+        direction: virtio_drivers::BufferDirection,
     ) -> virtio_drivers::PhysAddr {
-        let vaddr = buffer.as_ptr() as *mut u8 as usize;
-        vaddr - REMAP_MEM_OFFSET
+        // virtio-drivers has no way to fail sharing a buffer, e.g. if it needs a bounce buffer and
+        // frames run out
+        dma::map(buffer, direction.into())
+            .unwrap_or_else(|err| {
+                panic!(
+                    "virtio: failed to map a {}-byte buffer for DMA: {:?}",
+                    buffer.len(),
+                    err
+                )
+            })
+            .as_usize()
     }
 
     unsafe fn unshare(
-        _paddr: virtio_drivers::PhysAddr,
-        _buffer: core::ptr::NonNull<[u8]>,
-        _direction: virtio_drivers::BufferDirection,
+        paddr: virtio_drivers::PhysAddr,
+        buffer: core::ptr::NonNull<[u8]>,
+        direction: virtio_drivers::BufferDirection,
     ) {
+        dma::unmap(PhysAddr::new(paddr), buffer, direction.into());
     }
 }
@@ -0,0 +1,134 @@
+//! Devices in the FDT no kernel driver is bound to, whose registers and interrupts are left for
+//! drivers in user space.
+
+use alloc::{string::String, vec::Vec};
+
+use fdt::{node::FdtNode, Fdt};
+use jrinx_addr::PhysAddr;
+use jrinx_error::{InternalError, Result};
+use spin::RwLock;
+
+static DEVICE_REGISTRY: RwLock<Vec<Device>> = RwLock::new(Vec::new());
+
+#[derive(Debug, Clone)]
+pub struct Device {
+    name: String,
+    compatible: String,
+    regs: Vec<(PhysAddr, usize)>,
+    irqs: Vec<usize>,
+    irq_parent: Option<usize>,
+}
+
+impl Device {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn compatible(&self) -> &str {
+        &self.compatible
+    }
+
+    /// Returns the register regions of the device, as `(addr, len)` pairs.
+    pub fn regs(&self) -> &[(PhysAddr, usize)] {
+        &self.regs
+    }
+
+    pub fn irqs(&self) -> &[usize] {
+        &self.irqs
+    }
+
+    /// Returns the phandle of the interrupt controller the interrupts of the device go to.
+    pub fn irq_parent(&self) -> Option<usize> {
+        self.irq_parent
+    }
+
+    fn from_node(node: &FdtNode) -> Option<Self> {
+        let compatible = node.compatible()?.first();
+        let regs = node
+            .reg()?
+            .filter_map(|region| {
+                let size = region.size?;
+                Some((PhysAddr::new(region.starting_address as usize), size))
+            })
+            .collect::<Vec<_>>();
+        if regs.is_empty() {
+            return None;
+        }
+        let irqs = node
+            .interrupts()
+            .map(|irqs| irqs.collect())
+            .unwrap_or_default();
+        let irq_parent = node
+            .interrupt_parent()
+            .and_then(|parent| parent.property("phandle"))
+            .and_then(|phandle| phandle.as_usize());
+        Some(Self {
+            name: String::from(node.name),
+            compatible: String::from(compatible),
+            regs,
+            irqs,
+            irq_parent,
+        })
+    }
+}
+
+/// Collects the devices of `fdt` left for user space, which must run after every kernel driver
+/// has been probed.
+pub(crate) fn init(fdt: &Fdt<'_>) {
+    let mut registry = DEVICE_REGISTRY.write();
+    for node in fdt.all_nodes() {
+        // interrupt controllers and devices wired straight to the CPUs, e.g. the CLINT, are
+        // kept away from user space along with every device a kernel driver is bound to
+        if node.property("interrupt-controller").is_some()
+            || node.property("interrupts-extended").is_some()
+            || jrinx_devprober::has_prober(&node)
+        {
+            continue;
+        }
+        if let Some(device) = Device::from_node(&node) {
+            info!(
+                "device {} ({}) left for user space",
+                device.name, device.compatible
+            );
+            registry.push(device);
+        }
+    }
+}
+
+/// Returns the first device left for user space that is compatible with `compatible`.
+pub fn find(compatible: &str) -> Option<Device> {
+    DEVICE_REGISTRY
+        .read()
+        .iter()
+        .find(|device| device.compatible == compatible)
+        .cloned()
+}
+
+/// Returns the device whose registers fully cover `[addr, addr + len)`.
+pub fn find_region(addr: PhysAddr, len: usize) -> Result<Device> {
+    let end = addr
+        .as_usize()
+        .checked_add(len)
+        .ok_or(InternalError::InvalidDeviceRegion)?;
+    DEVICE_REGISTRY
+        .read()
+        .iter()
+        .find(|device| {
+            device
+                .regs
+                .iter()
+                .any(|&(reg, size)| reg <= addr && end <= reg.as_usize() + size)
+        })
+        .cloned()
+        .ok_or(InternalError::InvalidDeviceRegion)
+}
+
+/// Returns the device raising `irq_num`.
+pub fn find_irq(irq_num: usize) -> Result<Device> {
+    DEVICE_REGISTRY
+        .read()
+        .iter()
+        .find(|device| device.irqs.contains(&irq_num))
+        .cloned()
+        .ok_or(InternalError::InvalidIrqNumber)
+}
@@ -0,0 +1,236 @@
+//! DMA for drivers, through buffers coherent with devices and streaming mappings of buffers the
+//! drivers own.
+//!
+//! Coherent buffers come from a pool of frames set aside on first use, or from the frame allocator
+//! once it runs out. A streaming mapping hands the device the physical address of a buffer in the
+//! linear mapping, after the cache maintenance its direction needs. A buffer out of the linear
+//! mapping, e.g. on an executor stack, or beyond the address limit of devices is bounced through a
+//! coherent buffer instead.
+
+use core::{
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::collections::BTreeMap;
+use jrinx_addr::{PhysAddr, VirtAddr};
+use jrinx_config::{DMA_POOL_SIZE, PAGE_SIZE, PHYS_MEM_BASE, PHYS_MEM_LIMIT};
+use jrinx_error::{InternalError, Result};
+use jrinx_hal::{hal, Cache, Hal};
+use jrinx_phys_frame::frame_alloc;
+use spin::{Lazy, Mutex};
+
+const POOL_PAGES: usize = DMA_POOL_SIZE / PAGE_SIZE;
+
+static POOL: Lazy<Mutex<Pool>> = Lazy::new(|| Mutex::new(Pool::new()));
+
+/// Bounce buffers by their physical address, along with their size in pages.
+static BOUNCES: Mutex<BTreeMap<PhysAddr, usize>> = Mutex::new(BTreeMap::new());
+
+static ADDR_LIMIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+static COHERENT_PAGES: AtomicUsize = AtomicUsize::new(0);
+static MAPPED: AtomicUsize = AtomicUsize::new(0);
+static BOUNCED: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    ToDevice,
+    FromDevice,
+    Bidirectional,
+}
+
+/// Buffers handed out and not yet given back, to tell what a driver leaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmaStats {
+    /// Pages of coherent buffers.
+    pub coherent: usize,
+    /// Streaming mappings, including the bounced ones.
+    pub mapped: usize,
+    /// Streaming mappings bounced through a coherent buffer.
+    pub bounced: usize,
+}
+
+struct Pool {
+    base: Option<PhysAddr>,
+    used: [bool; POOL_PAGES],
+}
+
+impl Pool {
+    fn new() -> Self {
+        let base = frame_alloc::alloc(POOL_PAGES, PAGE_SIZE).ok();
+        if base.is_none() {
+            warn!("no frames for the DMA pool, coherent buffers are allocated one by one");
+        }
+        Self {
+            base,
+            used: [false; POOL_PAGES],
+        }
+    }
+
+    fn alloc(&mut self, pages: usize) -> Option<PhysAddr> {
+        let base = self.base?;
+        let start = (0..=POOL_PAGES.checked_sub(pages)?)
+            .find(|&start| self.used[start..start + pages].iter().all(|&used| !used))?;
+        self.used[start..start + pages].fill(true);
+        Some(base + start * PAGE_SIZE)
+    }
+
+    fn dealloc(&mut self, addr: PhysAddr, pages: usize) -> bool {
+        let Some(base) = self
+            .base
+            .filter(|&base| base <= addr && addr < base + DMA_POOL_SIZE)
+        else {
+            return false;
+        };
+        let start = (addr - base) / PAGE_SIZE;
+        self.used[start..start + pages].fill(false);
+        true
+    }
+}
+
+/// Sets the address devices can reach up to, exclusive. Buffers beyond are bounced.
+pub fn set_addr_limit(limit: usize) {
+    ADDR_LIMIT.store(limit, Ordering::SeqCst);
+}
+
+pub fn stats() -> DmaStats {
+    DmaStats {
+        coherent: COHERENT_PAGES.load(Ordering::SeqCst),
+        mapped: MAPPED.load(Ordering::SeqCst),
+        bounced: BOUNCED.load(Ordering::SeqCst),
+    }
+}
+
+/// Allocates a zeroed buffer of `pages` pages coherent with devices, returning its physical
+/// address and where the CPU accesses it.
+pub fn alloc_coherent(pages: usize) -> Result<(PhysAddr, NonNull<u8>)> {
+    let addr = alloc_pages(pages)?;
+    COHERENT_PAGES.fetch_add(pages, Ordering::SeqCst);
+    Ok((
+        addr,
+        NonNull::new(addr.to_virt().as_usize() as *mut u8).unwrap(),
+    ))
+}
+
+/// Gives back a buffer allocated by [`alloc_coherent`].
+///
+/// # Safety
+///
+/// The buffer must no longer be used, neither by the CPU nor by any device.
+pub unsafe fn dealloc_coherent(addr: PhysAddr, pages: usize) {
+    dealloc_pages(addr, pages);
+    COHERENT_PAGES.fetch_sub(pages, Ordering::SeqCst);
+}
+
+/// Maps `buf` for a device to access in `direction`, returning the address the device accesses it
+/// at.
+///
+/// # Safety
+///
+/// `buf` must stay valid and must not be accessed by the CPU until it is unmapped by [`unmap`].
+pub unsafe fn map(buf: NonNull<[u8]>, direction: Direction) -> Result<PhysAddr> {
+    let vaddr = VirtAddr::new(buf.as_ptr() as *mut u8 as usize);
+    let len = buf.len();
+
+    let addr = match direct(vaddr, len) {
+        Some(addr) => {
+            sync_for_device(vaddr, len, direction);
+            addr
+        }
+        None => {
+            let pages = len.div_ceil(PAGE_SIZE).max(1);
+            let addr = alloc_pages(pages)?;
+            let bounce = addr.to_virt();
+            if direction != Direction::FromDevice {
+                ptr::copy_nonoverlapping(
+                    vaddr.as_usize() as *const u8,
+                    bounce.as_usize() as *mut u8,
+                    len,
+                );
+            }
+            sync_for_device(bounce, len, direction);
+            BOUNCES.lock().insert(addr, pages);
+            BOUNCED.fetch_add(1, Ordering::SeqCst);
+            addr
+        }
+    };
+    MAPPED.fetch_add(1, Ordering::SeqCst);
+    Ok(addr)
+}
+
+/// Unmaps `buf` mapped at `addr` by [`map`] once the device is done with it.
+///
+/// # Safety
+///
+/// `addr`, `buf` and `direction` must be those it was mapped with.
+pub unsafe fn unmap(addr: PhysAddr, buf: NonNull<[u8]>, direction: Direction) {
+    let vaddr = VirtAddr::new(buf.as_ptr() as *mut u8 as usize);
+    let len = buf.len();
+
+    let bounce = BOUNCES.lock().remove(&addr);
+    match bounce {
+        Some(pages) => {
+            let bounce = addr.to_virt();
+            sync_for_cpu(bounce, len, direction);
+            if direction != Direction::ToDevice {
+                ptr::copy_nonoverlapping(
+                    bounce.as_usize() as *const u8,
+                    vaddr.as_usize() as *mut u8,
+                    len,
+                );
+            }
+            dealloc_pages(addr, pages);
+            BOUNCED.fetch_sub(1, Ordering::SeqCst);
+        }
+        None => sync_for_cpu(vaddr, len, direction),
+    }
+    MAPPED.fetch_sub(1, Ordering::SeqCst);
+}
+
+/// Returns the physical address of `[vaddr, vaddr + len)` if a device can access it in place.
+fn direct(vaddr: VirtAddr, len: usize) -> Option<PhysAddr> {
+    let start = PhysAddr::new(PHYS_MEM_BASE).to_virt();
+    let end = PhysAddr::new(PHYS_MEM_LIMIT).to_virt();
+    (start <= vaddr && vaddr.as_usize() + len <= end.as_usize())
+        .then(|| vaddr.to_phys())
+        .filter(|&addr| reachable(addr, len))
+}
+
+fn reachable(addr: PhysAddr, len: usize) -> bool {
+    addr.as_usize() + len <= ADDR_LIMIT.load(Ordering::SeqCst)
+}
+
+fn alloc_pages(pages: usize) -> Result<PhysAddr> {
+    let addr = match POOL.lock().alloc(pages) {
+        Some(addr) => addr,
+        None => frame_alloc::alloc(pages, PAGE_SIZE)?,
+    };
+    if !reachable(addr, pages * PAGE_SIZE) {
+        dealloc_pages(addr, pages);
+        return Err(InternalError::NotEnoughMem);
+    }
+    unsafe { ptr::write_bytes(addr.to_virt().as_usize() as *mut u8, 0, pages * PAGE_SIZE) };
+    Ok(addr)
+}
+
+fn dealloc_pages(addr: PhysAddr, pages: usize) {
+    if !POOL.lock().dealloc(addr, pages) {
+        unsafe { frame_alloc::dealloc(addr, pages) };
+    }
+}
+
+fn sync_for_device(vaddr: VirtAddr, len: usize, direction: Direction) {
+    match direction {
+        Direction::ToDevice => hal!().cache().clean(vaddr, len),
+        Direction::FromDevice => hal!().cache().invalidate(vaddr, len),
+        Direction::Bidirectional => hal!().cache().flush(vaddr, len),
+    }
+}
+
+fn sync_for_cpu(vaddr: VirtAddr, len: usize, direction: Direction) {
+    match direction {
+        Direction::ToDevice => {}
+        Direction::FromDevice | Direction::Bidirectional => hal!().cache().invalidate(vaddr, len),
+    }
+}
@@ -1,16 +1,20 @@
 
+use super::user_irq::IrqNotification;
 use crate::Driver;
 use alloc::{collections::BTreeMap, sync::Arc};
 use core::{ops::Range, time::Duration};
+use jrinx_error::{InternalError, Result};
 pub struct IrqManager {
     irq_range: Range<usize>,
     table: BTreeMap<usize, Option<Arc<dyn Driver>>>,
+    forwards: BTreeMap<usize, Arc<IrqNotification>>,
 }
 impl IrqManager {
     pub fn new(irq_range: Range<usize>) -> Self {
         Self {
             irq_range,
             table: BTreeMap::<usize, Option<Arc<dyn Driver>>>::new(),
+            forwards: BTreeMap::new(),
         }
     }
     pub fn register_device(&mut self, irq_num: usize, dev: Arc<dyn Driver>) {
@@ -19,6 +23,30 @@ impl IrqManager {
         }
     }
 
+    /// Forwards `irq_num` to a driver in user space, unless a kernel driver or another user
+    /// driver already handles it.
+    pub fn forward(&mut self, irq_num: usize, notification: Arc<IrqNotification>) -> Result<()> {
+        if !self.irq_range.contains(&irq_num) || irq_num == 0 {
+            return Err(InternalError::InvalidIrqNumber);
+        }
+        if self.table.contains_key(&irq_num) || self.forwards.contains_key(&irq_num) {
+            return Err(InternalError::DuplicateIrqClaim);
+        }
+        self.forwards.insert(irq_num, notification);
+        Ok(())
+    }
+
+    pub fn unforward(&mut self, irq_num: usize) -> Result<()> {
+        self.forwards
+            .remove(&irq_num)
+            .map(|_| ())
+            .ok_or(InternalError::InvalidIrqNumber)
+    }
+
+    pub fn is_forwarded(&self, irq_num: usize) -> bool {
+        self.forwards.contains_key(&irq_num)
+    }
+
     // pub fn unregister_handler(&mut self, irq_num: usize) {
     //     if self.irq_range.contains(&irq_num) && irq_num != 0 {
     //         self.table.remove(&irq_num);
@@ -30,6 +58,9 @@ impl IrqManager {
             if let Some(dev) = self.table.get(&irq_num) {
                 start_time = dev.as_ref().unwrap().handle_irq(irq_num);
             }
+            else if let Some(notification) = self.forwards.get(&irq_num) {
+                notification.notify();
+            }
             else
             {
                 info!("handle error");
@@ -1,4 +1,5 @@
+pub mod irq_dispatch;
 mod irq_manager;
 pub mod riscv_intc;
 pub mod riscv_plic;
-pub mod irq_dispatch;
\ No newline at end of file
+pub mod user_irq;
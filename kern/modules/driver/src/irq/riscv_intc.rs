@@ -14,6 +14,7 @@ use spin::{Mutex, Once, RwLock};
 
 // use super::irq_dispatch::rotate_strategy;
 use super::riscv_plic::PLIC_PHANDLE;
+use super::user_irq::IrqNotification;
 pub static GLOBAL_INTC: Once<Arc<dyn InterruptController>> = Once::new();
 pub static IRQ_TABLE: RwLock<BTreeMap<usize, Arc<Mutex<dyn InterruptController>>>> =
     RwLock::new(BTreeMap::new());
@@ -61,6 +62,16 @@ impl InterruptController for Intc {
     fn register_device(&self, _irq_num: usize, _dev: Arc<dyn Driver>) -> Result<()> {
         todo!()
     }
+    // the local interrupts of a hart are the kernel's own, none is ever left for user space
+    fn forward(&self, _irq_num: usize, _notification: Arc<IrqNotification>) -> Result<()> {
+        Err(InternalError::InvalidIrqNumber)
+    }
+    fn unforward(&self, _irq_num: usize) -> Result<()> {
+        Err(InternalError::InvalidIrqNumber)
+    }
+    fn unmask(&self, _irq_num: usize) -> Result<()> {
+        Err(InternalError::InvalidIrqNumber)
+    }
 
     fn enable(&mut self, _cpu_id: usize, irq_num: usize) -> Result<()> {
         unsafe {
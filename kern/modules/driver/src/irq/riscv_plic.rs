@@ -1,5 +1,6 @@
 use super::irq_dispatch::IRQ_COUNT;
 use super::irq_manager::IrqManager;
+use super::user_irq::IrqNotification;
 use crate::io::{Io, Mmio};
 use crate::irq::riscv_intc::IRQ_TABLE;
 use crate::{Driver, InterruptController};
@@ -15,7 +16,7 @@ use jrinx_paging::boot::BootPageTable;
 use log::info;
 use spin::{Mutex, Once};
 extern crate jrinx_config;
-use jrinx_config::{EXTERNAL_DEVICE_REGION, PAGE_SIZE};
+use jrinx_config::PAGE_SIZE;
 
 pub static PLIC_PHANDLE: Once<usize> = Once::new();
 
@@ -37,7 +38,7 @@ fn probe(node: &FdtNode) -> Result<()> {
         .ok_or(InternalError::DevProbeError)?
         .next()
         .ok_or(InternalError::DevProbeError)?;
-    let addr = region.starting_address as usize + EXTERNAL_DEVICE_REGION.addr;
+    let addr = region.starting_address as usize + jrinx_kaslr::external_device_region().addr;
     let size = region.size.ok_or(InternalError::DevProbeError)?;
     let phandle = node
         .property("phandle")
@@ -243,6 +244,10 @@ impl Driver for Plic {
                 debug!("cpu {} claim irq {}", hal!().cpu().id(), irq_num);
                 *IRQ_COUNT.lock().get_mut(&irq_num).unwrap() += 1;
                 let start_time = inner.irq_manager.handle_irq(irq_num);
+                if inner.irq_manager.is_forwarded(irq_num) {
+                    // masked until the driver in user space acknowledges it
+                    inner.set_priority(irq_num, 0);
+                }
                 inner.end_of_interrupt(irq_num);
                 start_time
             }
@@ -279,4 +284,27 @@ impl InterruptController for Plic {
         inner.set_priority(irq_num, 7);
         Ok(())
     }
+
+    fn forward(&self, irq_num: usize, notification: Arc<IrqNotification>) -> Result<()> {
+        let mut inner = self.inner.lock();
+        inner.irq_manager.forward(irq_num, notification)?;
+        inner.set_priority(irq_num, 7);
+        Ok(())
+    }
+
+    fn unforward(&self, irq_num: usize) -> Result<()> {
+        let mut inner = self.inner.lock();
+        inner.irq_manager.unforward(irq_num)?;
+        inner.set_priority(irq_num, 0);
+        Ok(())
+    }
+
+    fn unmask(&self, irq_num: usize) -> Result<()> {
+        let mut inner = self.inner.lock();
+        if !inner.irq_manager.is_forwarded(irq_num) {
+            return Err(InternalError::InvalidIrqNumber);
+        }
+        inner.set_priority(irq_num, 7);
+        Ok(())
+    }
 }
@@ -0,0 +1,122 @@
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    future::poll_fn,
+    mem,
+    task::{Poll, Waker},
+};
+
+use jrinx_error::{InternalError, Result};
+use jrinx_hal::{hal, Cpu, Hal, Interrupt};
+use spin::Mutex;
+
+use super::riscv_intc::IRQ_TABLE;
+use crate::device;
+
+/// An interrupt line claimed by a driver in user space.
+///
+/// Every interrupt on the line is turned into a notification and the line is masked until the
+/// driver acknowledges it, so that a level-triggered device does not keep interrupting before the
+/// driver gets to quiet it. The line is released when the last reference goes away.
+pub struct IrqLine {
+    irq_num: usize,
+    parent: usize,
+    cpu_id: usize,
+    notification: Arc<IrqNotification>,
+}
+
+/// The part of an [`IrqLine`] the interrupt controller holds on to, so that the line is never
+/// released in interrupt context.
+#[derive(Default)]
+pub struct IrqNotification {
+    state: Mutex<IrqNotificationState>,
+}
+
+#[derive(Default)]
+struct IrqNotificationState {
+    pending: bool,
+    waiters: Vec<Waker>,
+}
+
+impl IrqLine {
+    /// Claims `irq_num`, which must be raised by a device left for user space, and routes it to
+    /// the current CPU.
+    pub fn claim(irq_num: usize) -> Result<Arc<Self>> {
+        let parent = device::find_irq(irq_num)?
+            .irq_parent()
+            .ok_or(InternalError::InvalidIrqNumber)?;
+        let line = Arc::new(Self {
+            irq_num,
+            parent,
+            cpu_id: hal!().cpu().id(),
+            notification: Arc::new(IrqNotification::default()),
+        });
+
+        // the interrupt controller is locked in interrupt context as well
+        hal!().interrupt().with_saved_off(|| {
+            let irq_table = IRQ_TABLE.read();
+            let mut intc = irq_table
+                .get(&line.parent)
+                .ok_or(InternalError::InvalidIrqNumber)?
+                .lock();
+            intc.forward(irq_num, line.notification.clone())?;
+            intc.enable(line.cpu_id, irq_num)
+        })?;
+        Ok(line)
+    }
+
+    pub fn irq_num(&self) -> usize {
+        self.irq_num
+    }
+
+    /// Waits until the line is raised, consuming the notification.
+    pub async fn wait(&self) {
+        poll_fn(|cx| {
+            hal!().interrupt().with_saved_off(|| {
+                let mut state = self.notification.state.lock();
+                if mem::take(&mut state.pending) {
+                    Poll::Ready(())
+                } else {
+                    state.waiters.push(cx.waker().clone());
+                    Poll::Pending
+                }
+            })
+        })
+        .await
+    }
+
+    /// Unmasks the line, to be called once the device no longer asserts it.
+    pub fn ack(&self) -> Result<()> {
+        hal!().interrupt().with_saved_off(|| {
+            IRQ_TABLE
+                .read()
+                .get(&self.parent)
+                .ok_or(InternalError::InvalidIrqNumber)?
+                .lock()
+                .unmask(self.irq_num)
+        })
+    }
+}
+
+impl IrqNotification {
+    /// Notifies the claiming driver, called in interrupt context.
+    pub(crate) fn notify(&self) {
+        let waiters = {
+            let mut state = self.state.lock();
+            state.pending = true;
+            mem::take(&mut state.waiters)
+        };
+        waiters.into_iter().for_each(Waker::wake);
+    }
+}
+
+impl Drop for IrqLine {
+    fn drop(&mut self) {
+        hal!().interrupt().with_saved_off(|| {
+            if let Some(intc) = IRQ_TABLE.read().get(&self.parent) {
+                let mut intc = intc.lock();
+                let _ = intc.disable(self.cpu_id, self.irq_num);
+                let _ = intc.unforward(self.irq_num);
+            }
+        });
+    }
+}
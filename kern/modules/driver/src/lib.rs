@@ -7,10 +7,13 @@ extern crate alloc;
 #[macro_use]
 extern crate log;
 
+pub mod blk;
 pub mod bus;
+pub mod device;
+pub mod dma;
 pub mod io;
 pub mod irq;
-mod mem;
+pub mod mem;
 pub mod net;
 pub mod uart;
 pub mod smoltcp_impl;
@@ -19,13 +22,16 @@ use core::time::Duration;
 
 use alloc::{boxed::Box, sync::Arc};
 use fdt::Fdt;
+use irq::user_irq::IrqNotification;
 use jrinx_error::Result;
 use net::net_buf::NetBufPtr;
 use smoltcp::wire::EthernetAddress;
 
 pub fn probe_all(fdt: &Fdt<'_>) {
     info!("probing all devices");
+    mem::reserve_from_fdt(fdt);
     jrinx_devprober::probe_all_device(fdt).unwrap();
+    device::init(fdt);
 }
 
 pub type InterruptHandler = Box<dyn Fn() + Send + Sync>;
@@ -40,9 +46,25 @@ pub trait InterruptController: Driver {
     fn enable(&mut self, cpu_id: usize, irq_num: usize) -> Result<()>;
     fn disable(&mut self, cpu_id: usize, irq_num: usize) -> Result<()>;
     fn register_device(&self, irq_num: usize, dev: Arc<dyn Driver>) -> Result<()>;
+    /// Forwards `irq_num` to a driver in user space, masking it after every interrupt.
+    fn forward(&self, irq_num: usize, notification: Arc<IrqNotification>) -> Result<()>;
+    fn unforward(&self, irq_num: usize) -> Result<()>;
+    /// Unmasks a forwarded `irq_num` once the driver in user space has handled it.
+    fn unmask(&self, irq_num: usize) -> Result<()>;
     fn info(&self);
 }
 
+pub trait BlockDevice: Send + Sync {
+    fn block_size(&self) -> usize;
+    fn num_blocks(&self) -> usize;
+    /// Reads the blocks starting at `block` into `buf`, whose length is a multiple of the block
+    /// size.
+    fn read_blocks(&self, block: usize, buf: &mut [u8]) -> Result<()>;
+    /// Writes `buf`, whose length is a multiple of the block size, to the blocks starting at
+    /// `block`.
+    fn write_blocks(&self, block: usize, buf: &[u8]) -> Result<()>;
+}
+
 pub trait Uart: Driver {
     fn init(&self) -> Result<()>;
     fn read(&self) -> Result<u8>;
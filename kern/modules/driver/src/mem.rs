@@ -1,33 +1,110 @@
 use alloc::vec::Vec;
-use fdt::node::FdtNode;
+use fdt::{node::FdtNode, Fdt};
 use jrinx_addr::{PhysAddr, VirtAddr};
 use jrinx_config::PHYS_MEM_BASE;
 use jrinx_devprober::devprober;
 use jrinx_error::{InternalError, Result};
+use jrinx_phys_frame::frame_alloc;
 use jrinx_util::interval::{Bound, ExclusiveIntervals};
+use spin::Mutex;
+
+/// Regions of RAM which are not handed to the frame allocator, by physical address.
+static RESERVED: Mutex<Vec<Bound>> = Mutex::new(Vec::new());
+
+/// Keeps the RAM at `[addr, addr + len)` away from the frame allocator, must be called before the
+/// memory is probed.
+pub fn reserve(name: &str, addr: PhysAddr, len: usize) {
+    report(name, addr.as_usize(), len);
+    RESERVED.lock().push(Bound::new(addr.as_usize(), len));
+}
+
+fn report(name: &str, addr: usize, len: usize) {
+    info!(
+        "reserve memory [{:#x}, {:#x}) for {}",
+        addr,
+        addr + len,
+        name
+    );
+}
+
+/// Returns the regions reserved so far, apart from the firmware and the kernel image.
+pub fn reserved() -> Vec<(PhysAddr, usize)> {
+    RESERVED
+        .lock()
+        .iter()
+        .map(|&bound| bound.into())
+        .map(|(addr, len)| (PhysAddr::new(addr), len))
+        .collect()
+}
+
+/// Reserves the regions of the `/memreserve/` entries of the header and under `/reserved-memory`,
+/// and the initrd given in `/chosen`.
+pub(crate) fn reserve_from_fdt(fdt: &Fdt) {
+    for region in fdt.memory_reservations() {
+        reserve(
+            "/memreserve/",
+            PhysAddr::new(region.address() as usize),
+            region.size(),
+        );
+    }
+
+    if let Some(node) = fdt.find_node("/reserved-memory") {
+        for child in node.children() {
+            // regions without `reg` are only allocated by the OS they are meant for
+            for region in child.reg().into_iter().flatten() {
+                if let Some(size) = region.size {
+                    reserve(
+                        child.name,
+                        PhysAddr::new(region.starting_address as usize),
+                        size,
+                    );
+                }
+            }
+        }
+    }
+
+    if let Some(node) = fdt.find_node("/chosen") {
+        let prop = |name: &str| node.property(name).and_then(|prop| prop.as_usize());
+        if let (Some(start), Some(end)) = (prop("linux,initrd-start"), prop("linux,initrd-end")) {
+            reserve("initrd", PhysAddr::new(start), end.saturating_sub(start));
+        }
+    }
+}
 
 #[devprober(device_type = "memory")]
 fn probe(node: &FdtNode) -> Result<()> {
+    // the firmware lies below the kernel image
+    let image = Bound::new(
+        PHYS_MEM_BASE,
+        VirtAddr::new(jrinx_layout::_end()).align_page_up()
+            - PhysAddr::new(PHYS_MEM_BASE).to_virt(),
+    );
+    let (addr, len) = image.into();
+    report("firmware and kernel image", addr, len);
+    let reserved = RESERVED.lock();
+
     node.reg()
         .ok_or(InternalError::DevProbeError)?
         .filter_map(|mem_region| {
             mem_region.size.map(|size| {
-                let addr = PhysAddr::new(mem_region.starting_address as usize).to_virt();
-                let bound = Bound::new(addr.as_usize(), size);
+                let bound = Bound::new(mem_region.starting_address as usize, size);
                 let mut intervals = ExclusiveIntervals::new([bound]);
-                intervals -= Bound::new(
-                    PhysAddr::new(PHYS_MEM_BASE).to_virt().as_usize(),
-                    VirtAddr::new(jrinx_layout::_end()).align_page_up()
-                        - PhysAddr::new(PHYS_MEM_BASE).to_virt(),
-                );
+                for &reserved in reserved.iter().chain([&image]) {
+                    intervals -= reserved;
+                }
                 intervals
                     .into_iter()
                     .map(|bound| bound.into())
-                    .map(|(addr, len)| (VirtAddr::new(addr), len))
+                    .map(|(addr, len)| (PhysAddr::new(addr), len))
                     .collect::<Vec<_>>()
             })
         })
         .flatten()
-        .for_each(jrinx_heap::enlarge);
+        .for_each(|(addr, len)| frame_alloc::add_region(addr, len));
+    let stats = frame_alloc::stats();
+    info!(
+        "{} page frames of RAM available, {} free",
+        stats.total, stats.free
+    );
     Ok(())
 }
@@ -3,7 +3,7 @@ use core::ptr::NonNull;
 use alloc::sync::Arc;
 use fdt::node::FdtNode;
 use jrinx_addr::{PhysAddr, VirtAddr};
-use jrinx_config::{EXTERNAL_DEVICE_REGION, PAGE_SIZE};
+use jrinx_config::PAGE_SIZE;
 use jrinx_devprober::devprober;
 use jrinx_error::{InternalError, Result};
 use jrinx_hal::{hal, Hal as _, Vm};
@@ -14,6 +14,7 @@ use virtio_drivers::transport::{
 };
 
 use crate::{
+    blk::{self, virtio_blk::VirtIoBlk},
     irq::riscv_intc::IRQ_TABLE,
     net::virtio_net::{VirtIoNetInner, VirtIoNetMutex},
     smoltcp_impl::init,
@@ -27,7 +28,7 @@ fn probe(node: &FdtNode) -> Result<()> {
         .next()
         .ok_or(InternalError::DevProbeError)?;
     let paddr = region.starting_address as usize;
-    let vaddr = paddr + EXTERNAL_DEVICE_REGION.addr;
+    let vaddr = paddr + jrinx_kaslr::external_device_region().addr;
     let size = region.size.ok_or(InternalError::DevProbeError)?;
     let count = size / PAGE_SIZE;
     let interrupt_parent = node
@@ -68,7 +69,10 @@ fn probe(node: &FdtNode) -> Result<()> {
 fn virtio_device(transport: MmioTransport, interrupt_parent: usize, irq_num: usize) {
     //info!("virtio type is {:?}", transport.device_type());
     match transport.device_type() {
-        DeviceType::Block => {}
+        DeviceType::Block => match VirtIoBlk::new(transport) {
+            Ok(dev) => blk::register(Arc::new(dev)),
+            Err(e) => warn!("Error creating VirtIO block device: {:?}", e),
+        },
         DeviceType::GPU => {}
         DeviceType::Input => {}
         DeviceType::Network => {
@@ -253,6 +253,18 @@ impl<'a> SocketSetWrapper<'a> {
         info!("socket {}: destroyed", handle);
     }
 }
+/// Returns whether a network interface is present, which sockets need.
+pub fn has_interface() -> bool {
+    SOCKET_SET.get().is_some()
+}
+
+/// Polls every network interface for incoming and outgoing packets, if any is present.
+pub fn poll_interfaces() {
+    if let Some(socket_set) = SOCKET_SET.get() {
+        socket_set.poll_interfaces();
+    }
+}
+
 pub(crate) fn init(net_dev: Arc<VirtIoNetMutex>) {
     let ether_addr = EthernetAddress(net_dev.inner.lock().mac_address().0);
     let eth0 = InterfaceWrapper::new("eth0", net_dev, ether_addr);
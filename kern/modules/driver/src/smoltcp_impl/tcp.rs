@@ -3,6 +3,7 @@ use super::{SocketSetWrapper, ETH0, LISTEN_TABLE, SOCKET_SET};
 use core::cell::UnsafeCell;
 use core::net::SocketAddr;
 use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use core::task::Waker;
 use jrinx_error::{InternalError, Result};
 use log::info;
 use smoltcp::iface::SocketHandle;
@@ -375,6 +376,35 @@ impl TcpSocket {
         })
     }
 
+    /// Registers `waker` to be woken once receiving, or sending if `send`, may make progress, or
+    /// wakes it at once if it already may.
+    pub fn register_waker(&self, waker: &Waker, send: bool) {
+        // SAFETY: `self.handle` is only written while the socket is busy.
+        let Some(handle) = (unsafe { self.handle.get().read() }) else {
+            waker.wake_by_ref();
+            return;
+        };
+        SOCKET_SET
+            .get()
+            .unwrap()
+            .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                // every change of state wakes both wakers, e.g. once connected
+                let connecting = matches!(socket.state(), State::SynSent | State::SynReceived);
+                let ready = if send {
+                    !socket.may_send() || socket.can_send()
+                } else {
+                    !socket.may_recv() || socket.can_recv()
+                };
+                if ready && !connecting {
+                    waker.wake_by_ref();
+                } else if send {
+                    socket.register_send_waker(waker);
+                } else {
+                    socket.register_recv_waker(waker);
+                }
+            });
+    }
+
     /// Whether the socket is readable or writable.
     pub fn poll(&self) -> Result<PollState> {
         match self.get_state() {
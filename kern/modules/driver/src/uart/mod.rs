@@ -1 +1,43 @@
 pub mod ns16550a;
+
+use alloc::{sync::Arc, vec::Vec};
+use core::{mem, task::Waker};
+
+use jrinx_hal::{hal, Hal, Interrupt};
+use spin::{Mutex, Once};
+
+use self::ns16550a::NS16550a;
+
+/// The UART the console is on, which is the first one probed.
+static CONSOLE: Once<Arc<NS16550a>> = Once::new();
+
+/// Tasks waiting for the console UART to receive any byte.
+static RX_WAITERS: Mutex<Vec<Waker>> = Mutex::new(Vec::new());
+
+/// Returns whether a UART raising interrupts on input drives the console.
+pub fn has_console() -> bool {
+    CONSOLE.get().is_some()
+}
+
+/// Takes a byte received by the console UART, or arranges for `waker`, if any, to be woken once
+/// one is received.
+pub fn getc(waker: Option<&Waker>) -> Option<u8> {
+    let console = CONSOLE.get()?;
+    // the receive interrupt must not find the buffer locked on this CPU
+    hal!().interrupt().with_saved_off(|| {
+        console.pop_with(|| {
+            if let Some(waker) = waker {
+                RX_WAITERS.lock().push(waker.clone());
+            }
+        })
+    })
+}
+
+fn set_console(uart: Arc<NS16550a>) {
+    CONSOLE.call_once(|| uart);
+}
+
+fn wake_rx() {
+    let waiters = mem::take(&mut *RX_WAITERS.lock());
+    waiters.into_iter().for_each(Waker::wake);
+}
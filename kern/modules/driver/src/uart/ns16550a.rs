@@ -6,7 +6,6 @@ use alloc::sync::Arc;
 use bitflags::bitflags;
 use fdt::node::FdtNode;
 use jrinx_addr::{PhysAddr, VirtAddr};
-use jrinx_config::EXTERNAL_DEVICE_REGION;
 use jrinx_devprober::devprober;
 use jrinx_error::{InternalError, Result};
 use jrinx_hal::{hal, Cpu, Hal, Vm};
@@ -20,7 +19,7 @@ fn probe(node: &FdtNode) -> Result<()> {
         .ok_or(InternalError::DevProbeError)?
         .next()
         .ok_or(InternalError::DevProbeError)?;
-    let vaddr = region.starting_address as usize + EXTERNAL_DEVICE_REGION.addr;
+    let vaddr = region.starting_address as usize + jrinx_kaslr::external_device_region().addr;
     let size = region.size.ok_or(InternalError::DevProbeError)?;
     let irq_num = node
         .interrupts()
@@ -42,13 +41,15 @@ fn probe(node: &FdtNode) -> Result<()> {
         );
     }
     hal!().vm().sync_all();
+    let uart = Arc::new(NS16550a::new(vaddr));
     IRQ_TABLE
         .write()
         .get(&interrupt_parent)
         .unwrap()
         .lock()
-        .register_device(irq_num, Arc::new(NS16550a::new(vaddr)))
+        .register_device(irq_num, uart.clone())
         .unwrap();
+    super::set_console(uart);
     info!("ns16550a vaddr {:x}, size {:x}", vaddr, size);
     Ok(())
 }
@@ -139,6 +140,16 @@ impl NS16550a {
     pub fn read(&self) -> Option<u8> {
         self.inner.lock().read()
     }
+    /// Takes a received byte, or calls `empty` with the buffer still locked if there is none, so
+    /// that nothing is received in between.
+    pub(super) fn pop_with(&self, empty: impl FnOnce()) -> Option<u8> {
+        let mut buffer = self.buffer.lock();
+        let ch = buffer.pop_front();
+        if ch.is_none() {
+            empty();
+        }
+        ch
+    }
 }
 use core::time::Duration;
 impl Driver for NS16550a {
@@ -149,6 +160,7 @@ impl Driver for NS16550a {
         let start_time = hal!().cpu().get_time();
         if let Some(ch) = self.inner.lock().read() {
             self.buffer.lock().push_back(ch);
+            super::wake_rx();
             // black_box(pi(black_box(100)));
             // let finish_time = hal!().cpu().get_time();
             //info!("add time {:?}", finish_time - start_time);
@@ -27,6 +27,17 @@ pub enum InternalError {
     InvalidParam,
     DuplicateRuntimeSchedTable,
     InvalidTimedEventStatus,
+    InvalidProcessId,
+    InvalidEndpointId,
+    InvalidSharedMemoryName,
+    DuplicateSharedMemoryName,
+    InvalidFileDescriptor,
+    BrokenPipe,
+    InvalidHandle,
+    InsufficientRights,
+    InvalidDeviceRegion,
+    InvalidIrqNumber,
+    DuplicateIrqClaim,
 }
 
 pub type Result<T> = core::result::Result<T, InternalError>;
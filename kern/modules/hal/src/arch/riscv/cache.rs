@@ -1,3 +1,5 @@
+use jrinx_addr::VirtAddr;
+
 use crate::Cache;
 
 #[derive(Debug, Clone, Copy)]
@@ -9,4 +11,25 @@ impl Cache for CacheImpl {
             core::arch::asm!("fence.i");
         }
     }
+
+    // DMA is coherent with the caches on the supported platforms, so the accesses to memory only
+    // have to be ordered against those to the device
+
+    fn clean(&self, _addr: VirtAddr, _len: usize) {
+        unsafe {
+            core::arch::asm!("fence w, o");
+        }
+    }
+
+    fn invalidate(&self, _addr: VirtAddr, _len: usize) {
+        unsafe {
+            core::arch::asm!("fence i, r");
+        }
+    }
+
+    fn flush(&self, _addr: VirtAddr, _len: usize) {
+        unsafe {
+            core::arch::asm!("fence iorw, iorw");
+        }
+    }
 }
@@ -2,19 +2,32 @@ use riscv::register::satp::{self, Mode};
 
 use crate::Vm;
 
+/// The `satp` mode of the page tables, as selected by the `sv48` and `sv57` features on riscv64,
+/// see `jrinx_config::PT_LEVELS`.
+pub const MODE: Mode = match jrinx_config::PT_LEVELS {
+    2 => Mode::Sv32,
+    3 => Mode::Sv39,
+    4 => Mode::Sv48,
+    _ => Mode::Sv57,
+};
+
+#[cfg(target_arch = "riscv32")]
+const ASID_SHIFT: usize = 22;
+#[cfg(target_arch = "riscv32")]
+const ASID_MASK: usize = 0x1ff;
+
+#[cfg(target_arch = "riscv64")]
+const ASID_SHIFT: usize = 44;
+#[cfg(target_arch = "riscv64")]
+const ASID_MASK: usize = 0xffff;
+
 #[derive(Debug, Clone, Copy)]
 pub struct VmImpl;
 
 impl Vm for VmImpl {
-    fn enable(&self, page_table: jrinx_addr::PhysAddr) {
-        #[cfg(target_arch = "riscv32")]
-        unsafe {
-            satp::set(Mode::Sv32, 0, page_table.as_usize() >> 12);
-        }
-
-        #[cfg(target_arch = "riscv64")]
+    fn enable(&self, page_table: jrinx_addr::PhysAddr, asid: usize) {
         unsafe {
-            satp::set(Mode::Sv39, 0, page_table.as_usize() >> 12);
+            satp::set(MODE, asid, page_table.as_usize() >> 12);
         }
     }
 
@@ -29,4 +42,29 @@ impl Vm for VmImpl {
             riscv::asm::sfence_vma_all();
         }
     }
+
+    fn sync(&self, addr: jrinx_addr::VirtAddr) {
+        unsafe {
+            core::arch::asm!("sfence.vma {}, zero", in(reg) addr.as_usize());
+        }
+    }
+
+    fn sync_asid(&self, asid: usize) {
+        unsafe {
+            core::arch::asm!("sfence.vma zero, {}", in(reg) asid);
+        }
+    }
+
+    /// Writes every ASID bit of `satp` and counts the ones that stick, as the specification
+    /// suggests.
+    fn asid_bits(&self) -> usize {
+        let satp = satp::read().bits();
+        let probed = unsafe {
+            core::arch::asm!("csrw satp, {}", in(reg) satp | ASID_MASK << ASID_SHIFT);
+            let probed = satp::read().bits();
+            core::arch::asm!("csrw satp, {}", in(reg) satp);
+            probed
+        };
+        (probed >> ASID_SHIFT & ASID_MASK).count_ones() as usize
+    }
 }
@@ -8,7 +8,7 @@ use core::time::Duration;
 use alloc::vec::Vec;
 pub use arch::*;
 
-use jrinx_addr::PhysAddr;
+use jrinx_addr::{PhysAddr, VirtAddr};
 use spin::Once;
 
 #[macro_export]
@@ -80,6 +80,15 @@ pub trait Earlycon: Send + Sync {
 
 pub trait Cache: Send + Sync {
     fn sync_all(&self);
+
+    /// Writes back `[addr, addr + len)` so that a device reads what the CPU has written.
+    fn clean(&self, addr: VirtAddr, len: usize);
+
+    /// Discards `[addr, addr + len)` so that the CPU reads what a device has written.
+    fn invalidate(&self, addr: VirtAddr, len: usize);
+
+    /// Writes back and discards `[addr, addr + len)`.
+    fn flush(&self, addr: VirtAddr, len: usize);
 }
 
 pub trait Interrupt: Send + Sync {
@@ -135,11 +144,21 @@ pub trait Interrupt: Send + Sync {
 }
 
 pub trait Vm: Send + Sync {
-    fn enable(&self, page_table: PhysAddr);
+    /// Switches to `page_table`, whose translations are tagged with `asid` in the TLB.
+    fn enable(&self, page_table: PhysAddr, asid: usize);
 
     fn disable(&self);
 
     fn sync_all(&self);
+
+    /// Flushes the translations of the page at `addr` in every address space.
+    fn sync(&self, addr: VirtAddr);
+
+    /// Flushes the translations tagged with `asid`, except for global ones.
+    fn sync_asid(&self, asid: usize);
+
+    /// Returns the number of ASID bits the CPU implements, which may be none.
+    fn asid_bits(&self) -> usize;
 }
 
 pub trait Io: Send + Sync {
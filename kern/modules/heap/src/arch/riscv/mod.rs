@@ -0,0 +1,24 @@
+/// Returns the stack pointer and the frame pointer of the caller.
+#[inline(always)]
+pub fn stack_frame() -> (usize, usize) {
+    let (sp, fp);
+    unsafe {
+        core::arch::asm!(
+            "mv {}, sp",
+            "mv {}, s0",
+            out(reg) sp,
+            out(reg) fp,
+        );
+    }
+    (sp, fp)
+}
+
+/// Returns the return address and the frame pointer saved in the frame at `fp`.
+///
+/// # Safety
+///
+/// `fp` must point right above a frame laid out with frame pointers.
+pub unsafe fn unwind(fp: usize) -> (usize, usize) {
+    let fp = fp as *const usize;
+    (*fp.sub(1), *fp.sub(2))
+}
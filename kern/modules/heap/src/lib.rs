@@ -1,28 +1,213 @@
 #![no_std]
 
-use buddy_system_allocator::LockedHeap;
-use jrinx_addr::VirtAddr;
+extern crate alloc;
 
-use jrinx_config::{HEAP_ORDER, KHEAP_SIZE};
+#[cfg(feature = "trace")]
+mod arch;
+pub mod slab;
+#[cfg(feature = "trace")]
+pub mod trace;
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use buddy_system_allocator::{Heap, LockedHeapWithRescue};
+use jrinx_config::{HEAP_GROW_SIZE, HEAP_ORDER, KHEAP_SIZE, PAGE_SIZE};
+use jrinx_hal::{hal, Cpu, Hal};
+use jrinx_phys_frame::frame_alloc;
+use spin::RwLock;
 
 #[global_allocator]
-static mut HEAP_ALLOCATOR: LockedHeap<HEAP_ORDER> = LockedHeap::new();
+static HEAP_ALLOCATOR: HeapAllocator = HeapAllocator;
+
+static BUDDY_HEAP: LockedHeapWithRescue<HEAP_ORDER> = LockedHeapWithRescue::new(grow);
+
+static USED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+static OOM_HOOK: RwLock<Option<OomHook>> = RwLock::new(None);
+
+/// CPUs running the OOM hook, one bit each.
+static OOM_HANDLING: AtomicUsize = AtomicUsize::new(0);
+
+/// The CPU whose allocations are limited by [`QUOTA`], none if it is `usize::MAX`.
+static QUOTA_CPU: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Bytes the CPU in [`QUOTA_CPU`] may still allocate.
+static QUOTA: AtomicUsize = AtomicUsize::new(0);
+
+/// Called once the heap has run out of memory even after growing, with no lock of the heap held.
+/// Returns whether it has freed any memory, so that the allocation is worth retrying.
+pub type OomHook = fn(&Layout) -> bool;
+
+/// Statistics of the kernel heap, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Memory handed to the buddy heap, including what it has grown by.
+    pub total: usize,
+    /// Memory allocated and not yet freed, as requested by the layouts.
+    pub used: usize,
+    /// The most `used` has ever been.
+    pub peak: usize,
+    /// The largest block the buddy heap can serve without growing.
+    pub largest_free: usize,
+}
+
+/// Serves small allocations from the per-CPU [`slab`] caches and the rest from the buddy heap.
+struct HeapAllocator;
+
+unsafe impl GlobalAlloc for HeapAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        loop {
+            let ptr = if charge(layout.size()) {
+                let ptr = match slab::class(&layout) {
+                    Some(class) => slab::alloc(class),
+                    None => BUDDY_HEAP.alloc(layout),
+                };
+                if ptr.is_null() {
+                    refund(layout.size());
+                }
+                ptr
+            } else {
+                ptr::null_mut()
+            };
+            if !ptr.is_null() {
+                let used = USED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+                PEAK.fetch_max(used, Ordering::Relaxed);
+                #[cfg(feature = "trace")]
+                trace::record(ptr, &layout);
+                return ptr;
+            }
+            if !oom(&layout) {
+                return ptr::null_mut();
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        USED.fetch_sub(layout.size(), Ordering::Relaxed);
+        #[cfg(feature = "trace")]
+        trace::forget(ptr);
+        match slab::class(&layout) {
+            Some(class) => slab::dealloc(class, ptr),
+            None => BUDDY_HEAP.dealloc(ptr, layout),
+        }
+    }
+}
 
 pub fn init() {
     #[repr(C, align(4096))]
     struct HeapSpace([u8; KHEAP_SIZE]);
     static mut HEAP_SPACE: HeapSpace = HeapSpace([0; KHEAP_SIZE]);
     unsafe {
-        HEAP_ALLOCATOR
+        BUDDY_HEAP
             .lock()
             .init(HEAP_SPACE.0.as_ptr() as usize, KHEAP_SIZE);
     };
 }
 
-pub fn enlarge(region: (VirtAddr, usize)) {
-    unsafe {
-        HEAP_ALLOCATOR
-            .lock()
-            .add_to_heap(region.0.as_usize(), region.0.as_usize() + region.1);
+pub fn set_oom_hook(hook: OomHook) {
+    *OOM_HOOK.write() = Some(hook);
+}
+
+/// Returns whether the current CPU is running the OOM hook. Its allocations meanwhile succeed as
+/// long as memory is left, but fail without running the hook again once the heap runs out.
+pub fn oom_handling() -> bool {
+    OOM_HANDLING.load(Ordering::SeqCst) & 1 << hal!().cpu().id() != 0
+}
+
+/// Limits the current CPU to allocating `bytes` more, as if the heap ran out of memory beyond,
+/// or lifts the limit if `None`. A single CPU is limited at a time, and what it frees meanwhile
+/// does not count back. This lets tests run out of memory without starving the other CPUs.
+pub fn set_quota(bytes: Option<usize>) {
+    match bytes {
+        Some(bytes) => {
+            QUOTA.store(bytes, Ordering::SeqCst);
+            QUOTA_CPU.store(hal!().cpu().id(), Ordering::SeqCst);
+        }
+        None => QUOTA_CPU.store(usize::MAX, Ordering::SeqCst),
+    }
+}
+
+/// Takes `size` bytes off the quota if the current CPU is limited, failing if it has run out.
+fn charge(size: usize) -> bool {
+    match QUOTA_CPU.load(Ordering::Relaxed) {
+        usize::MAX => true,
+        cpu if cpu != hal!().cpu().id() => true,
+        _ => QUOTA
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                left.checked_sub(size)
+            })
+            .is_ok(),
+    }
+}
+
+fn refund(size: usize) {
+    if QUOTA_CPU.load(Ordering::Relaxed) == hal!().cpu().id() {
+        QUOTA.fetch_add(size, Ordering::SeqCst);
+    }
+}
+
+pub fn stats() -> HeapStats {
+    let mut heap = BUDDY_HEAP.lock();
+    HeapStats {
+        total: heap.stats_total_bytes(),
+        used: USED.load(Ordering::Relaxed),
+        peak: PEAK.load(Ordering::Relaxed),
+        largest_free: largest_free(&mut heap),
+    }
+}
+
+/// Finds the largest free block of `heap`, by allocating and freeing the largest one it can from
+/// the top order down. The first block found needs no split, so `heap` is left as it was.
+fn largest_free(heap: &mut Heap<HEAP_ORDER>) -> usize {
+    (0..HEAP_ORDER)
+        .rev()
+        .filter_map(|order| Layout::from_size_align(1 << order, 1 << order).ok())
+        .find(|&layout| {
+            heap.alloc(layout)
+                .map(|ptr| heap.dealloc(ptr, layout))
+                .is_ok()
+        })
+        .map_or(0, |layout| layout.size())
+}
+
+fn oom(layout: &Layout) -> bool {
+    let Some(hook) = *OOM_HOOK.read() else {
+        return false;
+    };
+
+    // allocations made by the hook itself that run out of memory fail rather than run it again
+    let this = 1 << hal!().cpu().id();
+    if OOM_HANDLING.fetch_or(this, Ordering::SeqCst) & this != 0 {
+        return false;
+    }
+    let freed = hook(layout);
+    OOM_HANDLING.fetch_and(!this, Ordering::SeqCst);
+    freed
+}
+
+/// Grows the heap by frames enough for `layout`, or by [`HEAP_GROW_SIZE`] if that is more and
+/// there are that many frames in a row. Once ordinary allocations have run out of frames, what
+/// `layout` needs is taken from the frames held back for the heap.
+///
+/// Frames are taken from the page chosen for the heap by KASLR on if possible.
+fn grow(heap: &mut Heap<HEAP_ORDER>, layout: &Layout) {
+    // twice the block the buddy allocator serves `layout` from always holds an aligned one
+    let block = layout.size().max(layout.align()).next_power_of_two();
+    let pages = (block * 2).div_ceil(PAGE_SIZE);
+    let chunk = pages.max(HEAP_GROW_SIZE / PAGE_SIZE).next_power_of_two();
+
+    let from = jrinx_kaslr::heap_page();
+    let grown = frame_alloc::alloc_from(chunk, chunk * PAGE_SIZE, from)
+        .map(|addr| (addr, chunk))
+        .or_else(|_| frame_alloc::alloc_from(pages, PAGE_SIZE, from).map(|addr| (addr, pages)))
+        .or_else(|_| frame_alloc::alloc_for_heap(pages).map(|addr| (addr, pages)));
+    if let Ok((addr, pages)) = grown {
+        let start = addr.to_virt().as_usize();
+        unsafe { heap.add_to_heap(start, start + pages * PAGE_SIZE) };
     }
 }
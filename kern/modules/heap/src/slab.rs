@@ -0,0 +1,226 @@
+//! Per-CPU slab caches in front of the buddy heap, for allocations of up to [`MAX_CLASS`] bytes.
+//!
+//! Size classes are powers of two, exactly the blocks the buddy heap serves the same layouts
+//! from, so that objects move freely between the caches and the buddy heap. Each CPU allocates
+//! from and frees to a magazine of free objects per class without taking any lock. An empty
+//! magazine is refilled from the depot of its class, shared by every CPU, which carves new slabs
+//! out of the buddy heap when it runs out. A full magazine flushes half of its objects to the
+//! depot, which hands the objects beyond its limit back to the buddy heap.
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    mem::size_of,
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use jrinx_config::PAGE_SIZE;
+use jrinx_hal::{hal, Cpu, Hal, Interrupt};
+use jrinx_percpu::percpu;
+use spin::Mutex;
+
+use crate::BUDDY_HEAP;
+
+const MIN_CLASS: usize = size_of::<usize>();
+pub const MAX_CLASS: usize = 1024;
+pub const CLASSES: usize = (MAX_CLASS / MIN_CLASS).trailing_zeros() as usize + 1;
+
+const MAGAZINE_SIZE: usize = 32;
+/// Objects a depot keeps at most.
+const DEPOT_LIMIT: usize = MAGAZINE_SIZE * 4;
+const SLAB_SIZE: usize = PAGE_SIZE;
+
+/// CPUs whose magazines are ready, one bit each.
+static ONLINE: AtomicUsize = AtomicUsize::new(0);
+
+#[percpu]
+static MAGAZINES: [Magazine; CLASSES] = [Magazine::EMPTY; CLASSES];
+
+static DEPOTS: [Mutex<Depot>; CLASSES] = [Depot::EMPTY; CLASSES];
+
+struct Magazine {
+    objects: [usize; MAGAZINE_SIZE],
+    len: usize,
+    allocs: AtomicUsize,
+    frees: AtomicUsize,
+    refills: AtomicUsize,
+    flushes: AtomicUsize,
+}
+
+impl Magazine {
+    const EMPTY: Self = Self {
+        objects: [0; MAGAZINE_SIZE],
+        len: 0,
+        allocs: AtomicUsize::new(0),
+        frees: AtomicUsize::new(0),
+        refills: AtomicUsize::new(0),
+        flushes: AtomicUsize::new(0),
+    };
+}
+
+/// Free objects linked through their first word.
+struct Depot {
+    head: usize,
+    len: usize,
+    slabs: usize,
+}
+
+impl Depot {
+    const EMPTY: Mutex<Self> = Mutex::new(Self {
+        head: 0,
+        len: 0,
+        slabs: 0,
+    });
+
+    unsafe fn push(&mut self, object: usize) {
+        *(object as *mut usize) = self.head;
+        self.head = object;
+        self.len += 1;
+    }
+
+    unsafe fn pop(&mut self) -> Option<usize> {
+        (self.head != 0).then(|| {
+            let object = self.head;
+            self.head = *(object as *const usize);
+            self.len -= 1;
+            object
+        })
+    }
+}
+
+/// Statistics of a size class, summed over every CPU.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlabStats {
+    pub size: usize,
+    pub allocs: usize,
+    pub frees: usize,
+    /// Times a magazine has been refilled from the depot.
+    pub refills: usize,
+    /// Times a magazine has flushed objects to the depot.
+    pub flushes: usize,
+    /// Free objects held in magazines and the depot.
+    pub cached: usize,
+    /// Slabs carved out of the buddy heap.
+    pub slabs: usize,
+}
+
+/// Enables the magazines of the current CPU, once its per-CPU area is set up. Allocations made
+/// before are served by the buddy heap.
+pub fn init() {
+    ONLINE.fetch_or(1 << hal!().cpu().id(), Ordering::SeqCst);
+}
+
+pub fn stats() -> [SlabStats; CLASSES] {
+    let mut stats = [SlabStats::default(); CLASSES];
+    for (class, stats) in stats.iter_mut().enumerate() {
+        stats.size = size(class);
+        for magazines in MAGAZINES.iter() {
+            let magazine = &magazines[class];
+            stats.allocs += magazine.allocs.load(Ordering::Relaxed);
+            stats.frees += magazine.frees.load(Ordering::Relaxed);
+            stats.refills += magazine.refills.load(Ordering::Relaxed);
+            stats.flushes += magazine.flushes.load(Ordering::Relaxed);
+            // owned by another CPU, the length may be stale but is never torn
+            stats.cached += unsafe { ptr::read_volatile(&magazine.len) };
+        }
+        let (cached, slabs) = hal!().interrupt().with_saved_off(|| {
+            let depot = DEPOTS[class].lock();
+            (depot.len, depot.slabs)
+        });
+        stats.cached += cached;
+        stats.slabs = slabs;
+    }
+    stats
+}
+
+/// Returns the class serving `layout`, if the current CPU has its magazines ready.
+pub(crate) fn class(layout: &Layout) -> Option<usize> {
+    let size = layout
+        .size()
+        .next_power_of_two()
+        .max(layout.align())
+        .max(MIN_CLASS);
+    (size <= MAX_CLASS && ONLINE.load(Ordering::Relaxed) & 1 << hal!().cpu().id() != 0)
+        .then(|| (size / MIN_CLASS).trailing_zeros() as usize)
+}
+
+pub(crate) fn alloc(class: usize) -> *mut u8 {
+    hal!().interrupt().with_saved_off(|| {
+        MAGAZINES.with_ref_mut(|magazines| {
+            let magazine = &mut magazines[class];
+            if magazine.len == 0 {
+                refill(class, magazine);
+            }
+            if magazine.len == 0 {
+                return ptr::null_mut();
+            }
+            magazine.len -= 1;
+            magazine.allocs.fetch_add(1, Ordering::Relaxed);
+            magazine.objects[magazine.len] as *mut u8
+        })
+    })
+}
+
+pub(crate) fn dealloc(class: usize, ptr: *mut u8) {
+    hal!().interrupt().with_saved_off(|| {
+        MAGAZINES.with_ref_mut(|magazines| {
+            let magazine = &mut magazines[class];
+            if magazine.len == MAGAZINE_SIZE {
+                flush(class, magazine);
+            }
+            magazine.objects[magazine.len] = ptr as usize;
+            magazine.len += 1;
+            magazine.frees.fetch_add(1, Ordering::Relaxed);
+        })
+    })
+}
+
+const fn size(class: usize) -> usize {
+    MIN_CLASS << class
+}
+
+/// Fills half of `magazine` from the depot, carving slabs out of the buddy heap as needed.
+fn refill(class: usize, magazine: &mut Magazine) {
+    let size = size(class);
+    let mut depot = DEPOTS[class].lock();
+    while magazine.len < MAGAZINE_SIZE / 2 {
+        if let Some(object) = unsafe { depot.pop() } {
+            magazine.objects[magazine.len] = object;
+            magazine.len += 1;
+            continue;
+        }
+
+        let slab = unsafe {
+            BUDDY_HEAP.alloc(Layout::from_size_align_unchecked(SLAB_SIZE.max(size), size))
+        };
+        if slab.is_null() {
+            break;
+        }
+        for object in (slab as usize..slab as usize + SLAB_SIZE.max(size)).step_by(size) {
+            unsafe { depot.push(object) };
+        }
+        depot.slabs += 1;
+    }
+    magazine.refills.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Moves half of `magazine` to the depot, handing the objects beyond its limit back to the buddy
+/// heap.
+fn flush(class: usize, magazine: &mut Magazine) {
+    let size = size(class);
+    let mut depot = DEPOTS[class].lock();
+    while magazine.len > MAGAZINE_SIZE / 2 {
+        magazine.len -= 1;
+        unsafe { depot.push(magazine.objects[magazine.len]) };
+    }
+    while depot.len > DEPOT_LIMIT {
+        let object = unsafe { depot.pop() }.unwrap();
+        unsafe {
+            BUDDY_HEAP.dealloc(
+                object as *mut u8,
+                Layout::from_size_align_unchecked(size, size),
+            )
+        };
+    }
+    magazine.flushes.fetch_add(1, Ordering::Relaxed);
+}
@@ -0,0 +1,189 @@
+//! Live allocations of the kernel heap along with their call sites, to tell what a scenario leaks.
+//!
+//! Every allocation is recorded with a sequence number and the return addresses of its call
+//! stack, walked through the frame pointers that builds with this feature keep. The first few
+//! return addresses lie in the allocator plumbing, e.g. `alloc::alloc`, the rest point at the code
+//! which allocates. Records live in a table of their own apart from the heap, allocations made
+//! while it is full are counted as dropped instead.
+
+use core::{
+    alloc::Layout,
+    mem::size_of,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use alloc::vec::Vec;
+use jrinx_config::EXECUTOR_STACK_SIZE;
+use jrinx_hal::{hal, Cpu, Hal, Interrupt};
+use spin::Mutex;
+
+use crate::arch;
+
+/// Return addresses recorded for each allocation.
+pub const DEPTH: usize = 6;
+const CAPACITY: usize = 8192;
+
+static SEQ: AtomicU64 = AtomicU64::new(0);
+
+static TABLE: Mutex<Table> = Mutex::new(Table {
+    records: [Record::EMPTY; CAPACITY],
+    len: 0,
+    dropped: 0,
+});
+
+/// An allocation that has not been freed yet.
+#[derive(Debug, Clone, Copy)]
+pub struct Record {
+    pub seq: u64,
+    pub cpu: usize,
+    pub addr: usize,
+    pub size: usize,
+    /// Return addresses of the call stack, innermost first and zero past the outermost.
+    pub callers: [usize; DEPTH],
+}
+
+impl Record {
+    const EMPTY: Self = Self {
+        seq: 0,
+        cpu: 0,
+        addr: 0,
+        size: 0,
+        callers: [0; DEPTH],
+    };
+}
+
+/// Records hashed by address with linear probing, a free slot has a zero address.
+struct Table {
+    records: [Record; CAPACITY],
+    len: usize,
+    dropped: usize,
+}
+
+impl Table {
+    fn home(addr: usize) -> usize {
+        (addr / size_of::<usize>()).wrapping_mul(0x9E37_79B9) % CAPACITY
+    }
+
+    fn insert(&mut self, record: Record) {
+        // probing slows down sharply once the table is mostly full
+        if self.len >= CAPACITY / 4 * 3 {
+            self.dropped += 1;
+            return;
+        }
+        let mut i = Self::home(record.addr);
+        while self.records[i].addr != 0 {
+            i = (i + 1) % CAPACITY;
+        }
+        self.records[i] = record;
+        self.len += 1;
+    }
+
+    fn remove(&mut self, addr: usize) {
+        let mut i = Self::home(addr);
+        while self.records[i].addr != addr {
+            if self.records[i].addr == 0 {
+                // dropped when it was allocated
+                return;
+            }
+            i = (i + 1) % CAPACITY;
+        }
+        self.records[i] = Record::EMPTY;
+        self.len -= 1;
+
+        // shift back the records after it which would no longer be found
+        let mut j = i;
+        loop {
+            j = (j + 1) % CAPACITY;
+            if self.records[j].addr == 0 {
+                break;
+            }
+            let home = Self::home(self.records[j].addr);
+            let stays = if i <= j {
+                i < home && home <= j
+            } else {
+                i < home || home <= j
+            };
+            if !stays {
+                self.records[i] = self.records[j];
+                self.records[j] = Record::EMPTY;
+                i = j;
+            }
+        }
+    }
+}
+
+/// Returns the sequence number the next allocation will be recorded with, to mark the start of a
+/// scenario.
+pub fn mark() -> u64 {
+    SEQ.load(Ordering::SeqCst)
+}
+
+/// Returns the allocations recorded since `mark` that are still live.
+pub fn leaks(mark: u64) -> Vec<Record> {
+    let count = || {
+        hal!().interrupt().with_saved_off(|| {
+            let table = TABLE.lock();
+            table
+                .records
+                .iter()
+                .filter(|r| r.addr != 0 && r.seq >= mark)
+                .count()
+        })
+    };
+
+    // the table must not be locked while the result is allocated, which is recorded itself
+    let mut leaks = Vec::with_capacity(count());
+    let (own, capacity) = (leaks.as_ptr() as usize, leaks.capacity());
+    hal!().interrupt().with_saved_off(|| {
+        let table = TABLE.lock();
+        leaks.extend(
+            table
+                .records
+                .iter()
+                .filter(|r| r.addr != 0 && r.addr != own && r.seq >= mark)
+                .take(capacity)
+                .copied(),
+        );
+    });
+    leaks.sort_unstable_by_key(|r| r.seq);
+    leaks
+}
+
+/// Returns how many allocations have not been recorded as the table was full.
+pub fn dropped() -> usize {
+    hal!().interrupt().with_saved_off(|| TABLE.lock().dropped)
+}
+
+pub(crate) fn record(ptr: *mut u8, layout: &Layout) {
+    let mut record = Record {
+        seq: SEQ.fetch_add(1, Ordering::SeqCst),
+        cpu: hal!().cpu().id(),
+        addr: ptr as usize,
+        size: layout.size(),
+        callers: [0; DEPTH],
+    };
+
+    // caller frames lie above, within the stack this one is on
+    let (sp, mut fp) = arch::stack_frame();
+    for caller in record.callers.iter_mut() {
+        if fp <= sp || fp - sp > EXECUTOR_STACK_SIZE || fp % size_of::<usize>() != 0 {
+            break;
+        }
+        let (ra, next) = unsafe { arch::unwind(fp) };
+        *caller = ra;
+        if next <= fp {
+            break;
+        }
+        fp = next;
+    }
+
+    hal!()
+        .interrupt()
+        .with_saved_off(|| TABLE.lock().insert(record));
+}
+
+pub(crate) fn forget(ptr: *mut u8) {
+    hal!()
+        .interrupt()
+        .with_saved_off(|| TABLE.lock().remove(ptr as usize));
+}
@@ -0,0 +1,106 @@
+//! Kernel address space layout randomization.
+//!
+//! The executor stack region and the window devices are mapped in slide by random offsets within
+//! the room their regions leave, and the heap grows from frames at a random place in memory rather
+//! than from the lowest ones. The offsets are chosen once at boot by [`init`], from the seed the
+//! bootloader passes in `/chosen/rng-seed`, or from the time if there is none. The time hardly
+//! varies from one boot to the next, so the layout is then easy to guess, which is warned about.
+//! Until then, or if the kernel is built without KASLR, every offset is zero.
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use fdt::Fdt;
+use jrinx_config::{
+    VirtMemRegion, EXECUTOR_STACK_REGION, EXECUTOR_STACK_SIZE, EXTERNAL_DEVICE_REGION, PAGE_SIZE,
+    PHYS_MEM_BASE,
+};
+use jrinx_hal::{hal, Cpu, Hal};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+static EXECUTOR_STACK_OFFSET: AtomicUsize = AtomicUsize::new(0);
+static EXTERNAL_DEVICE_OFFSET: AtomicUsize = AtomicUsize::new(0);
+static HEAP_PAGE: AtomicUsize = AtomicUsize::new(0);
+
+/// Chooses the offsets, before any device is mapped, any executor is created and the heap takes
+/// any frame.
+pub fn init(fdt: &Fdt) {
+    let mut rng = SmallRng::seed_from_u64(seed(fdt));
+
+    let stack = rng.gen_range(0..=stack_room() / EXECUTOR_STACK_SIZE) * EXECUTOR_STACK_SIZE;
+    let device = rng.gen_range(0..=device_room() / PAGE_SIZE) * PAGE_SIZE;
+    let memory = fdt
+        .memory()
+        .regions()
+        .filter_map(|region| region.size)
+        .sum::<usize>();
+    let heap = rng.gen_range(0..=memory / PAGE_SIZE / 2);
+
+    EXECUTOR_STACK_OFFSET.store(stack, Ordering::SeqCst);
+    EXTERNAL_DEVICE_OFFSET.store(device, Ordering::SeqCst);
+    HEAP_PAGE.store(heap, Ordering::SeqCst);
+
+    info!(
+        "kaslr: executor stacks at {:#x} (+{:#x}), devices at {:#x} (+{:#x}), heap from page {:#x}",
+        executor_stack_region().addr,
+        stack,
+        external_device_region().addr,
+        device,
+        heap,
+    );
+}
+
+/// Returns the region executor stacks are allocated from.
+pub fn executor_stack_region() -> VirtMemRegion {
+    VirtMemRegion {
+        addr: EXECUTOR_STACK_REGION.addr + EXECUTOR_STACK_OFFSET.load(Ordering::SeqCst),
+        len: EXECUTOR_STACK_REGION.len - stack_room(),
+    }
+}
+
+/// Returns the window devices are mapped in, at their physical address from its start.
+pub fn external_device_region() -> VirtMemRegion {
+    let offset = EXTERNAL_DEVICE_OFFSET.load(Ordering::SeqCst);
+    VirtMemRegion {
+        addr: EXTERNAL_DEVICE_REGION.addr + offset,
+        len: EXTERNAL_DEVICE_REGION.len - offset,
+    }
+}
+
+/// Returns the page of memory the heap prefers to grow from, counting the pages of every region
+/// of memory in order.
+pub fn heap_page() -> usize {
+    HEAP_PAGE.load(Ordering::SeqCst)
+}
+
+/// Half of the executor stack region is given up for its base to slide in.
+const fn stack_room() -> usize {
+    EXECUTOR_STACK_REGION.len / 2
+}
+
+/// Devices sit below memory, so the window slides in what it has beyond that.
+const fn device_room() -> usize {
+    EXTERNAL_DEVICE_REGION.len.saturating_sub(PHYS_MEM_BASE)
+}
+
+fn seed(fdt: &Fdt) -> u64 {
+    match fdt
+        .find_node("/chosen")
+        .and_then(|chosen| chosen.property("rng-seed"))
+    {
+        Some(seed) => seed.value.iter().fold(FNV_OFFSET, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+        }),
+        None => {
+            warn!("kaslr: no /chosen/rng-seed, seeding from the time, KASLR is weak");
+            hal!().cpu().get_time().as_nanos() as u64
+        }
+    }
+}
@@ -1,24 +1,25 @@
 use core::{
     fmt::Display,
+    mem::size_of,
     pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
     task::{Context, Poll, Waker},
 };
 
 use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, task::Wake};
 use jrinx_addr::VirtAddr;
 use jrinx_error::{InternalError, Result};
-use jrinx_hal::{Hal, Vm};
 use jrinx_paging::{GenericPagePerm, GenericPageTable, PagePerm};
 use jrinx_phys_frame::PhysFrame;
 use jrinx_serial_id_macro::SerialId;
 use jrinx_stack_alloc::StackAllocator;
 use jrinx_util::fastpq::{FastPriority, FastPriorityQueueWithLock};
-use jrinx_vmm::KERN_PAGE_TABLE;
 use spin::Lazy;
 
 use crate::{
     arch::{self, SwitchContext},
     inspector::{Inspector, InspectorStatus},
+    registry::Registry,
     runtime::Runtime,
     Task, TaskId, TaskPriority,
 };
@@ -26,26 +27,28 @@ use crate::{
 type TaskQueue = FastPriorityQueueWithLock<TaskPriority, TaskId>;
 
 static EXECUTOR_STACK_ALLOCATOR: Lazy<StackAllocator> = Lazy::new(|| {
+    let region = jrinx_kaslr::executor_stack_region();
     StackAllocator::new(
-        (
-            VirtAddr::new(jrinx_config::EXECUTOR_STACK_REGION.addr),
-            jrinx_config::EXECUTOR_STACK_REGION.len,
-        ),
+        (VirtAddr::new(region.addr), region.len),
         jrinx_config::EXECUTOR_STACK_SIZE,
         |addr| {
-            let mut page_table = KERN_PAGE_TABLE.write();
             let phys_frame = PhysFrame::alloc()?;
-            page_table.map(addr, phys_frame, PagePerm::G | PagePerm::R | PagePerm::W)?;
-            Ok(())
+            jrinx_vmm::update_kern_page_table(|page_table| {
+                page_table.map_range(addr, &[phys_frame], PagePerm::G | PagePerm::R | PagePerm::W)
+            })
         },
         |addr| {
-            let mut page_table = KERN_PAGE_TABLE.write();
-            page_table.unmap(addr)?;
-            Ok(())
+            jrinx_vmm::update_kern_page_table(|page_table| {
+                page_table.unmap_range(addr, jrinx_config::PAGE_SIZE)
+            })
         },
     )
 });
 
+/// Tops of the stacks of executors dropped by the OOM hook, each linked to the next through the
+/// word below it, see [`release_retired_stacks`].
+static RETIRED_STACKS: AtomicUsize = AtomicUsize::new(0);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, SerialId)]
 pub struct ExecutorId(u64);
 
@@ -82,7 +85,7 @@ pub struct Executor {
     status: ExecutorStatus,
     stack_top: VirtAddr,
     switch_context: SwitchContext,
-    task_registry: BTreeMap<TaskId, Task>,
+    task_registry: Registry<TaskId, Task>,
     task_queue: Arc<TaskQueue>,
     task_waker: BTreeMap<TaskId, Waker>,
     current_task_priority: TaskPriority,
@@ -90,24 +93,27 @@ pub struct Executor {
 
 impl Executor {
     pub fn new(priority: ExecutorPriority, root_task: Task) -> Pin<Box<Self>> {
-        let entry = VirtAddr::new(arch::executor_launch as usize);
-        let stack_top = EXECUTOR_STACK_ALLOCATOR
-            .allocate(jrinx_config::EXECUTOR_STACK_SIZE)
-            .unwrap();
+        Self::try_new(priority, root_task).unwrap()
+    }
 
-        hal!().vm().sync_all();
+    pub fn try_new(priority: ExecutorPriority, root_task: Task) -> Result<Pin<Box<Self>>> {
+        let entry = VirtAddr::new(arch::executor_launch as usize);
+        let task_queue = Arc::try_new(TaskQueue::new()).map_err(|_| InternalError::NotEnoughMem)?;
+        let stack_top = EXECUTOR_STACK_ALLOCATOR.allocate(jrinx_config::EXECUTOR_STACK_SIZE)?;
 
-        let mut executor = Box::pin(Self {
+        // the stack is handed back on drop, also if the executor cannot be boxed
+        let mut executor = Box::try_pin(Self {
             id: ExecutorId::new(),
             priority,
             status: ExecutorStatus::Runnable,
             stack_top,
             switch_context: SwitchContext::new_executor(entry, stack_top),
-            task_registry: BTreeMap::new(),
-            task_queue: Arc::new(TaskQueue::new()),
+            task_registry: Registry::new(),
+            task_queue,
             task_waker: BTreeMap::new(),
             current_task_priority:TaskPriority::default(),
-        });
+        })
+        .map_err(|_| InternalError::NotEnoughMem)?;
 
         let executor_addr = &*executor as *const _ as usize;
 
@@ -115,9 +121,9 @@ impl Executor {
             .switch_context
             .init_executor_addr(VirtAddr::new(executor_addr));
 
-        executor.spawn(root_task).unwrap();
+        executor.spawn(root_task)?;
 
-        executor
+        Ok(executor)
     }
 
     pub fn id(&self) -> ExecutorId {
@@ -135,11 +141,13 @@ impl Executor {
     }
 
     pub fn spawn(&mut self, task: Task) -> Result<&mut Self> {
-        let id = task.id;
-        self.task_queue.enqueue(task.priority, id);
+        let (id, priority) = (task.id, task.priority);
         self.task_registry
-            .try_insert(id, task)
-            .map_err(|_| InternalError::DuplicateTaskId)?;
+            .try_insert(id, task, InternalError::DuplicateTaskId)?;
+        if self.task_queue.try_enqueue(priority, id).is_err() {
+            self.task_registry.remove(&id);
+            return Err(InternalError::NotEnoughMem);
+        }
         Ok(self)
     }
 
@@ -205,9 +213,43 @@ impl Executor {
 
 impl Drop for Executor {
     fn drop(&mut self) {
-        EXECUTOR_STACK_ALLOCATOR.deallocate(self.stack_top).unwrap();
+        // handing the stack back changes the kernel page table and shoots it down, which allocates
+        // and may fail again while the heap has run out
+        if jrinx_heap::oom_handling() {
+            retire_stack(self.stack_top);
+        } else {
+            EXECUTOR_STACK_ALLOCATOR.deallocate(self.stack_top).unwrap();
+        }
+    }
+}
+
+fn retire_stack(stack_top: VirtAddr) {
+    let link = (stack_top - size_of::<usize>()).as_usize() as *mut usize;
+    let mut head = RETIRED_STACKS.load(Ordering::SeqCst);
+    loop {
+        unsafe { link.write(head) };
+        match RETIRED_STACKS.compare_exchange(
+            head,
+            stack_top.as_usize(),
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        ) {
+            Ok(_) => break,
+            Err(current) => head = current,
+        }
+    }
+}
 
-        hal!().vm().sync_all();
+/// Hands back the stacks of the executors dropped by the OOM hook, which must be done where
+/// allocating is allowed.
+pub(crate) fn release_retired_stacks() {
+    let mut stack_top = RETIRED_STACKS.swap(0, Ordering::SeqCst);
+    while stack_top != 0 {
+        let next = unsafe { *((stack_top - size_of::<usize>()) as *const usize) };
+        EXECUTOR_STACK_ALLOCATOR
+            .deallocate(VirtAddr::new(stack_top))
+            .unwrap();
+        stack_top = next;
     }
 }
 
@@ -1,6 +1,6 @@
 use core::{fmt::Display, pin::Pin};
 
-use alloc::{boxed::Box, collections::BTreeMap};
+use alloc::boxed::Box;
 use jrinx_addr::VirtAddr;
 use jrinx_error::{InternalError, Result};
 use jrinx_serial_id_macro::SerialId;
@@ -10,6 +10,7 @@ use spin::{Mutex, RwLock};
 use crate::{
     arch,
     executor::{Executor, ExecutorId, ExecutorPriority, ExecutorStatus},
+    registry::Registry,
     runtime::{Runtime, RuntimeStatus},
 };
 
@@ -38,24 +39,28 @@ pub struct Inspector {
 }
 
 struct Scheduler {
-    registry: BTreeMap<ExecutorId, Pin<Box<Executor>>>,
+    registry: Registry<ExecutorId, Pin<Box<Executor>>>,
     queue: ExecutorQueue,
 }
 
 impl Inspector {
     pub fn new(root_executor: Pin<Box<Executor>>) -> Self {
+        Self::try_new(root_executor).unwrap()
+    }
+
+    pub fn try_new(root_executor: Pin<Box<Executor>>) -> Result<Self> {
         let inspector = Self {
             id: InspectorId::new(),
             status: Mutex::new(InspectorStatus::Idle),
             scheduler: RwLock::new(Scheduler {
-                registry: BTreeMap::new(),
+                registry: Registry::new(),
                 queue: ExecutorQueue::new(),
             }),
         };
 
-        inspector.register(root_executor).unwrap();
+        inspector.register(root_executor)?;
 
-        inspector
+        Ok(inspector)
     }
 
     pub fn id(&self) -> InspectorId {
@@ -66,6 +71,16 @@ impl Inspector {
         *self.status.lock()
     }
 
+    /// Returns the highest priority of its executors, none if it has no executor.
+    pub fn priority(&self) -> Option<ExecutorPriority> {
+        self.scheduler
+            .read()
+            .registry
+            .values()
+            .map(|executor| executor.priority())
+            .max()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.scheduler.read().registry.is_empty()
     }
@@ -91,9 +106,11 @@ impl Inspector {
 
         scheduler
             .registry
-            .try_insert(id, executor)
-            .map_err(|_| InternalError::DuplicateExecutorId)?;
-        scheduler.queue.enqueue(priority, id);
+            .try_insert(id, executor, InternalError::DuplicateExecutorId)?;
+        if scheduler.queue.try_enqueue(priority, id).is_err() {
+            scheduler.registry.remove(&id);
+            return Err(InternalError::NotEnoughMem);
+        }
         Ok(())
     }
 
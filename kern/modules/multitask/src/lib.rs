@@ -1,4 +1,5 @@
 #![no_std]
+#![feature(allocator_api)]
 #![feature(asm_const)]
 #![feature(iter_map_windows)]
 #![feature(map_try_insert)]
@@ -9,6 +10,7 @@
 mod arch;
 pub mod executor;
 pub mod inspector;
+mod registry;
 pub mod runtime;
 
 extern crate alloc;
@@ -25,6 +27,7 @@ use core::{
 
 use alloc::boxed::Box;
 use executor::Executor;
+use jrinx_error::{InternalError, Result};
 use jrinx_serial_id_macro::SerialId;
 use jrinx_util::fastpq::FastPriority;
 
@@ -70,11 +73,20 @@ impl Task {
         future: impl Future<Output = ()> + Send + Sync + 'static,
         priority: TaskPriority,
     ) -> Self {
-        Self {
+        Self::try_new(future, priority).unwrap()
+    }
+
+    pub fn try_new(
+        future: impl Future<Output = ()> + Send + Sync + 'static,
+        priority: TaskPriority,
+    ) -> Result<Self> {
+        let future: Box<dyn Future<Output = ()> + Send + Sync> =
+            Box::try_new(future).map_err(|_| InternalError::NotEnoughMem)?;
+        Ok(Self {
             id: TaskId::new(),
             priority,
-            future: Box::pin(future),
-        }
+            future: Box::into_pin(future),
+        })
     }
 
     pub fn poll(&mut self, cx: &mut Context) -> Poll<()> {
@@ -83,10 +95,15 @@ impl Task {
 }
 
 pub fn do_spawn(future: impl Future<Output = ()> + Send + Sync + 'static, priority: TaskPriority) {
-    Executor::with_current(|ex| {
-        ex.spawn(Task::new(future, priority)).unwrap();
-    })
-    .unwrap();
+    try_spawn(future, priority).unwrap();
+}
+
+pub fn try_spawn(
+    future: impl Future<Output = ()> + Send + Sync + 'static,
+    priority: TaskPriority,
+) -> Result<()> {
+    let task = Task::try_new(future, priority)?;
+    Executor::with_current(|ex| ex.spawn(task).map(|_| ()))?
 }
 
 #[macro_export]
@@ -0,0 +1,64 @@
+use alloc::vec::Vec;
+
+use jrinx_error::{InternalError, Result};
+
+/// Values kept in a vector sorted by their IDs, which unlike a `BTreeMap` fails to grow instead of
+/// aborting once the heap has run out of memory.
+pub(crate) struct Registry<K, V> {
+    entries: Vec<(K, V)>,
+}
+
+impl<K: Ord, V> Registry<K, V> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Inserts `value` with `key`, failing with `duplicate` if `key` is already there.
+    pub(crate) fn try_insert(&mut self, key: K, value: V, duplicate: InternalError) -> Result<()> {
+        let Err(index) = self.search(&key) else {
+            return Err(duplicate);
+        };
+        self.entries
+            .try_reserve(1)
+            .map_err(|_| InternalError::NotEnoughMem)?;
+        self.entries.insert(index, (key, value));
+        Ok(())
+    }
+
+    pub(crate) fn remove(&mut self, key: &K) -> Option<V> {
+        let index = self.search(key).ok()?;
+        Some(self.entries.remove(index).1)
+    }
+
+    pub(crate) fn get(&self, key: &K) -> Option<&V> {
+        let index = self.search(key).ok()?;
+        Some(&self.entries[index].1)
+    }
+
+    pub(crate) fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let index = self.search(key).ok()?;
+        Some(&mut self.entries[index].1)
+    }
+
+    pub(crate) fn contains_key(&self, key: &K) -> bool {
+        self.search(key).is_ok()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.iter().map(|(_, value)| value)
+    }
+
+    fn search(&self, key: &K) -> core::result::Result<usize, usize> {
+        self.entries.binary_search_by(|(probe, _)| probe.cmp(key))
+    }
+}
@@ -1,9 +1,12 @@
-use core::{cell::SyncUnsafeCell, future::Future, sync::atomic::AtomicUsize, time::Duration};
-
-use alloc::{
-    collections::{BTreeMap, VecDeque},
-    vec::Vec,
+use core::{
+    alloc::Layout,
+    cell::SyncUnsafeCell,
+    future::Future,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
 };
+
+use alloc::{collections::VecDeque, vec::Vec};
 use jrinx_addr::VirtAddr;
 use jrinx_error::{InternalError, Result};
 use jrinx_hal::{Cpu, Hal, HaltReason, Interrupt};
@@ -14,8 +17,9 @@ use spin::{Mutex, RwLock};
 
 use crate::{
     arch::{self, SwitchContext},
-    executor::{Executor, ExecutorPriority},
+    executor::{self, Executor, ExecutorPriority},
     inspector::{Inspector, InspectorId, InspectorStatus},
+    registry::Registry,
     Task, TaskPriority,
 };
 
@@ -35,7 +39,7 @@ pub struct Runtime {
 }
 
 struct RuntimeInspectorScheduler {
-    registry: BTreeMap<InspectorId, Inspector>,
+    registry: Registry<InspectorId, Inspector>,
     queue: VecDeque<InspectorId>,
     sched_table: Option<RuntimeSchedTable>,
 }
@@ -60,7 +64,7 @@ impl Runtime {
     pub const fn new() -> Self {
         Self {
             scheduler: RwLock::new(RuntimeInspectorScheduler {
-                registry: BTreeMap::new(),
+                registry: Registry::new(),
                 queue: VecDeque::new(),
                 sched_table: None,
             }),
@@ -99,10 +103,13 @@ impl Runtime {
     pub fn register(&self, inspector: Inspector) -> Result<()> {
         let id = inspector.id();
         let mut inspectors = self.scheduler.write();
+        inspectors
+            .queue
+            .try_reserve(1)
+            .map_err(|_| InternalError::NotEnoughMem)?;
         inspectors
             .registry
-            .try_insert(id, inspector)
-            .map_err(|_| InternalError::DuplicateInspectorId)?;
+            .try_insert(id, inspector, InternalError::DuplicateInspectorId)?;
         inspectors.queue.push_back(id);
         Ok(())
     }
@@ -223,6 +230,38 @@ impl Runtime {
         Ok(())
     }
 
+    /// Takes out the inspector of the lowest priority below `below`, leaving alone the running one
+    /// and those in the sched table. Gives up if the scheduler is locked, e.g. by the allocation
+    /// which has run out of memory.
+    fn shed(&self, below: ExecutorPriority) -> Option<Inspector> {
+        let current = match self.status() {
+            RuntimeStatus::Running(id) => Some(id),
+            _ => None,
+        };
+        let mut scheduler = self.scheduler.try_write()?;
+
+        let (_, id) = scheduler
+            .registry
+            .iter()
+            .filter(|&(&id, _)| {
+                Some(id) != current
+                    && scheduler
+                        .sched_table
+                        .as_ref()
+                        .map_or(true, |table| !table.contains(id))
+            })
+            .filter_map(|(&id, inspector)| {
+                inspector
+                    .priority()
+                    .filter(|&priority| priority < below)
+                    .map(|priority| (priority, id))
+            })
+            .min()?;
+
+        scheduler.queue.retain(|&queued| queued != id);
+        scheduler.registry.remove(&id)
+    }
+
     fn switch_context_addr(&self) -> VirtAddr {
         VirtAddr::new(self.switch_context.get() as *const _ as usize)
     }
@@ -265,6 +304,7 @@ impl Runtime {
             });
 
             trace!("switch from inspector {:?}", entry.inspector_id);
+            reap();
         }
     }
 
@@ -291,6 +331,7 @@ impl Runtime {
             });
 
             trace!("switch from inspector {:?}", inspector_id);
+            reap();
 
             if Runtime::with_current(|rt| {
                 rt.with_inspector(inspector_id, |is| {
@@ -363,6 +404,10 @@ impl RuntimeSchedTable {
         *self.datum.lock() = hal!().cpu().get_time();
     }
 
+    fn contains(&self, id: InspectorId) -> bool {
+        self.table.iter().any(|entry| entry.inspector_id == id)
+    }
+
     pub(crate) fn sched_next(&self) -> RuntimeSchedTableEntry {
         self.events.lock().retain(|event| !event.retired());
 
@@ -473,12 +518,54 @@ impl Drop for RuntimeSchedTable {
 #[percpu]
 static RUNTIME: Runtime = Runtime::new();
 
+/// Inspectors whose executors are all below this priority may be shed once the heap runs out of
+/// memory, none are if it is not set.
+static OOM_SHED_BELOW: Mutex<Option<ExecutorPriority>> = Mutex::new(None);
+
+/// Inspectors shed since [`reap`] last ran.
+static SHED: AtomicUsize = AtomicUsize::new(0);
+
 pub fn init(future: impl Future<Output = ()> + Send + Sync + 'static) {
+    try_init(future).unwrap();
+}
+
+pub fn try_init(future: impl Future<Output = ()> + Send + Sync + 'static) -> Result<()> {
+    jrinx_heap::set_oom_hook(oom);
+
     RUNTIME
         .as_ref()
-        .register(Inspector::new(Executor::new(
+        .register(Inspector::try_new(Executor::try_new(
             ExecutorPriority::default(),
-            Task::new(future, TaskPriority::default()),
-        )))
-        .unwrap();
+            Task::try_new(future, TaskPriority::default())?,
+        )?)?)
+}
+
+pub fn set_oom_shed_below(priority: Option<ExecutorPriority>) {
+    *OOM_SHED_BELOW.lock() = priority;
+}
+
+/// Sheds an inspector of the current CPU to free memory, see [`set_oom_shed_below`].
+///
+/// Allocations fail while it runs, so the stacks of its executors are handed back and the
+/// shedding is logged later on by [`reap`].
+fn oom(_layout: &Layout) -> bool {
+    let Some(below) = *OOM_SHED_BELOW.lock() else {
+        return false;
+    };
+    let Some(inspector) = Runtime::with_current(|rt| rt.shed(below)) else {
+        return false;
+    };
+    drop(inspector);
+    SHED.fetch_add(1, Ordering::SeqCst);
+    true
+}
+
+/// Finishes tearing down the inspectors shed by the OOM hook, which the runtime does between
+/// inspectors, where allocating is allowed.
+fn reap() {
+    let shed = SHED.swap(0, Ordering::SeqCst);
+    if shed != 0 {
+        warn!("out of memory, shed {} inspectors", shed);
+    }
+    executor::release_retired_stacks();
 }
@@ -2,6 +2,7 @@ use alloc::alloc::Global;
 use core::{
     alloc::{Allocator, Layout},
     mem::size_of,
+    slice::from_raw_parts_mut,
 };
 
 use jrinx_addr::{PhysAddr, VirtAddr};
@@ -12,14 +13,76 @@ use riscv::{
     asm,
     register::satp::{self, Mode},
 };
+use sbi::system_reset::{ResetReason, ResetType};
 
-use crate::{GenericPagePerm, PagePerm, PageTableEntry};
+use crate::{GenericPagePerm, PagePerm, PageSize, PageTableEntry, MODE};
+
+const ENTRIES: usize = PAGE_SIZE / size_of::<usize>();
+
+/// Size of the leaves remapping memory at boot, which sit right under the root in Sv32 and Sv39,
+/// and further down in the deeper modes.
+#[cfg(target_arch = "riscv32")]
+const REMAP_PAGE_SIZE: PageSize = PageSize::Size4M;
+#[cfg(target_arch = "riscv64")]
+const REMAP_PAGE_SIZE: PageSize = PageSize::Size1G;
+
+const _: () = assert!(REMAP_PAGE_SIZE.size() == REMAP_HUGE_PAGE_SIZE);
+
+/// Depth of the entries of `REMAP_PAGE_SIZE` leaves, the root being at depth 0.
+const REMAP_DEPTH: usize = PageSize::LEVELS.len() - 1 - REMAP_PAGE_SIZE.level();
+
+/// Modes probed at boot.
+#[cfg(target_arch = "riscv32")]
+const MODES: &[Mode] = &[Mode::Sv32];
+#[cfg(target_arch = "riscv64")]
+const MODES: &[Mode] = &[Mode::Sv39, Mode::Sv48, Mode::Sv57];
 
 #[repr(C, align(4096))]
-struct BootPageTableInner([usize; PAGE_SIZE / size_of::<usize>()]);
+struct BootPageTableInner([usize; ENTRIES]);
+
+impl BootPageTableInner {
+    const EMPTY: Self = Self([0; ENTRIES]);
+}
+
+static mut BOOT_PAGE_TABLE: BootPageTableInner = BootPageTableInner::EMPTY;
+
+/// Tables between the root and the leaves of `REMAP_MEM_REGIONS`, taken in order.
+static mut BOOT_TABLES: [BootPageTableInner; boot_table_count()] =
+    [BootPageTableInner::EMPTY; boot_table_count()];
+static mut BOOT_TABLES_USED: usize = 0;
 
-static mut BOOT_PAGE_TABLE: BootPageTableInner =
-    BootPageTableInner([0; PAGE_SIZE / size_of::<usize>()]);
+/// A root table whose leaves map memory to itself in every mode the hart may support.
+static mut PROBE_PAGE_TABLE: BootPageTableInner = BootPageTableInner::EMPTY;
+
+/// Modes the hart supports, one bit per `satp` mode.
+static mut SUPPORTED_MODES: usize = 0;
+
+/// Returns how many tables the regions of `REMAP_MEM_REGIONS` need at each depth between the root
+/// and their leaves, some of which may be shared.
+const fn boot_table_count() -> usize {
+    let mut count = 0;
+    let mut i = 0;
+    while i < REMAP_MEM_REGIONS.len() {
+        let start = REMAP_MEM_REGIONS[i].virt_addr;
+        let last = start + REMAP_MEM_REGIONS[i].len - 1;
+        let mut depth = 1;
+        while depth <= REMAP_DEPTH {
+            let span = PageSize::LEVELS[PageSize::LEVELS.len() - depth].size();
+            count += last / span - start / span + 1;
+            depth += 1;
+        }
+        i += 1;
+    }
+    count
+}
+
+/// Returns the paging modes the hart supports, as probed at boot.
+pub fn supported_modes() -> impl Iterator<Item = Mode> {
+    MODES
+        .iter()
+        .copied()
+        .filter(|&mode| unsafe { SUPPORTED_MODES } & 1 << mode as usize != 0)
+}
 
 pub struct BootPageTable;
 
@@ -35,8 +98,22 @@ impl BootPageTable {
     /// BootPageTable.init();
     /// BootPageTable.start();
     /// ```
+    ///
+    /// Memory is remapped with every permission until [`BootPageTable::protect`] narrows it.
     #[inline(always)]
     pub unsafe fn init(&self) {
+        Self::probe();
+        if SUPPORTED_MODES & 1 << MODE as usize == 0 {
+            for &c in b"the paging mode the kernel is built for is not supported\n" {
+                sbi::legacy::console_putchar(c);
+            }
+            let _ =
+                sbi::system_reset::system_reset(ResetType::Shutdown, ResetReason::SystemFailure);
+            loop {
+                asm::wfi();
+            }
+        }
+
         for &RemapMemRegion {
             virt_addr,
             phys_addr,
@@ -46,7 +123,7 @@ impl BootPageTable {
             for i in 0..(len / REMAP_HUGE_PAGE_SIZE) {
                 let vaddr = VirtAddr::new(virt_addr + i * REMAP_HUGE_PAGE_SIZE);
                 let paddr = PhysAddr::new(phys_addr + i * REMAP_HUGE_PAGE_SIZE);
-                BOOT_PAGE_TABLE.0[vaddr.indexes()[0]] = PageTableEntry::new(
+                *Self::remap_entry(vaddr) = PageTableEntry::new(
                     paddr,
                     PagePerm::G | PagePerm::X | PagePerm::W | PagePerm::R | PagePerm::V,
                 )
@@ -55,6 +132,50 @@ impl BootPageTable {
         }
     }
 
+    /// Writes each mode to `satp` and reads it back, as writing a mode the hart does not support
+    /// has no effect. Runs with the memory mapped to itself, before the kernel is remapped.
+    #[inline(always)]
+    unsafe fn probe() {
+        // a leaf of the root is as large as a remapping leaf in Sv32 and Sv39, the first leaf
+        // alone covers the kernel in the deeper modes
+        for (i, pte) in PROBE_PAGE_TABLE.0.iter_mut().enumerate() {
+            *pte = PageTableEntry::new(
+                PhysAddr::new(i * REMAP_HUGE_PAGE_SIZE),
+                PagePerm::G | PagePerm::X | PagePerm::W | PagePerm::R | PagePerm::V,
+            )
+            .into();
+        }
+
+        let pt_ppn: usize = PROBE_PAGE_TABLE.0.as_ptr() as usize / PAGE_SIZE;
+        for &mode in MODES {
+            satp::set(mode, 0, pt_ppn);
+            if satp::read().mode() == mode {
+                SUPPORTED_MODES |= 1 << mode as usize;
+            }
+            satp::set(Mode::Bare, 0, 0);
+            asm::sfence_vma_all();
+        }
+    }
+
+    /// Returns the entry of the remapping leaf of `vaddr`, taking the tables it lacks from
+    /// `BOOT_TABLES`. Runs before the kernel is remapped, with tables at their physical addresses.
+    #[inline(always)]
+    unsafe fn remap_entry(vaddr: VirtAddr) -> &'static mut usize {
+        let indexes = vaddr.indexes();
+        let mut table = &mut BOOT_PAGE_TABLE.0[..];
+        for &index in &indexes[..REMAP_DEPTH] {
+            let pte = &mut table[index];
+            if *pte & PagePerm::V.bits() == 0 {
+                let next = BOOT_TABLES[BOOT_TABLES_USED].0.as_ptr() as usize;
+                BOOT_TABLES_USED += 1;
+                *pte = PageTableEntry::new(PhysAddr::new(next), PagePerm::V).into();
+            }
+            let (next, _) = PageTableEntry::from_raw(*pte).into();
+            table = from_raw_parts_mut(next.as_usize() as *mut usize, ENTRIES);
+        }
+        &mut table[indexes[REMAP_DEPTH]]
+    }
+
     /// # Safety
     ///
     /// This function is used to enable kernel remapping at the bootstrap entry **AFTER** `BootPageTable.init`.
@@ -70,11 +191,7 @@ impl BootPageTable {
     pub unsafe fn start(&self) {
         let pt_ppn: usize = BOOT_PAGE_TABLE.0.as_ptr() as usize / PAGE_SIZE;
 
-        #[cfg(target_arch = "riscv32")]
-        satp::set(Mode::Sv32, 0, pt_ppn);
-
-        #[cfg(target_arch = "riscv64")]
-        satp::set(Mode::Sv39, 0, pt_ppn);
+        satp::set(MODE, 0, pt_ppn);
 
         asm::sfence_vma_all();
 
@@ -86,6 +203,68 @@ impl BootPageTable {
         core::arch::asm!("li t0, {OFFSET}", "add sp, sp, t0", "add ra, ra, t0", "ret", OFFSET = const REMAP_MEM_OFFSET, options(noreturn),);
     }
 
+    /// # Safety
+    ///
+    /// This function is used to narrow the permissions of the remapped memory once the kernel
+    /// runs at its remapped addresses and the kernel heap is ready, before any other hart starts
+    /// and any page table clones the boot page table.
+    ///
+    /// Each section of the kernel image is mapped with the narrowest permission it needs,
+    /// splitting the leaves it shares with others, and the rest of the remapped memory, where the
+    /// kernel heap lives, is no longer executable. Memory mapped to itself for the harts to start
+    /// stays as is.
+    pub unsafe fn protect(&self) {
+        for &RemapMemRegion {
+            virt_addr,
+            phys_addr,
+            len,
+        } in REMAP_MEM_REGIONS
+        {
+            if virt_addr != phys_addr + REMAP_MEM_OFFSET {
+                continue;
+            }
+            for i in 0..(len / REMAP_HUGE_PAGE_SIZE) {
+                let vaddr = VirtAddr::new(virt_addr + i * REMAP_HUGE_PAGE_SIZE);
+                Self::protect_leaf(Self::remapped_entry(vaddr), vaddr, REMAP_PAGE_SIZE);
+            }
+        }
+        asm::sfence_vma_all();
+    }
+
+    /// Returns the entry of the remapping leaf of `vaddr`, once the kernel is remapped.
+    unsafe fn remapped_entry(vaddr: VirtAddr) -> &'static mut usize {
+        let indexes = vaddr.indexes();
+        let mut table = &mut BOOT_PAGE_TABLE.0[..];
+        for &index in &indexes[..REMAP_DEPTH] {
+            let (next, _) = PageTableEntry::from_raw(table[index]).into();
+            table = next.to_virt().as_array_base();
+        }
+        &mut table[indexes[REMAP_DEPTH]]
+    }
+
+    /// Sets the permission of the leaf of `size` at `pte` mapping `vaddr`, replacing it with a
+    /// table of smaller leaves if sections needing different permissions share it.
+    unsafe fn protect_leaf(pte: &mut usize, vaddr: VirtAddr, size: PageSize) {
+        let (paddr, _) = PageTableEntry::from_raw(*pte).into();
+        if let Some(perm) = kernel_perm(vaddr.as_usize(), vaddr.as_usize() + size.size()) {
+            *pte = PageTableEntry::new(paddr, perm).into();
+            return;
+        }
+
+        let smaller = PageSize::LEVELS[size.level() - 1];
+        let table = Global
+            .allocate_zeroed(Layout::from_size_align_unchecked(PAGE_SIZE, PAGE_SIZE))
+            .unwrap()
+            .cast::<usize>()
+            .as_ptr();
+        let entries = from_raw_parts_mut(table, ENTRIES);
+        for (i, entry) in entries.iter_mut().enumerate() {
+            *entry = PageTableEntry::new(paddr + i * smaller.size(), PagePerm::V).into();
+            Self::protect_leaf(entry, vaddr + i * smaller.size(), smaller);
+        }
+        *pte = PageTableEntry::new(VirtAddr::new(table as usize).to_phys(), PagePerm::V).into();
+    }
+
     /// # Safety
     ///
     /// This function is used to map a page of memory into the boot page table. Caller must ensure
@@ -105,7 +284,7 @@ impl BootPageTable {
             if i == indexes.len() - 1 {
                 *pte = PageTableEntry::new(
                     phys_addr,
-                    PagePerm::G | PagePerm::X | PagePerm::W | PagePerm::R | PagePerm::V,
+                    PagePerm::G | PagePerm::W | PagePerm::R | PagePerm::V,
                 )
                 .into();
                 break;
@@ -131,7 +310,37 @@ impl BootPageTable {
     }
 
     pub fn clone_into(dst: &mut [usize]) {
-        const HALF: usize = PAGE_SIZE / size_of::<usize>() / 2;
+        const HALF: usize = ENTRIES / 2;
         dst[HALF..].copy_from_slice(&unsafe { BOOT_PAGE_TABLE.0 }[HALF..]);
     }
 }
+
+/// Returns the permission of the remapped memory at `[start, end)`, or `None` if sections of the
+/// kernel image needing different permissions share it.
+///
+/// The text and read-only data end at page boundaries, as the sections following them are aligned
+/// to pages. Everything else, from the data of the kernel image to the memory past its end, is
+/// readable and writable.
+fn kernel_perm(start: usize, end: usize) -> Option<PagePerm> {
+    let sections = [
+        (
+            jrinx_layout::_stext(),
+            jrinx_layout::_etext().next_multiple_of(PAGE_SIZE),
+            PagePerm::G | PagePerm::X | PagePerm::R | PagePerm::V,
+        ),
+        (
+            jrinx_layout::_srodata(),
+            jrinx_layout::_erodata().next_multiple_of(PAGE_SIZE),
+            PagePerm::G | PagePerm::R | PagePerm::V,
+        ),
+    ];
+    match sections
+        .iter()
+        .find(|&&(section_start, section_end, _)| section_start < end && start < section_end)
+    {
+        Some(&(section_start, section_end, perm)) => {
+            (section_start <= start && end <= section_end).then_some(perm)
+        }
+        None => Some(PagePerm::G | PagePerm::W | PagePerm::R | PagePerm::V),
+    }
+}
@@ -3,7 +3,8 @@ use core::fmt::Display;
 
 use alloc::string::String;
 use bitflags::bitflags;
-use jrinx_addr::PhysAddr;
+use jrinx_addr::{PhysAddr, VirtAddr};
+pub use jrinx_hal::vm::MODE;
 
 use crate::{common, CloneKernel, GenericPagePerm, GenericPageTableEntry};
 
@@ -18,6 +19,7 @@ bitflags! {
         const __G = 1 << 5;
         const __A = 1 << 6;
         const __D = 1 << 7;
+        const __COW = 1 << 8; // software-bit: copy-on-write
     }
 }
 
@@ -28,6 +30,7 @@ impl GenericPagePerm for PagePerm {
     const X: Self = Self::__X;
     const U: Self = Self::__U;
     const G: Self = Self::__G;
+    const COW: Self = Self::__COW;
 }
 
 impl Display for PagePerm {
@@ -42,6 +45,89 @@ impl Display for PagePerm {
     }
 }
 
+/// Sizes of the leaves a page table maps, a leaf of a level above the last one is a huge page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PageSize {
+    Size4K,
+    #[cfg(target_arch = "riscv32")]
+    Size4M,
+    #[cfg(target_arch = "riscv64")]
+    Size2M,
+    #[cfg(target_arch = "riscv64")]
+    Size1G,
+    #[cfg(any(feature = "sv48", feature = "sv57"))]
+    Size512G,
+    #[cfg(feature = "sv57")]
+    Size256T,
+}
+
+impl PageSize {
+    /// Sizes indexed by the level of their leaves, counting up from the last level.
+    #[cfg(target_arch = "riscv32")]
+    pub(crate) const LEVELS: [Self; 2] = [Self::Size4K, Self::Size4M];
+    #[cfg(all(target_arch = "riscv64", not(any(feature = "sv48", feature = "sv57"))))]
+    pub(crate) const LEVELS: [Self; 3] = [Self::Size4K, Self::Size2M, Self::Size1G];
+    #[cfg(all(feature = "sv48", not(feature = "sv57")))]
+    pub(crate) const LEVELS: [Self; 4] =
+        [Self::Size4K, Self::Size2M, Self::Size1G, Self::Size512G];
+    #[cfg(feature = "sv57")]
+    pub(crate) const LEVELS: [Self; 5] = [
+        Self::Size4K,
+        Self::Size2M,
+        Self::Size1G,
+        Self::Size512G,
+        Self::Size256T,
+    ];
+
+    pub const fn size(self) -> usize {
+        match self {
+            Self::Size4K => 1 << 12,
+            #[cfg(target_arch = "riscv32")]
+            Self::Size4M => 1 << 22,
+            #[cfg(target_arch = "riscv64")]
+            Self::Size2M => 1 << 21,
+            #[cfg(target_arch = "riscv64")]
+            Self::Size1G => 1 << 30,
+            #[cfg(any(feature = "sv48", feature = "sv57"))]
+            Self::Size512G => 1 << 39,
+            #[cfg(feature = "sv57")]
+            Self::Size256T => 1 << 48,
+        }
+    }
+
+    pub(crate) const fn level(self) -> usize {
+        match self {
+            Self::Size4K => 0,
+            #[cfg(target_arch = "riscv32")]
+            Self::Size4M => 1,
+            #[cfg(target_arch = "riscv64")]
+            Self::Size2M => 1,
+            #[cfg(target_arch = "riscv64")]
+            Self::Size1G => 2,
+            #[cfg(any(feature = "sv48", feature = "sv57"))]
+            Self::Size512G => 3,
+            #[cfg(feature = "sv57")]
+            Self::Size256T => 4,
+        }
+    }
+
+    pub(crate) fn align_down(self, addr: VirtAddr) -> VirtAddr {
+        VirtAddr::new(addr.as_usize() & !(self.size() - 1))
+    }
+}
+
+impl Display for PageSize {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let size = self.size() as u64;
+        match size.trailing_zeros() {
+            40.. => write!(f, "{}T", size >> 40),
+            30.. => write!(f, "{}G", size >> 30),
+            20.. => write!(f, "{}M", size >> 20),
+            _ => write!(f, "{}K", size >> 10),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 #[repr(C)]
 pub struct PageTableEntry {
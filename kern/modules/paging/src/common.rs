@@ -1,15 +1,21 @@
-use alloc::{collections::BTreeMap, sync::Arc};
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{fmt::Display, mem::size_of};
+
 use jrinx_addr::{PhysAddr, VirtAddr};
 use jrinx_error::{InternalError, Result};
 use jrinx_phys_frame::PhysFrame;
 
 use crate::{
-    CloneKernel, GenericPagePerm, GenericPageTable, GenericPageTableEntry, PagePerm, PageTableEntry,
+    CloneKernel, GenericPagePerm, GenericPageTable, GenericPageTableEntry, PagePerm, PageSize,
+    PageTableEntry, TlbFlush,
 };
 
 pub struct PageTable {
     root: PhysAddr,
+    /// Frames mapped, keyed by the address of their leaf. The pages of a split huge page all hold
+    /// the frame of the whole block.
     frames: BTreeMap<VirtAddr, Arc<PhysFrame>>,
+    tables: BTreeMap<PhysAddr, Arc<PhysFrame>>,
 }
 
 impl GenericPageTable<PagePerm, PageTableEntry> for PageTable {
@@ -18,69 +24,303 @@ impl GenericPageTable<PagePerm, PageTableEntry> for PageTable {
     }
 
     fn translate(&self, addr: VirtAddr) -> jrinx_error::Result<(PhysAddr, PagePerm)> {
-        if let Ok((phys_frame, perm)) = self.lookup(addr) {
-            Ok((
-                phys_frame.addr() + (addr.as_usize() & (jrinx_config::PAGE_SIZE - 1)),
-                perm,
-            ))
+        let (pte, size) = self.find(addr)?;
+        if pte.valid() {
+            let (phys_addr, perm) = pte.clone().into();
+            Ok((phys_addr + (addr.as_usize() & (size.size() - 1)), perm))
         } else {
             Err(InternalError::InvalidVirtAddr)
         }
     }
 
+    /// Returns the frame mapping `addr`, which is the whole block for a page of a huge page.
     fn lookup(&self, addr: VirtAddr) -> jrinx_error::Result<(Arc<PhysFrame>, PagePerm)> {
-        let addr = addr.align_page_down();
-        let pte = self.find(addr)?;
+        let (pte, size) = self.find(addr)?;
         if pte.valid() {
             let (_, perm) = pte.clone().into();
-            Ok((self.frames[&addr].clone(), perm))
+            let frame = self
+                .frames
+                .get(&size.align_down(addr))
+                .ok_or(InternalError::InvalidVirtAddr)?;
+            Ok((frame.clone(), perm))
         } else {
             Err(InternalError::InvalidVirtAddr)
         }
     }
 
+    fn leaf_size(&self, addr: VirtAddr) -> jrinx_error::Result<PageSize> {
+        match self.find(addr)? {
+            (pte, size) if pte.valid() => Ok(size),
+            _ => Err(InternalError::InvalidVirtAddr),
+        }
+    }
+
     fn map(
         &mut self,
         addr: VirtAddr,
         phys_frame: Arc<PhysFrame>,
         perm: PagePerm,
     ) -> jrinx_error::Result<()> {
-        let addr = addr.align_page_down();
+        self.map_sized(addr.align_page_down(), phys_frame, PageSize::Size4K, perm)
+    }
+
+    fn map_sized(
+        &mut self,
+        addr: VirtAddr,
+        phys_frame: Arc<PhysFrame>,
+        size: PageSize,
+        perm: PagePerm,
+    ) -> jrinx_error::Result<()> {
+        if (addr.as_usize() | phys_frame.addr().as_usize()) & (size.size() - 1) != 0
+            || phys_frame.size() < size.size()
+        {
+            return Err(InternalError::InvalidParam);
+        }
         let phys_addr = phys_frame.addr();
-        self.frames.insert(addr, phys_frame);
+        self.set_leaf(addr, phys_frame, phys_addr, size, perm)
+    }
 
-        let pte = self.find_or_create(addr)?;
-        pte.set(phys_addr, perm.union(PagePerm::V));
-        Ok(())
+    fn map_range(
+        &mut self,
+        addr: VirtAddr,
+        phys_frames: &[Arc<PhysFrame>],
+        perm: PagePerm,
+    ) -> jrinx_error::Result<TlbFlush> {
+        let start = addr.align_page_down();
+        let end = start + phys_frames.len() * jrinx_config::PAGE_SIZE;
+        let pages = (start.as_usize()..end.as_usize())
+            .step_by(jrinx_config::PAGE_SIZE)
+            .map(VirtAddr::new);
+
+        // tables are created up front, so that nothing is mapped if one cannot be allocated
+        for page in pages.clone() {
+            if let Err(err) = self.walk(page, PageSize::Size4K, true) {
+                self.reclaim(start, end);
+                return Err(err);
+            }
+        }
+
+        let mut flush = TlbFlush::default();
+        for (page, phys_frame) in pages.zip(phys_frames) {
+            if self.translate(page).is_ok() {
+                flush.addrs.push(page);
+            }
+            self.map(page, phys_frame.clone(), perm)?;
+        }
+        Ok(flush)
     }
 
-    fn unmap(&mut self, addr: VirtAddr) -> jrinx_error::Result<()> {
+    fn unmap(&mut self, addr: VirtAddr) -> jrinx_error::Result<TlbFlush> {
         let addr = addr.align_page_down();
-        self.frames
-            .remove(&addr)
-            .ok_or(InternalError::InvalidVirtAddr)?;
-        let pte = self.find(addr)?;
-        pte.clr();
+        self.owned_leaf(addr)?;
+        self.unmap_range(addr, jrinx_config::PAGE_SIZE)
+    }
+
+    fn unmap_range(&mut self, addr: VirtAddr, len: usize) -> jrinx_error::Result<TlbFlush> {
+        let start = addr.align_page_down();
+        let end = (addr + len).align_page_up();
+        let mut flush = TlbFlush::default();
+        if start >= end {
+            return Ok(flush);
+        }
+
+        // huge pages straddling a bound are split, so that the part out of the range stays mapped
+        for bound in [start, end] {
+            if let Ok(size) = self.owned_leaf(bound) {
+                if size.align_down(bound) != bound {
+                    self.walk(bound, PageSize::Size4K, false)?;
+                }
+            }
+        }
+
+        let leaves = self
+            .frames
+            .range(start..end)
+            .map(|(&addr, _)| addr)
+            .collect::<Vec<_>>();
+        for leaf in leaves {
+            let (pte, _) = self.find(leaf)?;
+            if pte.valid() {
+                flush.addrs.push(leaf);
+            }
+            pte.clr();
+            self.frames.remove(&leaf);
+        }
+        flush.tables_freed = self.reclaim(start, end);
+        Ok(flush)
+    }
+
+    fn protect(&mut self, addr: VirtAddr, perm: PagePerm) -> jrinx_error::Result<()> {
+        let addr = addr.align_page_down();
+        self.owned_leaf(addr)?;
+        let pte = self.walk(addr, PageSize::Size4K, false)?;
+        let (phys_addr, _) = pte.clone().into();
+        pte.set(phys_addr, leaf_perm(perm));
         Ok(())
     }
+
+    /// Huge pages wholly in the range keep their size, the others are split.
+    fn protect_range(
+        &mut self,
+        addr: VirtAddr,
+        len: usize,
+        perm: PagePerm,
+    ) -> jrinx_error::Result<TlbFlush> {
+        let start = addr.align_page_down();
+        let end = (addr + len).align_page_up();
+
+        // every page is checked and huge pages straddling a bound are split before any permission
+        // is changed, so that nothing is left to flush if either fails
+        let mut addr = start;
+        while addr < end {
+            let size = self.owned_leaf(addr)?;
+            addr = size.align_down(addr) + size.size();
+        }
+        for bound in [start, end] {
+            if let Ok(size) = self.owned_leaf(bound) {
+                if size.align_down(bound) != bound {
+                    self.walk(bound, PageSize::Size4K, false)?;
+                }
+            }
+        }
+
+        let mut flush = TlbFlush::default();
+        let mut addr = start;
+        while addr < end {
+            let (pte, size) = self.find(addr)?;
+            let old = pte.clone();
+            let (phys_addr, old_perm): (PhysAddr, PagePerm) = old.clone().into();
+
+            // copy-on-write pages must stay read-only until the next write fault
+            let perm = if old_perm.contains(PagePerm::COW) {
+                perm.difference(PagePerm::W).union(PagePerm::COW)
+            } else {
+                perm
+            };
+            pte.set(phys_addr, leaf_perm(perm));
+            if old.valid() && usize::from(old) != usize::from(pte.clone()) {
+                flush.addrs.push(addr);
+            }
+            addr = addr + size.size();
+        }
+        Ok(flush)
+    }
 }
 
 impl PageTable {
     pub fn new() -> Result<Self> {
         let frame = PhysFrame::alloc()?;
         let root = frame.addr();
-        let mut frames = BTreeMap::new();
-        frames.insert(root.to_virt(), frame);
         Self::clone_kernel(root.to_virt().as_array_base());
-        Ok(Self { root, frames })
+        Ok(Self {
+            root,
+            frames: BTreeMap::new(),
+            tables: BTreeMap::from([(root, frame)]),
+        })
     }
-    fn find(&self, addr: VirtAddr) -> Result<&mut PageTableEntry> {
+
+    /// Copies the kernel half of `kern`'s root table into this page table, so that kernel mappings
+    /// created after this page table (e.g. executor stacks) stay visible in it.
+    ///
+    /// Returns whether the kernel half has changed since it was last copied.
+    pub fn sync_kernel(&mut self, kern: &PageTable) -> bool {
+        const HALF: usize = jrinx_config::PAGE_SIZE / size_of::<usize>() / 2;
+        let src = kern.root.to_virt().as_array_base::<usize>();
+        let dst = self.root.to_virt().as_array_base::<usize>();
+        if dst[HALF..] == src[HALF..] {
+            return false;
+        }
+        dst[HALF..].copy_from_slice(&src[HALF..]);
+        true
+    }
+
+    /// Creates a child page table sharing every user frame with this one.
+    ///
+    /// Every user page becomes a read-only copy-on-write page in both page tables, whatever its
+    /// permission, so that neither gains write access to the frame of the other by changing it
+    /// later. The caller is responsible for flushing the TLB of this page table.
+    pub fn fork(&mut self) -> Result<Self> {
+        let mut child = Self::new()?;
+        for (&addr, frame) in self.frames.iter() {
+            let (pte, size) = self.find(addr)?;
+            let (phys_addr, perm) = pte.clone().into();
+            if !perm.contains(PagePerm::U) {
+                continue;
+            }
+            let perm = perm.difference(PagePerm::W).union(PagePerm::COW);
+            pte.set(phys_addr, perm);
+            child.set_leaf(addr, frame.clone(), phys_addr, size, perm)?;
+        }
+        Ok(child)
+    }
+
+    /// Returns every valid leaf in the order of their addresses, the adjacent ones mapping
+    /// consecutive frames with the same size and permission merged.
+    pub fn mappings(&self) -> Mappings<'_> {
+        Mappings {
+            leaves: Leaves::new(self),
+            pending: None,
+        }
+    }
+
+    /// Resolves a write fault on a copy-on-write page.
+    ///
+    /// The page is copied only if its frame is still shared with another owner, otherwise write
+    /// permission is restored in place, for the whole huge page if the page belongs to one.
+    pub fn resolve_cow(&mut self, addr: VirtAddr) -> Result<()> {
+        let addr = addr.align_page_down();
+        let (frame, perm) = self.lookup(addr)?;
+        if !perm.contains(PagePerm::COW) {
+            return Err(InternalError::InvalidVirtAddr);
+        }
+        let perm = perm.difference(PagePerm::COW).union(PagePerm::W);
+
+        if !self.is_shared(addr, &frame)? {
+            let (pte, _) = self.find(addr)?;
+            let (phys_addr, _) = pte.clone().into();
+            pte.set(phys_addr, leaf_perm(perm));
+            Ok(())
+        } else {
+            let (phys_addr, _) = self.translate(addr)?;
+            let copied = PhysFrame::alloc()?;
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    phys_addr.to_virt().as_usize() as *const u8,
+                    copied.addr().to_virt().as_usize() as *mut u8,
+                    jrinx_config::PAGE_SIZE,
+                );
+            }
+            self.map(addr, copied, perm)
+        }
+    }
+
+    /// Returns whether `frame`, mapping `addr`, is also held by another owner than this page table.
+    ///
+    /// The pages of a split huge page each hold the frame of the whole block, so the leaves of
+    /// this page table holding it are counted before comparing with its references.
+    fn is_shared(&self, addr: VirtAddr, frame: &Arc<PhysFrame>) -> Result<bool> {
+        let (phys_addr, _) = self.translate(addr)?;
+        let base =
+            VirtAddr::new(addr.as_usize() - (phys_addr.as_usize() - frame.addr().as_usize()));
+        let held = self
+            .frames
+            .range(base..base + frame.size())
+            .filter(|(_, held)| Arc::ptr_eq(held, frame))
+            .count();
+
+        // one more reference is held by `frame` itself
+        Ok(Arc::strong_count(frame) > held + 1)
+    }
+
+    /// Returns the entry of the leaf mapping `addr`, along with its size.
+    fn find(&self, addr: VirtAddr) -> Result<(&mut PageTableEntry, PageSize)> {
         let indexes = addr.indexes();
         let mut pa = self.root;
-        for i in 0..indexes.len() {
-            let pte = &mut pa.to_virt().as_array_base::<PageTableEntry>()[indexes[i]];
-            if i == indexes.len() - 1 {
-                return Ok(pte);
+        for (i, &index) in indexes.iter().enumerate() {
+            let pte = &mut pa.to_virt().as_array_base::<PageTableEntry>()[index];
+            let level = indexes.len() - 1 - i;
+            if level == 0 || is_huge_leaf(pte) {
+                return Ok((pte, PageSize::LEVELS[level]));
             } else if !pte.valid() {
                 return Err(InternalError::InvalidVirtAddr);
             }
@@ -89,21 +329,311 @@ impl PageTable {
         Err(InternalError::InvalidVirtAddr)
     }
 
-    fn find_or_create(&mut self, addr: VirtAddr) -> Result<&mut PageTableEntry> {
+    /// Returns the size of the leaf mapping `addr` if its frame is held by this page table, unlike
+    /// e.g. the huge pages of the kernel mapped at boot, which are never split.
+    fn owned_leaf(&self, addr: VirtAddr) -> Result<PageSize> {
+        let (_, size) = self.find(addr)?;
+        if self.frames.contains_key(&size.align_down(addr)) {
+            Ok(size)
+        } else {
+            Err(InternalError::InvalidVirtAddr)
+        }
+    }
+
+    /// Returns the entry of `addr` at the level of leaves of `size`, splitting the huge pages on
+    /// the way, and creating the missing tables if `create`.
+    fn walk(
+        &mut self,
+        addr: VirtAddr,
+        size: PageSize,
+        create: bool,
+    ) -> Result<&mut PageTableEntry> {
         let indexes = addr.indexes();
         let mut pa = self.root;
-        for i in 0..indexes.len() {
-            let pte = &mut pa.to_virt().as_array_base::<PageTableEntry>()[indexes[i]];
-            if i == indexes.len() - 1 {
+        for (i, &index) in indexes.iter().enumerate() {
+            let pte = &mut pa.to_virt().as_array_base::<PageTableEntry>()[index];
+            let level = indexes.len() - 1 - i;
+            if level == size.level() {
                 return Ok(pte);
+            } else if is_huge_leaf(pte) {
+                self.split(pte, addr, PageSize::LEVELS[level])?;
             } else if !pte.valid() {
+                if !create {
+                    return Err(InternalError::InvalidVirtAddr);
+                }
                 let frame = PhysFrame::alloc()?;
                 let addr = frame.addr();
                 pte.set(addr, PagePerm::V);
-                self.frames.insert(addr.to_virt(), frame);
+                self.tables.insert(addr, frame);
             }
             (pa, _) = pte.clone().into();
         }
         Err(InternalError::InvalidVirtAddr)
     }
+
+    /// Replaces the huge page of `size` at `pte` with a table of leaves of the next smaller size,
+    /// which map the same frame with the same permission.
+    fn split(&mut self, pte: &mut PageTableEntry, addr: VirtAddr, size: PageSize) -> Result<()> {
+        let smaller = PageSize::LEVELS[size.level() - 1];
+        let (phys_addr, perm): (PhysAddr, PagePerm) = pte.clone().into();
+        let table = PhysFrame::alloc()?;
+        let entries = table.addr().to_virt().as_array_base::<PageTableEntry>();
+        for (i, entry) in entries.iter_mut().enumerate() {
+            entry.set(phys_addr + i * smaller.size(), perm);
+        }
+        pte.set(table.addr(), PagePerm::V);
+        self.tables.insert(table.addr(), table);
+
+        let base = size.align_down(addr);
+        if let Some(frame) = self.frames.get(&base).cloned() {
+            for i in 1..entries.len() {
+                self.frames.insert(base + i * smaller.size(), frame.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Frees the tables left without any entry by unmapping `[start, end)`, and returns whether
+    /// any has been freed.
+    fn reclaim(&mut self, start: VirtAddr, end: VirtAddr) -> bool {
+        let tables = self.tables.len();
+        if start < end {
+            self.reclaim_table(self.root, PageSize::LEVELS.len() - 1, start, end - 1);
+        }
+        self.tables.len() < tables
+    }
+
+    /// Reclaims the tables under the one at `table` of `level` mapping `[first, last]`, and returns
+    /// whether it is left empty.
+    ///
+    /// Tables not held by this page table are left alone, so are the ones right under the kernel
+    /// half of the root, since every other page table holds a copy of the root entries to them.
+    fn reclaim_table(
+        &mut self,
+        table: PhysAddr,
+        level: usize,
+        first: VirtAddr,
+        last: VirtAddr,
+    ) -> bool {
+        let entries = table.to_virt().as_array_base::<PageTableEntry>();
+        let span = PageSize::LEVELS[level].size();
+        let depth = PageSize::LEVELS.len() - 1 - level;
+        let mut addr = first;
+        loop {
+            let index = addr.indexes()[depth];
+            let pte = &mut entries[index];
+            let entry_last = VirtAddr::new((addr.as_usize() | (span - 1)).min(last.as_usize()));
+            if level > 0 && pte.valid() && !is_huge_leaf(pte) {
+                let (child, _): (PhysAddr, PagePerm) = pte.clone().into();
+                let kernel_half = table == self.root && index >= entries.len() / 2;
+                if self.tables.contains_key(&child)
+                    && self.reclaim_table(child, level - 1, addr, entry_last)
+                    && !kernel_half
+                {
+                    pte.clr();
+                    self.tables.remove(&child);
+                }
+            }
+            if entry_last >= last {
+                break;
+            }
+            addr = entry_last + 1;
+        }
+        entries.iter().all(|pte| usize::from(pte.clone()) == 0)
+    }
+
+    fn set_leaf(
+        &mut self,
+        addr: VirtAddr,
+        phys_frame: Arc<PhysFrame>,
+        phys_addr: PhysAddr,
+        size: PageSize,
+        perm: PagePerm,
+    ) -> Result<()> {
+        let pte = self.walk(addr, size, true)?;
+        let replaced = self
+            .frames
+            .range(addr..addr + size.size())
+            .map(|(&addr, _)| addr)
+            .collect::<Vec<_>>();
+        for addr in replaced {
+            self.frames.remove(&addr);
+        }
+        self.frames.insert(addr, phys_frame);
+        pte.set(phys_addr, leaf_perm(perm));
+        Ok(())
+    }
+}
+
+/// Entries above the last level are huge pages if they grant any of `R`, `W` and `X`, or if they
+/// are kept invalid by [`leaf_perm`] while still holding a frame, as tables are never invalid.
+fn is_huge_leaf(pte: &PageTableEntry) -> bool {
+    let (_, perm): (PhysAddr, PagePerm) = pte.clone().into();
+    let bits: usize = pte.clone().into();
+    bits != 0
+        && (!perm.contains(PagePerm::V) || perm.intersects(PagePerm::R | PagePerm::W | PagePerm::X))
+}
+
+/// Leaves without any of `R`, `W` and `X` are kept invalid, as a valid one would be taken for a
+/// pointer to the next level table.
+fn leaf_perm(perm: PagePerm) -> PagePerm {
+    if perm.intersects(PagePerm::R | PagePerm::W | PagePerm::X) {
+        perm.union(PagePerm::V)
+    } else {
+        perm.difference(PagePerm::V)
+    }
+}
+
+/// Leaves of the same size mapping consecutive pages to consecutive frames with the same
+/// permission, as found by [`PageTable::mappings`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mapping {
+    pub addr: VirtAddr,
+    pub len: usize,
+    pub phys_addr: PhysAddr,
+    /// Size of the leaves.
+    pub size: PageSize,
+    /// Permission of the leaves, without the accessed and dirty bits.
+    pub perm: PagePerm,
+}
+
+impl Mapping {
+    /// Returns whether `leaf` right follows this mapping and can be merged into it.
+    fn extends_to(&self, leaf: &Mapping) -> bool {
+        self.addr.as_usize().wrapping_add(self.len) == leaf.addr.as_usize()
+            && self.phys_addr.as_usize() + self.len == leaf.phys_addr.as_usize()
+            && self.size == leaf.size
+            && self.perm == leaf.perm
+    }
+}
+
+/// Formats the mapping in the style of `/proc/self/maps`, along with the size of its leaves.
+impl Display for Mapping {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        const WIDTH: usize = size_of::<usize>() * 2;
+        let flag = |perm: PagePerm, c: char| if self.perm.contains(perm) { c } else { '-' };
+        write!(
+            f,
+            "{:0WIDTH$x}-{:0WIDTH$x} {}{}{}{}{} {:0WIDTH$x} {}",
+            self.addr.as_usize(),
+            self.addr.as_usize().wrapping_add(self.len),
+            flag(PagePerm::R, 'r'),
+            flag(PagePerm::W, 'w'),
+            flag(PagePerm::X, 'x'),
+            flag(PagePerm::U, 'u'),
+            flag(PagePerm::G, 'g'),
+            self.phys_addr.as_usize(),
+            self.size,
+        )
+    }
+}
+
+/// Iterator of [`PageTable::mappings`].
+pub struct Mappings<'a> {
+    leaves: Leaves<'a>,
+    pending: Option<Mapping>,
+}
+
+impl Iterator for Mappings<'_> {
+    type Item = Mapping;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for leaf in self.leaves.by_ref() {
+            match self.pending.as_mut() {
+                Some(mapping) if mapping.extends_to(&leaf) => mapping.len += leaf.len,
+                _ => {
+                    if let Some(mapping) = self.pending.replace(leaf) {
+                        return Some(mapping);
+                    }
+                }
+            }
+        }
+        self.pending.take()
+    }
+}
+
+/// Walks the valid leaves of a page table depth first, keeping the table and the index of the
+/// next entry at each depth, the root being at depth 0.
+struct Leaves<'a> {
+    tables: [PhysAddr; PageSize::LEVELS.len()],
+    indexes: [usize; PageSize::LEVELS.len()],
+    depth: usize,
+    _page_table: &'a PageTable,
+}
+
+impl<'a> Leaves<'a> {
+    const ENTRIES: usize = jrinx_config::PAGE_SIZE / size_of::<usize>();
+
+    fn new(page_table: &'a PageTable) -> Self {
+        Self {
+            tables: [page_table.root; PageSize::LEVELS.len()],
+            indexes: [0; PageSize::LEVELS.len()],
+            depth: 0,
+            _page_table: page_table,
+        }
+    }
+
+    /// Returns the address mapped by the current entry, sign-extended beyond the bits the page
+    /// table translates.
+    fn addr(&self) -> VirtAddr {
+        let addr = (0..=self.depth)
+            .map(|depth| self.indexes[depth] * level_size(depth))
+            .sum::<usize>();
+        let bits = level_size(0).trailing_zeros() + Self::ENTRIES.trailing_zeros();
+        if bits < usize::BITS {
+            let shift = usize::BITS - bits;
+            VirtAddr::new(((addr << shift) as isize >> shift) as usize)
+        } else {
+            VirtAddr::new(addr)
+        }
+    }
+}
+
+impl Iterator for Leaves<'_> {
+    type Item = Mapping;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.indexes[self.depth] == Self::ENTRIES {
+                if self.depth == 0 {
+                    return None;
+                }
+                self.depth -= 1;
+                self.indexes[self.depth] += 1;
+                continue;
+            }
+
+            let entries = self.tables[self.depth]
+                .to_virt()
+                .as_array_base::<PageTableEntry>();
+            let pte = entries[self.indexes[self.depth]].clone();
+            let level = PageSize::LEVELS.len() - 1 - self.depth;
+            let (phys_addr, perm): (PhysAddr, PagePerm) = pte.clone().into();
+            if level == 0 || is_huge_leaf(&pte) {
+                let addr = self.addr();
+                self.indexes[self.depth] += 1;
+                if pte.valid() {
+                    let size = PageSize::LEVELS[level];
+                    return Some(Mapping {
+                        addr,
+                        len: size.size(),
+                        phys_addr,
+                        size,
+                        perm: perm.difference(PagePerm::__A | PagePerm::__D),
+                    });
+                }
+            } else if pte.valid() {
+                self.depth += 1;
+                self.tables[self.depth] = phys_addr;
+                self.indexes[self.depth] = 0;
+            } else {
+                self.indexes[self.depth] += 1;
+            }
+        }
+    }
+}
+
+/// Returns the size an entry at `depth` maps, the root being at depth 0.
+fn level_size(depth: usize) -> usize {
+    PageSize::LEVELS[PageSize::LEVELS.len() - 1 - depth].size()
 }
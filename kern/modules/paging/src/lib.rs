@@ -10,7 +10,7 @@ pub use arch::*;
 
 pub mod common;
 
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec::Vec};
 use core::fmt::Display;
 
 use jrinx_addr::{PhysAddr, VirtAddr};
@@ -24,6 +24,7 @@ pub trait GenericPagePerm: Clone + Copy + Display + Send + Sync + bitflags::Flag
     const X: Self;
     const U: Self;
     const G: Self;
+    const COW: Self;
 }
 
 pub trait GenericPageTableEntry<P: GenericPagePerm>:
@@ -39,6 +40,23 @@ pub trait GenericPageTableEntry<P: GenericPagePerm>:
     fn clr(&mut self);
 }
 
+/// Translations changed by a page table operation, which must be flushed from the TLB before the
+/// frames and tables they point to are reused.
+#[derive(Debug, Default)]
+pub struct TlbFlush {
+    /// The address of every valid leaf changed or removed.
+    pub addrs: Vec<VirtAddr>,
+    /// Whether tables have been freed, whose entries may be cached on the walk to any address, so
+    /// that only a full flush drops them.
+    pub tables_freed: bool,
+}
+
+impl TlbFlush {
+    pub fn is_empty(&self) -> bool {
+        self.addrs.is_empty() && !self.tables_freed
+    }
+}
+
 pub(crate) trait CloneKernel {
     fn clone_kernel(dst: &mut [usize]);
 }
@@ -54,7 +72,42 @@ where
 
     fn lookup(&self, addr: VirtAddr) -> Result<(Arc<PhysFrame>, P)>;
 
+    /// Returns the size of the leaf mapping `addr`.
+    fn leaf_size(&self, addr: VirtAddr) -> Result<PageSize>;
+
     fn map(&mut self, addr: VirtAddr, phys_frame: Arc<PhysFrame>, perm: P) -> Result<()>;
 
-    fn unmap(&mut self, addr: VirtAddr) -> Result<()>;
+    /// Maps `phys_frame` at `addr` with a single leaf of `size`, both of which must be aligned to
+    /// `size`. Mappings already in `[addr, addr + size)` are replaced.
+    fn map_sized(
+        &mut self,
+        addr: VirtAddr,
+        phys_frame: Arc<PhysFrame>,
+        size: PageSize,
+        perm: P,
+    ) -> Result<()>;
+
+    /// Maps `phys_frames` at consecutive pages from `addr`, either all of them or none, and returns
+    /// the previous mappings to flush from the TLB.
+    fn map_range(
+        &mut self,
+        addr: VirtAddr,
+        phys_frames: &[Arc<PhysFrame>],
+        perm: P,
+    ) -> Result<TlbFlush>;
+
+    /// Unmaps the page at `addr`, splitting the huge page it belongs to if any, and returns the
+    /// mappings to flush from the TLB.
+    fn unmap(&mut self, addr: VirtAddr) -> Result<TlbFlush>;
+
+    /// Unmaps whatever is mapped in `[addr, addr + len)`, frees the tables left empty, and returns
+    /// the mappings to flush from the TLB.
+    fn unmap_range(&mut self, addr: VirtAddr, len: usize) -> Result<TlbFlush>;
+
+    /// Changes the permission of the page at `addr`, splitting the huge page it belongs to if any.
+    fn protect(&mut self, addr: VirtAddr, perm: P) -> Result<()>;
+
+    /// Changes the permission of every page in `[addr, addr + len)`, which must all be mapped, and
+    /// returns the mappings to flush from the TLB. No permission is changed if it fails.
+    fn protect_range(&mut self, addr: VirtAddr, len: usize, perm: P) -> Result<TlbFlush>;
 }
@@ -7,6 +7,7 @@ use core::alloc::{Allocator, Layout};
 
 use alloc::alloc::Global;
 use jrinx_config::PAGE_SIZE;
+use jrinx_error::{InternalError, Result};
 use jrinx_layout::{_epercpu, _spercpu};
 use spin::{Lazy, Once};
 
@@ -31,15 +32,19 @@ pub fn local_area_base(cpu_id: usize) -> usize {
 }
 
 pub fn init(nproc: usize) {
+    try_init(nproc).unwrap();
+}
+
+pub fn try_init(nproc: usize) -> Result<()> {
     let total_size = local_area_size() * nproc;
-    let layout = Layout::from_size_align(total_size, PAGE_SIZE).unwrap();
-    GLOBAL_AREA_BASE.call_once(|| {
+    let layout =
+        Layout::from_size_align(total_size, PAGE_SIZE).map_err(|_| InternalError::NotEnoughMem)?;
+    GLOBAL_AREA_BASE.try_call_once(|| {
         Global
             .allocate_zeroed(layout)
-            .unwrap()
-            .cast::<usize>()
-            .as_ptr() as usize
-    });
+            .map(|area| area.cast::<usize>().as_ptr() as usize)
+            .map_err(|_| InternalError::NotEnoughMem)
+    })?;
 
     let origin = _spercpu() as *const u8;
     for i in 0..nproc {
@@ -48,4 +53,5 @@ pub fn init(nproc: usize) {
             core::ptr::copy_nonoverlapping(origin, target, local_area_size());
         }
     }
+    Ok(())
 }
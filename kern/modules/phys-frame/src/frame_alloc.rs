@@ -0,0 +1,293 @@
+//! Page frames of RAM, allocated from a bitmap per memory region apart from the kernel heap.
+//!
+//! The bitmap of a region lives in its first pages and is reached through the linear mapping, so
+//! the allocator never allocates from the heap, which in turn grows by taking frames from here.
+//! A few frames are held back for the heap, so that it can still grow once ordinary allocations
+//! have run out of frames. The frames the heap takes from them are not held back again.
+
+use core::{
+    alloc::{AllocError, Allocator, Layout},
+    mem::size_of,
+    ptr::NonNull,
+    slice,
+};
+
+use jrinx_addr::{PhysAddr, VirtAddr};
+use jrinx_config::{HEAP_RESERVE_SIZE, PAGE_SIZE};
+use jrinx_error::{InternalError, Result};
+use spin::Mutex;
+
+const MAX_ZONES: usize = 16;
+const BITS: usize = u64::BITS as usize;
+
+static FRAME_ALLOCATOR: Mutex<FrameAllocatorInner> = Mutex::new(FrameAllocatorInner::new());
+
+/// Statistics of the frame allocator, in pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    pub total: usize,
+    pub free: usize,
+}
+
+impl FrameStats {
+    pub fn used(&self) -> usize {
+        self.total - self.free
+    }
+}
+
+/// Allocates whole frames through the [`Allocator`] interface, e.g. for [`PhysFrame`].
+///
+/// [`PhysFrame`]: crate::PhysFrame
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameAllocator;
+
+unsafe impl Allocator for FrameAllocator {
+    fn allocate(&self, layout: Layout) -> core::result::Result<NonNull<[u8]>, AllocError> {
+        let pages = layout.size().max(1).div_ceil(PAGE_SIZE);
+        let addr = alloc(pages, layout.align()).map_err(|_| AllocError)?;
+        let ptr = addr.to_virt().as_usize() as *mut u8;
+        Ok(NonNull::slice_from_raw_parts(
+            NonNull::new(ptr).ok_or(AllocError)?,
+            pages * PAGE_SIZE,
+        ))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let pages = layout.size().max(1).div_ceil(PAGE_SIZE);
+        dealloc(VirtAddr::new(ptr.as_ptr() as usize).to_phys(), pages);
+    }
+}
+
+/// Hands the RAM at `[addr, addr + len)` over to the allocator, the part not page-aligned is left
+/// out.
+pub fn add_region(addr: PhysAddr, len: usize) {
+    let start = addr.as_usize().next_multiple_of(PAGE_SIZE);
+    let end = (addr.as_usize() + len) & !(PAGE_SIZE - 1);
+    if start >= end {
+        return;
+    }
+    FRAME_ALLOCATOR.lock().add_zone(start, end);
+}
+
+/// Allocates `pages` contiguous frames whose address is aligned to `align` bytes, leaving the
+/// frames held back for the heap alone.
+pub fn alloc(pages: usize, align: usize) -> Result<PhysAddr> {
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    let reserve = allocator.reserve;
+    allocator.alloc(pages, align, reserve)
+}
+
+/// Allocates like [`alloc`], preferring the frames from the `from`-th page on, counting the pages
+/// of every region in order, to those below.
+pub fn alloc_from(pages: usize, align: usize, from: usize) -> Result<PhysAddr> {
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    let reserve = allocator.reserve;
+    allocator
+        .alloc_from(pages, align, reserve, from)
+        .or_else(|_| allocator.alloc(pages, align, reserve))
+}
+
+/// Allocates `pages` contiguous frames for the heap, which may take the frames held back for it.
+pub fn alloc_for_heap(pages: usize) -> Result<PhysAddr> {
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    let addr = allocator.alloc(pages, PAGE_SIZE, 0)?;
+    allocator.reserve = allocator.reserve.min(allocator.free());
+    Ok(addr)
+}
+
+/// Gives back `pages` frames at `addr`.
+///
+/// # Safety
+///
+/// The frames must have been allocated together by [`alloc`] and must no longer be used.
+pub unsafe fn dealloc(addr: PhysAddr, pages: usize) {
+    FRAME_ALLOCATOR.lock().dealloc(addr, pages);
+}
+
+pub fn stats() -> FrameStats {
+    let allocator = FRAME_ALLOCATOR.lock();
+    allocator
+        .zones()
+        .fold(FrameStats { total: 0, free: 0 }, |stats, zone| FrameStats {
+            total: stats.total + zone.pages,
+            free: stats.free + zone.free,
+        })
+}
+
+struct FrameAllocatorInner {
+    zones: [Option<Zone>; MAX_ZONES],
+    /// Free frames only the heap may take.
+    reserve: usize,
+}
+
+impl FrameAllocatorInner {
+    const fn new() -> Self {
+        const NONE: Option<Zone> = None;
+        Self {
+            zones: [NONE; MAX_ZONES],
+            reserve: HEAP_RESERVE_SIZE / PAGE_SIZE,
+        }
+    }
+
+    fn zones(&self) -> impl Iterator<Item = &Zone> {
+        self.zones.iter().flatten()
+    }
+
+    fn free(&self) -> usize {
+        self.zones().map(|zone| zone.free).sum()
+    }
+
+    fn add_zone(&mut self, start: usize, end: usize) {
+        let Some(slot) = self.zones.iter_mut().find(|zone| zone.is_none()) else {
+            return;
+        };
+        *slot = Zone::new(start, end);
+    }
+
+    fn alloc(&mut self, pages: usize, align: usize, reserve: usize) -> Result<PhysAddr> {
+        self.alloc_from(pages, align, reserve, 0)
+    }
+
+    /// Allocates from the `from`-th page on, counting the pages of every zone in order.
+    fn alloc_from(
+        &mut self,
+        pages: usize,
+        align: usize,
+        reserve: usize,
+        mut from: usize,
+    ) -> Result<PhysAddr> {
+        if pages == 0 || !align.is_power_of_two() {
+            return Err(InternalError::InvalidParam);
+        }
+        if self.free() < pages + reserve {
+            return Err(InternalError::NotEnoughMem);
+        }
+        let align = align.max(PAGE_SIZE) / PAGE_SIZE;
+        self.zones
+            .iter_mut()
+            .flatten()
+            .find_map(|zone| {
+                let start = from;
+                from = from.saturating_sub(zone.pages);
+                (start < zone.pages)
+                    .then(|| zone.alloc(pages, align, start))
+                    .flatten()
+            })
+            .ok_or(InternalError::NotEnoughMem)
+    }
+
+    fn dealloc(&mut self, addr: PhysAddr, pages: usize) {
+        let zone = self
+            .zones
+            .iter_mut()
+            .flatten()
+            .find(|zone| zone.contains(addr))
+            .expect("frames not allocated by the frame allocator");
+        zone.dealloc(addr, pages);
+    }
+}
+
+/// A region of RAM, with a bit per page set while the page is allocated.
+struct Zone {
+    base: usize,
+    pages: usize,
+    free: usize,
+    /// Every page below is allocated.
+    hint: usize,
+    bitmap: &'static mut [u64],
+}
+
+impl Zone {
+    fn new(start: usize, end: usize) -> Option<Self> {
+        let total = (end - start) / PAGE_SIZE;
+        let words = total.div_ceil(BITS);
+        let bitmap_pages = (words * size_of::<u64>()).div_ceil(PAGE_SIZE);
+        if bitmap_pages >= total {
+            return None;
+        }
+        let bitmap = unsafe {
+            slice::from_raw_parts_mut(PhysAddr::new(start).to_virt().as_usize() as *mut u64, words)
+        };
+        bitmap.fill(0);
+        let pages = total - bitmap_pages;
+        Some(Self {
+            base: start + bitmap_pages * PAGE_SIZE,
+            pages,
+            free: pages,
+            hint: 0,
+            bitmap,
+        })
+    }
+
+    fn contains(&self, addr: PhysAddr) -> bool {
+        (self.base..self.base + self.pages * PAGE_SIZE).contains(&addr.as_usize())
+    }
+
+    /// Allocates from the `from`-th page on.
+    fn alloc(&mut self, pages: usize, align: usize, from: usize) -> Option<PhysAddr> {
+        if self.free < pages {
+            return None;
+        }
+        let mut start = self.align_up(self.next_free(self.hint.max(from))?, align);
+        while start + pages <= self.pages {
+            match (start..start + pages).find(|&index| self.is_used(index)) {
+                None => {
+                    (start..start + pages).for_each(|index| self.set_used(index, true));
+                    self.free -= pages;
+                    if start == self.hint {
+                        self.hint = self.next_free(start + pages).unwrap_or(self.pages);
+                    }
+                    return Some(PhysAddr::new(self.base + start * PAGE_SIZE));
+                }
+                Some(used) => start = self.align_up(self.next_free(used + 1)?, align),
+            }
+        }
+        None
+    }
+
+    fn dealloc(&mut self, addr: PhysAddr, pages: usize) {
+        let start = (addr.as_usize() - self.base) / PAGE_SIZE;
+        for index in start..start + pages {
+            assert!(
+                self.is_used(index),
+                "frame {:#x} freed twice",
+                addr.as_usize()
+            );
+            self.set_used(index, false);
+        }
+        self.free += pages;
+        self.hint = self.hint.min(start);
+    }
+
+    /// Returns the first free page at or after `index`, skipping whole words of allocated pages.
+    fn next_free(&self, mut index: usize) -> Option<usize> {
+        while index < self.pages {
+            if index % BITS == 0 && self.bitmap[index / BITS] == u64::MAX {
+                index += BITS;
+            } else if self.is_used(index) {
+                index += 1;
+            } else {
+                return Some(index);
+            }
+        }
+        None
+    }
+
+    /// Aligns `index` up so that the address of the page is a multiple of `align` pages.
+    fn align_up(&self, index: usize, align: usize) -> usize {
+        let pfn = self.base / PAGE_SIZE + index;
+        pfn.next_multiple_of(align) - self.base / PAGE_SIZE
+    }
+
+    fn is_used(&self, index: usize) -> bool {
+        self.bitmap[index / BITS] & 1 << (index % BITS) != 0
+    }
+
+    fn set_used(&mut self, index: usize, used: bool) {
+        if used {
+            self.bitmap[index / BITS] |= 1 << (index % BITS);
+        } else {
+            self.bitmap[index / BITS] &= !(1 << (index % BITS));
+        }
+    }
+}
@@ -4,20 +4,24 @@
 
 extern crate alloc;
 
-use alloc::{alloc::Global, sync::Arc};
+pub mod frame_alloc;
+
+use alloc::sync::Arc;
 use jrinx_addr::{PhysAddr, VirtAddr};
 use jrinx_error::{InternalError, Result};
 
 use core::{
-    alloc::{Allocator, Layout},
+    alloc::{AllocError, Allocator, Layout},
     fmt::Debug,
     ptr::NonNull,
 };
+use frame_alloc::FrameAllocator;
 
 pub trait PhysFrameAllocator = Allocator + Send + Sync + 'static;
 
 pub struct PhysFrame {
     addr: PhysAddr,
+    size: usize,
     alloc: Arc<dyn PhysFrameAllocator>,
 }
 
@@ -59,7 +63,7 @@ impl Drop for PhysFrame {
         unsafe {
             self.alloc.deallocate(
                 NonNull::new(self.addr().to_virt().as_usize() as *mut u8).unwrap(),
-                PHYS_FRAME_MEMORY_LAYOUT,
+                Layout::from_size_align_unchecked(self.size, self.size),
             );
         }
     }
@@ -67,24 +71,80 @@ impl Drop for PhysFrame {
 
 impl PhysFrame {
     pub fn alloc() -> Result<Arc<Self>> {
-        Self::alloc_in(Global)
+        Self::alloc_in(FrameAllocator)
     }
 
     pub fn alloc_in(alloc: impl PhysFrameAllocator) -> Result<Arc<Self>> {
-        let addr: NonNull<u8> =
-            core::hint::black_box(alloc.allocate_zeroed(PHYS_FRAME_MEMORY_LAYOUT))
-                .map_err(|_| InternalError::NotEnoughMem)?
-                .cast();
+        Self::alloc_layout(alloc, PHYS_FRAME_MEMORY_LAYOUT)
+    }
+
+    /// Allocates a block of contiguous frames, e.g. for a huge page, whose `size` is a power of two
+    /// no smaller than a page and which is aligned to `size`.
+    pub fn alloc_block(size: usize) -> Result<Arc<Self>> {
+        if !size.is_power_of_two() || size < jrinx_config::PAGE_SIZE {
+            return Err(InternalError::InvalidParam);
+        }
+        let layout =
+            Layout::from_size_align(size, size).map_err(|_| InternalError::InvalidParam)?;
+        Self::alloc_layout(FrameAllocator, layout)
+    }
+
+    fn alloc_layout(alloc: impl PhysFrameAllocator, layout: Layout) -> Result<Arc<Self>> {
+        let addr: NonNull<u8> = core::hint::black_box(alloc.allocate_zeroed(layout))
+            .map_err(|_| InternalError::NotEnoughMem)?
+            .cast();
 
         let frame = Self {
             addr: VirtAddr::new(addr.as_ptr() as usize).to_phys(),
+            size: layout.size(),
             alloc: Arc::new(alloc),
         };
 
         Ok(Arc::new(frame))
     }
 
+    /// Wraps the frame at `addr` which is not RAM, e.g. a page of device registers, it is never
+    /// given back to any allocator.
+    pub fn device(addr: PhysAddr) -> Arc<Self> {
+        Arc::new(Self {
+            addr,
+            size: jrinx_config::PAGE_SIZE,
+            alloc: Arc::new(DeviceMemory),
+        })
+    }
+
     pub fn addr(&self) -> PhysAddr {
         self.addr
     }
+
+    /// Returns the size of the frame, which is larger than a page for a block.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn duplicate(&self) -> Result<Arc<Self>> {
+        let frame = if self.size == jrinx_config::PAGE_SIZE {
+            Self::alloc()?
+        } else {
+            Self::alloc_block(self.size)?
+        };
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                self.addr.to_virt().as_usize() as *const u8,
+                frame.addr.to_virt().as_usize() as *mut u8,
+                self.size,
+            );
+        }
+        Ok(frame)
+    }
+}
+
+struct DeviceMemory;
+
+unsafe impl Allocator for DeviceMemory {
+    fn allocate(&self, _: Layout) -> core::result::Result<NonNull<[u8]>, AllocError> {
+        Err(AllocError)
+    }
+
+    unsafe fn deallocate(&self, _: NonNull<u8>, _: Layout) {}
 }
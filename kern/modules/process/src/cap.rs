@@ -0,0 +1,167 @@
+//! Capability handles, the only way for a process to reach a kernel object.
+//!
+//! Every process owns a handle table mapping small integers to kernel objects, each together with
+//! the rights the process holds on it. Handles can be duplicated with fewer rights and moved to
+//! another process in an IPC message, but never gain rights.
+
+use alloc::{sync::Arc, vec::Vec};
+
+use bitflags::bitflags;
+use jrinx_driver::irq::user_irq::IrqLine;
+use jrinx_error::{InternalError, Result};
+use jrinx_vmm::shm::SharedMemoryRef;
+
+use crate::{ipc::Endpoint, timer::Timer, Process};
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Rights: usize {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const MAP = 1 << 2;
+        const SIGNAL = 1 << 3;
+    }
+}
+
+/// Handle 0 never refers to an object, so that it can stand for "no handle" in system calls.
+pub const HANDLE_NONE: usize = 0;
+
+#[derive(Clone)]
+pub enum KernelObject {
+    Process(Arc<Process>),
+    Endpoint(Arc<Endpoint>),
+    Memory(SharedMemoryRef),
+    Irq(Arc<IrqLine>),
+    Timer(Arc<Timer>),
+}
+
+impl KernelObject {
+    pub fn process(self) -> Result<Arc<Process>> {
+        match self {
+            Self::Process(process) => Ok(process),
+            _ => Err(InternalError::InvalidHandle),
+        }
+    }
+
+    pub fn endpoint(self) -> Result<Arc<Endpoint>> {
+        match self {
+            Self::Endpoint(endpoint) => Ok(endpoint),
+            _ => Err(InternalError::InvalidHandle),
+        }
+    }
+
+    pub fn memory(self) -> Result<SharedMemoryRef> {
+        match self {
+            Self::Memory(shm) => Ok(shm),
+            _ => Err(InternalError::InvalidHandle),
+        }
+    }
+
+    pub fn irq(self) -> Result<Arc<IrqLine>> {
+        match self {
+            Self::Irq(irq) => Ok(irq),
+            _ => Err(InternalError::InvalidHandle),
+        }
+    }
+
+    pub fn timer(self) -> Result<Arc<Timer>> {
+        match self {
+            Self::Timer(timer) => Ok(timer),
+            _ => Err(InternalError::InvalidHandle),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Handle {
+    object: KernelObject,
+    rights: Rights,
+}
+
+impl Handle {
+    pub fn new(object: KernelObject, rights: Rights) -> Self {
+        Self { object, rights }
+    }
+
+    pub fn object(&self) -> &KernelObject {
+        &self.object
+    }
+
+    pub fn rights(&self) -> Rights {
+        self.rights
+    }
+
+    /// Returns the object if the handle holds all of `rights` on it.
+    pub fn check(&self, rights: Rights) -> Result<&KernelObject> {
+        if self.rights.contains(rights) {
+            Ok(&self.object)
+        } else {
+            Err(InternalError::InsufficientRights)
+        }
+    }
+
+    /// Creates another handle to the same object with `rights`, which must be a subset of the
+    /// rights of this handle.
+    pub fn duplicate(&self, rights: Rights) -> Result<Self> {
+        self.check(rights)?;
+        Ok(Self::new(self.object.clone(), rights))
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct HandleTable {
+    handles: Vec<Option<Handle>>,
+}
+
+impl HandleTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, handle: usize) -> Result<&Handle> {
+        handle
+            .checked_sub(1)
+            .and_then(|index| self.handles.get(index))
+            .and_then(Option::as_ref)
+            .ok_or(InternalError::InvalidHandle)
+    }
+
+    /// Returns the object behind `handle` if it holds all of `rights` on it.
+    pub fn object(&self, handle: usize, rights: Rights) -> Result<KernelObject> {
+        self.get(handle)?.check(rights).cloned()
+    }
+
+    /// Installs `handle` at the lowest free slot and returns its number.
+    pub fn insert(&mut self, handle: Handle) -> usize {
+        let index = match self.handles.iter().position(Option::is_none) {
+            Some(index) => {
+                self.handles[index] = Some(handle);
+                index
+            }
+            None => {
+                self.handles.push(Some(handle));
+                self.handles.len() - 1
+            }
+        };
+        index + 1
+    }
+
+    /// Removes `handle` from the table and returns it, e.g. to move it to another process.
+    pub fn remove(&mut self, handle: usize) -> Result<Handle> {
+        let handle = handle
+            .checked_sub(1)
+            .and_then(|index| self.handles.get_mut(index))
+            .and_then(Option::take)
+            .ok_or(InternalError::InvalidHandle)?;
+        while let Some(None) = self.handles.last() {
+            self.handles.pop();
+        }
+        Ok(handle)
+    }
+
+    /// Duplicates `handle` with `rights` into the lowest free slot.
+    pub fn duplicate(&mut self, handle: usize, rights: Rights) -> Result<usize> {
+        let handle = self.get(handle)?.duplicate(rights)?;
+        Ok(self.insert(handle))
+    }
+}
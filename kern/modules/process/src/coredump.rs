@@ -0,0 +1,394 @@
+//! ELF core files of processes killed by a page fault or an illegal instruction, for loading into
+//! gdb on the host along with the program that crashed.
+//!
+//! A core file holds the registers and the faulting address in its notes, followed by the contents
+//! of the pages mapped in every memory area, pages never touched are left out. It is written to
+//! the console as base64 lines tagged with the process id, which `sed -n 's/^CORE 1 //p' |
+//! base64 -d` turns back into one for process 1, unless a block device is configured by
+//! [`set_target`], whose image from the given block on is then a core file as is.
+
+use alloc::{format, string::String, vec, vec::Vec};
+use core::mem::size_of;
+
+use elf::abi::{ELFDATA2LSB, EM_RISCV, ET_CORE, EV_CURRENT, PF_R, PF_W, PF_X, PT_LOAD, PT_NOTE};
+use jrinx_addr::VirtAddr;
+use jrinx_config::PAGE_SIZE;
+use jrinx_driver::BlockDevice;
+use jrinx_error::{InternalError, Result};
+use jrinx_hal::{hal, Earlycon, Hal, Interrupt};
+use jrinx_paging::{GenericPagePerm, GenericPageTable, PagePerm};
+use jrinx_trap::{arch::Context, GenericContext, TrapReason};
+use jrinx_util::base64;
+use jrinx_vmm::addr_space::VirtMemArea;
+use spin::Mutex;
+
+use crate::{
+    signal::{SIGILL, SIGSEGV},
+    Process,
+};
+
+pub const NT_PRSTATUS: u32 = 1;
+pub const NT_SIGINFO: u32 = 0x53494749;
+
+const SEGV_MAPERR: u32 = 1;
+const SEGV_ACCERR: u32 = 2;
+const ILL_ILLOPC: u32 = 1;
+
+const SIGINFO_SIZE: usize = 128;
+const WORD_SIZE: usize = size_of::<usize>();
+
+#[cfg(target_pointer_width = "64")]
+const ELF_CLASS: u8 = elf::abi::ELFCLASS64;
+#[cfg(target_pointer_width = "32")]
+const ELF_CLASS: u8 = elf::abi::ELFCLASS32;
+
+#[cfg(target_pointer_width = "64")]
+const EHDR_SIZE: usize = 64;
+#[cfg(target_pointer_width = "32")]
+const EHDR_SIZE: usize = 52;
+
+#[cfg(target_pointer_width = "64")]
+const PHDR_SIZE: usize = 56;
+#[cfg(target_pointer_width = "32")]
+const PHDR_SIZE: usize = 32;
+
+// compressed instructions, along with double-precision floats on rv64
+#[cfg(target_pointer_width = "64")]
+const ELF_FLAGS: u32 = 0x1 | 0x4;
+#[cfg(target_pointer_width = "32")]
+const ELF_FLAGS: u32 = 0x1;
+
+/// Bytes encoded per line on the console, i.e. 76 characters.
+const CONSOLE_LINE_LEN: usize = 57;
+
+/// The largest core file written, beyond which a crash leaves none.
+const CORE_SIZE_MAX: usize = 16 << 20;
+
+static TARGET: Mutex<CoreTarget> = Mutex::new(CoreTarget::Console);
+
+/// Where core files are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoreTarget {
+    Console,
+    /// The blocks from `block` on of block device `dev`, counting in the order they are probed,
+    /// whose contents are overwritten.
+    Block {
+        dev: usize,
+        block: usize,
+    },
+}
+
+/// A run of pages of a memory area, which are either all mapped or all left out of the core.
+struct Segment {
+    start: VirtAddr,
+    end: VirtAddr,
+    perm: PagePerm,
+    dumped: bool,
+}
+
+/// Sets where core files are written, the console unless a block device is chosen explicitly.
+pub fn set_target(target: CoreTarget) {
+    *TARGET.lock() = target;
+}
+
+/// Builds the core file of `process`, which trapped with `ctx` for `reason`.
+pub fn build(process: &Process, ctx: &Context, reason: TrapReason) -> Result<Vec<u8>> {
+    let (sig, code, addr) = match reason {
+        TrapReason::PageFault { addr, .. } => {
+            let code = if process.with_addr_space(|addr_space| addr_space.area(addr).is_some()) {
+                SEGV_ACCERR
+            } else {
+                SEGV_MAPERR
+            };
+            (SIGSEGV, code, addr.as_usize())
+        }
+        TrapReason::Unknown { .. } => (SIGILL, ILL_ILLOPC, ctx.pc()),
+        _ => return Err(InternalError::InvalidParam),
+    };
+
+    let mut notes = CoreWriter::default();
+    notes.note(NT_PRSTATUS, &prstatus(process, ctx, sig, code));
+    notes.note(NT_SIGINFO, &siginfo(sig, code, addr));
+    let notes = notes.buf;
+
+    process.with_addr_space(|addr_space| {
+        let mut segments: Vec<Segment> = Vec::new();
+        for area in addr_space.areas() {
+            let mut page = area.start();
+            while page < area.end() {
+                // pages never touched are not mapped yet, gdb reads them as zeros
+                let dumped = is_dumpable(area) && addr_space.page_table().translate(page).is_ok();
+                match segments.last_mut() {
+                    Some(last)
+                        if last.end == page
+                            && last.dumped == dumped
+                            && last.perm == area.perm() =>
+                    {
+                        last.end = page + PAGE_SIZE;
+                    }
+                    _ => segments.push(Segment {
+                        start: page,
+                        end: page + PAGE_SIZE,
+                        perm: area.perm(),
+                        dumped,
+                    }),
+                }
+                page = page + PAGE_SIZE;
+            }
+        }
+
+        let phnum = 1 + segments.len();
+        let notes_offset = EHDR_SIZE + phnum * PHDR_SIZE;
+        let data_offset = (notes_offset + notes.len()).next_multiple_of(PAGE_SIZE);
+        let size = segments
+            .iter()
+            .filter(|segment| segment.dumped)
+            .fold(data_offset, |size, segment| {
+                size + (segment.end - segment.start)
+            });
+        if phnum > u16::MAX as usize || size > CORE_SIZE_MAX {
+            return Err(InternalError::NotEnoughMem);
+        }
+
+        let mut core = CoreWriter::default();
+        core.buf
+            .try_reserve_exact(size)
+            .map_err(|_| InternalError::NotEnoughMem)?;
+        core.ehdr(phnum);
+        core.phdr(PT_NOTE, 0, notes_offset, 0, notes.len(), 0, 4);
+        let mut offset = data_offset;
+        for segment in &segments {
+            let len = segment.end - segment.start;
+            let filesz = if segment.dumped { len } else { 0 };
+            core.phdr(
+                PT_LOAD,
+                segment_flags(segment.perm),
+                offset,
+                segment.start.as_usize(),
+                filesz,
+                len,
+                PAGE_SIZE,
+            );
+            offset += filesz;
+        }
+        core.put(&notes);
+
+        core.align(PAGE_SIZE);
+        for segment in segments.iter().filter(|segment| segment.dumped) {
+            let start = core.buf.len();
+            core.buf.resize(start + (segment.end - segment.start), 0);
+            addr_space.read_bytes(segment.start, &mut core.buf[start..])?;
+        }
+        Ok(core.buf)
+    })
+}
+
+/// Writes the core file of `process` if `reason` is a crash, to the block device set by
+/// [`set_target`] if the file fits there, or else to the console, and returns where it went.
+pub fn dump(process: &Process, ctx: &Context, reason: TrapReason) -> Result<CoreTarget> {
+    let core = build(process, ctx, reason)?;
+
+    let target = *TARGET.lock();
+    if let CoreTarget::Block { dev, block } = target {
+        let result = jrinx_driver::blk::get(dev)
+            .ok_or(InternalError::DevProbeError)
+            .and_then(|blk| write_blocks(&*blk, block, &core));
+        match result {
+            Ok(()) => {
+                info!(
+                    "core of process {} written to block device #{} from block {}, {} bytes",
+                    process.id(),
+                    dev,
+                    block,
+                    core.len()
+                );
+                return Ok(target);
+            }
+            Err(err) => warn!("failed to write core to block device #{}: {:?}", dev, err),
+        }
+    }
+
+    info!(
+        "core of process {} follows in base64, {} bytes",
+        process.id(),
+        core.len()
+    );
+    // a line at a time, so that interrupts are not held off for the whole file
+    let mut line = String::new();
+    for chunk in core.chunks(CONSOLE_LINE_LEN) {
+        line.clear();
+        line.push_str(&format!("CORE {} ", process.id()));
+        base64::encode_into(chunk, &mut line);
+        line.push('\n');
+        hal!().interrupt().with_saved_off(|| write_console(&line));
+    }
+    Ok(CoreTarget::Console)
+}
+
+fn write_blocks(dev: &dyn BlockDevice, block: usize, core: &[u8]) -> Result<()> {
+    let block_size = dev.block_size();
+    if block + core.len().div_ceil(block_size) > dev.num_blocks() {
+        return Err(InternalError::NotEnoughMem);
+    }
+    let (full, rest) = core.split_at(core.len() / block_size * block_size);
+    if !full.is_empty() {
+        dev.write_blocks(block, full)?;
+    }
+    if !rest.is_empty() {
+        let mut last = vec![0; block_size];
+        last[..rest.len()].copy_from_slice(rest);
+        dev.write_blocks(block + full.len() / block_size, &last)?;
+    }
+    Ok(())
+}
+
+fn write_console(s: &str) {
+    for b in s.bytes() {
+        hal!().earlycon().putc(b);
+    }
+}
+
+/// Returns the `elf_prstatus` of the crash, whose `pr_reg` is what gdb reads the registers from.
+fn prstatus(process: &Process, ctx: &Context, sig: usize, code: u32) -> Vec<u8> {
+    let mut desc = CoreWriter::default();
+    // pr_info, pr_cursig
+    desc.put_u32(sig as u32);
+    desc.put_u32(code);
+    desc.put_u32(0);
+    desc.put_u16(sig as u16);
+    desc.align(WORD_SIZE);
+    // pr_sigpend, pr_sighold
+    desc.put_word(0);
+    desc.put_word(0);
+    // pr_pid, pr_ppid, pr_pgrp, pr_sid
+    desc.put_u32(usize::from(process.id()) as u32);
+    desc.put_u32(
+        process
+            .parent()
+            .map_or(0, |parent| usize::from(parent) as u32),
+    );
+    desc.put_u32(0);
+    desc.put_u32(0);
+    // pr_utime, pr_stime, pr_cutime, pr_cstime
+    for _ in 0..8 {
+        desc.put_word(0);
+    }
+    for reg in ctx.gregs() {
+        desc.put_word(reg);
+    }
+    // pr_fpvalid
+    desc.put_u32(0);
+    desc.align(WORD_SIZE);
+    desc.buf
+}
+
+/// Returns the `siginfo_t` of the crash, which gdb shows as `$_siginfo`.
+fn siginfo(sig: usize, code: u32, addr: usize) -> Vec<u8> {
+    let mut desc = CoreWriter::default();
+    desc.put_u32(sig as u32);
+    desc.put_u32(0);
+    desc.put_u32(code);
+    desc.align(WORD_SIZE);
+    desc.put_word(addr);
+    desc.buf.resize(SIGINFO_SIZE, 0);
+    desc.buf
+}
+
+/// Device registers are left out along with areas the process cannot read.
+fn is_dumpable(area: &VirtMemArea) -> bool {
+    !area.device() && area.perm().contains(PagePerm::R)
+}
+
+fn segment_flags(perm: PagePerm) -> u32 {
+    [
+        (PagePerm::R, PF_R),
+        (PagePerm::W, PF_W),
+        (PagePerm::X, PF_X),
+    ]
+    .into_iter()
+    .filter(|&(page_perm, _)| perm.contains(page_perm))
+    .fold(0, |flags, (_, flag)| flags | flag)
+}
+
+#[derive(Default)]
+struct CoreWriter {
+    buf: Vec<u8>,
+}
+
+impl CoreWriter {
+    fn put(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    fn put_u16(&mut self, value: u16) {
+        self.put(&value.to_le_bytes());
+    }
+
+    fn put_u32(&mut self, value: u32) {
+        self.put(&value.to_le_bytes());
+    }
+
+    fn put_word(&mut self, value: usize) {
+        self.put(&value.to_le_bytes());
+    }
+
+    fn align(&mut self, align: usize) {
+        self.buf.resize(self.buf.len().next_multiple_of(align), 0);
+    }
+
+    fn ehdr(&mut self, phnum: usize) {
+        self.put(b"\x7fELF");
+        self.put(&[ELF_CLASS, ELFDATA2LSB, EV_CURRENT]);
+        self.align(16);
+        self.put_u16(ET_CORE);
+        self.put_u16(EM_RISCV);
+        self.put_u32(EV_CURRENT as u32);
+        // e_entry, e_phoff, e_shoff
+        self.put_word(0);
+        self.put_word(EHDR_SIZE);
+        self.put_word(0);
+        self.put_u32(ELF_FLAGS);
+        self.put_u16(EHDR_SIZE as u16);
+        self.put_u16(PHDR_SIZE as u16);
+        self.put_u16(phnum as u16);
+        // e_shentsize, e_shnum, e_shstrndx
+        self.put_u16(0);
+        self.put_u16(0);
+        self.put_u16(0);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn phdr(
+        &mut self,
+        p_type: u32,
+        flags: u32,
+        offset: usize,
+        vaddr: usize,
+        filesz: usize,
+        memsz: usize,
+        align: usize,
+    ) {
+        self.put_u32(p_type);
+        #[cfg(target_pointer_width = "64")]
+        self.put_u32(flags);
+        self.put_word(offset);
+        self.put_word(vaddr);
+        self.put_word(vaddr);
+        self.put_word(filesz);
+        self.put_word(memsz);
+        #[cfg(target_pointer_width = "32")]
+        self.put_u32(flags);
+        self.put_word(align);
+    }
+
+    fn note(&mut self, n_type: u32, desc: &[u8]) {
+        const NAME: &[u8] = b"CORE\0";
+        self.put_u32(NAME.len() as u32);
+        self.put_u32(desc.len() as u32);
+        self.put_u32(n_type);
+        self.put(NAME);
+        self.align(4);
+        self.put(desc);
+        self.align(4);
+    }
+}
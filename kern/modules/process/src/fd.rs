@@ -0,0 +1,249 @@
+//! File descriptors, the uniform I/O interface of processes to pipes, the console and sockets.
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::{
+    future::poll_fn,
+    net::SocketAddr,
+    task::{Poll, Waker},
+    time::Duration,
+};
+
+use jrinx_driver::{
+    smoltcp_impl::{self, tcp::TcpSocket},
+    uart,
+};
+use jrinx_error::{InternalError, Result};
+use jrinx_hal::{hal, Cpu, Earlycon, Hal, Interrupt};
+use jrinx_timed_event::{TimedEvent, TimedEventHandler};
+
+/// How long a task waiting on the console, if no UART interrupt tells when input arrives, waits
+/// before polling it again.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// An object that can be read or written through a file descriptor.
+///
+/// Both operations fail with `WouldBlock` if they cannot make progress yet, after arranging for
+/// `waker`, if any, to be woken once they may.
+pub trait File: Send + Sync {
+    fn read(&self, buf: &mut [u8], waker: Option<&Waker>) -> Result<usize>;
+
+    fn write(&self, buf: &[u8], waker: Option<&Waker>) -> Result<usize>;
+}
+
+#[derive(Clone)]
+pub struct FileDesc {
+    file: Arc<dyn File>,
+    nonblock: bool,
+}
+
+impl FileDesc {
+    pub fn new(file: Arc<dyn File>, nonblock: bool) -> Self {
+        Self { file, nonblock }
+    }
+
+    pub fn file(&self) -> &Arc<dyn File> {
+        &self.file
+    }
+
+    pub fn nonblock(&self) -> bool {
+        self.nonblock
+    }
+
+    /// Reads into `buf`, parking the calling task until any data is available unless the
+    /// descriptor is non-blocking.
+    pub async fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        if self.nonblock {
+            return self.file.read(buf, None);
+        }
+        poll_fn(|cx| match self.file.read(buf, Some(cx.waker())) {
+            Err(InternalError::WouldBlock) => Poll::Pending,
+            result => Poll::Ready(result),
+        })
+        .await
+    }
+
+    /// Writes `buf`, parking the calling task until any of it can be written unless the
+    /// descriptor is non-blocking.
+    pub async fn write(&self, buf: &[u8]) -> Result<usize> {
+        if self.nonblock {
+            return self.file.write(buf, None);
+        }
+        poll_fn(|cx| match self.file.write(buf, Some(cx.waker())) {
+            Err(InternalError::WouldBlock) => Poll::Pending,
+            result => Poll::Ready(result),
+        })
+        .await
+    }
+}
+
+/// The open file descriptors of a process, allocated lowest first.
+#[derive(Clone, Default)]
+pub struct FdTable {
+    files: Vec<Option<FileDesc>>,
+}
+
+impl FdTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a table with the console opened as standard input, output and error.
+    pub fn with_console() -> Self {
+        let console = FileDesc::new(Arc::new(Console), false);
+        Self {
+            files: vec![Some(console); 3],
+        }
+    }
+
+    pub fn get(&self, fd: usize) -> Result<FileDesc> {
+        self.files
+            .get(fd)
+            .and_then(Option::as_ref)
+            .cloned()
+            .ok_or(InternalError::InvalidFileDescriptor)
+    }
+
+    /// Installs `desc` at the lowest free descriptor and returns it.
+    pub fn insert(&mut self, desc: FileDesc) -> usize {
+        if let Some(fd) = self.files.iter().position(Option::is_none) {
+            self.files[fd] = Some(desc);
+            fd
+        } else {
+            self.files.push(Some(desc));
+            self.files.len() - 1
+        }
+    }
+
+    pub fn close(&mut self, fd: usize) -> Result<()> {
+        self.files
+            .get_mut(fd)
+            .and_then(Option::take)
+            .ok_or(InternalError::InvalidFileDescriptor)?;
+        while let Some(None) = self.files.last() {
+            self.files.pop();
+        }
+        Ok(())
+    }
+
+    /// Duplicates `fd` into the lowest free descriptor, both referring to the same file.
+    pub fn dup(&mut self, fd: usize) -> Result<usize> {
+        let desc = self.get(fd)?;
+        Ok(self.insert(desc))
+    }
+}
+
+/// The kernel console, read from its UART, or through the early console if no UART driver takes
+/// its input, and written through the early console.
+pub struct Console;
+
+impl File for Console {
+    fn read(&self, buf: &mut [u8], waker: Option<&Waker>) -> Result<usize> {
+        let irq = uart::has_console();
+        let mut len = 0;
+        while len < buf.len() {
+            let c = if irq {
+                // the reader is only woken if nothing has been read at all
+                uart::getc(waker.filter(|_| len == 0))
+            } else {
+                hal!().earlycon().getc()
+            };
+            let Some(c) = c else {
+                break;
+            };
+            buf[len] = c;
+            len += 1;
+            if c == b'\n' {
+                break;
+            }
+        }
+        if len == 0 && !buf.is_empty() {
+            return if irq {
+                Err(InternalError::WouldBlock)
+            } else {
+                poll_later(waker)
+            };
+        }
+        Ok(len)
+    }
+
+    fn write(&self, buf: &[u8], _waker: Option<&Waker>) -> Result<usize> {
+        for &c in buf {
+            hal!().earlycon().putc(c);
+        }
+        Ok(buf.len())
+    }
+}
+
+/// A TCP socket, connected or connecting, driven in non-blocking mode.
+pub struct Socket(TcpSocket);
+
+impl Socket {
+    pub fn new(socket: TcpSocket) -> Self {
+        socket.set_nonblocking(true);
+        Self(socket)
+    }
+
+    /// Starts connecting a socket to `addr`, which can be read and written once connected.
+    pub fn connect(addr: SocketAddr) -> Result<Self> {
+        if !smoltcp_impl::has_interface() {
+            return Err(InternalError::NetFail);
+        }
+        let socket = Self::new(TcpSocket::new());
+        match socket.0.connect(addr) {
+            Ok(()) | Err(InternalError::WouldBlock) => Ok(socket),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn inner(&self) -> &TcpSocket {
+        &self.0
+    }
+}
+
+/// The network interrupt polls the interfaces, which wakes the wakers registered on sockets.
+impl File for Socket {
+    fn read(&self, buf: &mut [u8], waker: Option<&Waker>) -> Result<usize> {
+        smoltcp_impl::poll_interfaces();
+        // a connecting socket only finds out that it is connected when polled
+        self.0.poll()?;
+        match self.0.recv(buf) {
+            Err(InternalError::WouldBlock) => {
+                if let Some(waker) = waker {
+                    self.0.register_waker(waker, false);
+                }
+                Err(InternalError::WouldBlock)
+            }
+            result => result,
+        }
+    }
+
+    fn write(&self, buf: &[u8], waker: Option<&Waker>) -> Result<usize> {
+        smoltcp_impl::poll_interfaces();
+        self.0.poll()?;
+        match self.0.send(buf) {
+            Err(InternalError::WouldBlock) => {
+                if let Some(waker) = waker {
+                    self.0.register_waker(waker, true);
+                }
+                Err(InternalError::WouldBlock)
+            }
+            result => result,
+        }
+    }
+}
+
+/// Arranges for `waker`, if any, to be woken after [`POLL_INTERVAL`] to poll again, rather than at
+/// once, which would keep the CPU spinning.
+fn poll_later<T>(waker: Option<&Waker>) -> Result<T> {
+    if let Some(waker) = waker.cloned() {
+        let deadline = hal!().cpu().get_time() + POLL_INTERVAL;
+        // timed events fire in interrupt context, which must not find the queue locked
+        hal!().interrupt().with_saved_off(|| {
+            TimedEvent::create(
+                deadline,
+                TimedEventHandler::new(move || waker.wake(), || {}),
+            );
+        });
+    }
+    Err(InternalError::WouldBlock)
+}
@@ -0,0 +1,308 @@
+//! Synchronous rendezvous IPC on kernel endpoints.
+//!
+//! A sender blocks until a receiver takes its message and vice versa; a caller additionally blocks
+//! until the receiver replies. Blocking never spins, the caller's task is parked on a waker and
+//! resumed by its peer. A message may carry a handle, which moves from the sender to the receiver.
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    fmt::Display,
+    future::poll_fn,
+    task::{Poll, Waker},
+};
+
+use jrinx_error::{InternalError, Result};
+use jrinx_serial_id_macro::SerialId;
+use spin::{Mutex, RwLock};
+
+use crate::cap::Handle;
+
+/// Number of message words carried in registers.
+pub const MSG_WORDS: usize = 2;
+
+/// Maximum length of the buffer carried by a message.
+pub const MSG_DATA_MAX: usize = jrinx_config::PAGE_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, SerialId)]
+pub struct EndpointId(u64);
+
+impl Display for EndpointId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<EndpointId> for usize {
+    fn from(value: EndpointId) -> Self {
+        value.0 as usize
+    }
+}
+
+impl From<usize> for EndpointId {
+    fn from(value: usize) -> Self {
+        Self(value as u64)
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct Message {
+    pub badge: usize,
+    pub words: [usize; MSG_WORDS],
+    pub data: Vec<u8>,
+    pub handle: Option<Handle>,
+}
+
+/// The right to answer a call, handed to the receiver of the call. The call fails if it is dropped
+/// without replying, e.g. as the receiver exits.
+pub struct Reply(Arc<Rendezvous<Message>>);
+
+impl Reply {
+    pub fn reply(self, msg: Message) {
+        self.0.fill(msg);
+    }
+}
+
+impl Drop for Reply {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+struct Envelope {
+    msg: Message,
+    reply: Option<Reply>,
+}
+
+/// A single-value slot one side fills or empties while the other side waits for it, until the
+/// filling side closes it.
+struct Rendezvous<T> {
+    inner: Mutex<RendezvousInner<T>>,
+}
+
+struct RendezvousInner<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+impl<T> Rendezvous<T> {
+    fn new(value: Option<T>) -> Arc<Self> {
+        Arc::new(Self {
+            inner: Mutex::new(RendezvousInner {
+                value,
+                waker: None,
+                closed: false,
+            }),
+        })
+    }
+
+    fn fill(&self, value: T) {
+        let mut inner = self.inner.lock();
+        inner.value = Some(value);
+        if let Some(waker) = inner.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn take(&self) -> Option<T> {
+        let mut inner = self.inner.lock();
+        let value = inner.value.take();
+        if let Some(waker) = inner.waker.take() {
+            waker.wake();
+        }
+        value
+    }
+
+    /// Tells the waiting side that the slot is never going to be filled again. A value filled
+    /// before can still be taken.
+    fn close(&self) {
+        let mut inner = self.inner.lock();
+        inner.closed = true;
+        if let Some(waker) = inner.waker.take() {
+            waker.wake();
+        }
+    }
+
+    async fn filled(&self) -> Result<T> {
+        poll_fn(|cx| {
+            let mut inner = self.inner.lock();
+            match inner.value.take() {
+                Some(value) => Poll::Ready(Ok(value)),
+                None if inner.closed => Poll::Ready(Err(InternalError::BrokenPipe)),
+                None => {
+                    inner.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    async fn emptied(&self) {
+        poll_fn(|cx| {
+            let mut inner = self.inner.lock();
+            if inner.value.is_none() {
+                Poll::Ready(())
+            } else {
+                inner.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+/// Endpoints by their id, which leave it as they are dropped, e.g. with the last handle to them as
+/// their process exits.
+static ENDPOINT_REGISTRY: RwLock<BTreeMap<EndpointId, Weak<Endpoint>>> =
+    RwLock::new(BTreeMap::new());
+
+pub struct Endpoint {
+    id: EndpointId,
+    queues: Mutex<Queues>,
+}
+
+#[derive(Default)]
+struct Queues {
+    senders: VecDeque<Arc<Rendezvous<Envelope>>>,
+    receivers: VecDeque<Arc<Rendezvous<Envelope>>>,
+}
+
+impl Endpoint {
+    pub fn create() -> Arc<Self> {
+        let endpoint = Arc::new(Self {
+            id: EndpointId::new(),
+            queues: Mutex::new(Queues::default()),
+        });
+        ENDPOINT_REGISTRY
+            .write()
+            .insert(endpoint.id, Arc::downgrade(&endpoint));
+        endpoint
+    }
+
+    pub fn find(id: EndpointId) -> Result<Arc<Self>> {
+        ENDPOINT_REGISTRY
+            .read()
+            .get(&id)
+            .and_then(Weak::upgrade)
+            .ok_or(InternalError::InvalidEndpointId)
+    }
+
+    pub fn destroy(&self) -> Result<()> {
+        ENDPOINT_REGISTRY
+            .write()
+            .remove(&self.id)
+            .ok_or(InternalError::InvalidEndpointId)?;
+        Ok(())
+    }
+
+    pub fn id(&self) -> EndpointId {
+        self.id
+    }
+
+    /// Sends `msg`, waiting until a receiver takes it.
+    pub async fn send(&self, msg: Message) {
+        self.deliver(Envelope { msg, reply: None }).await;
+    }
+
+    /// Sends `msg` and waits for the receiver to reply, failing if it drops the right to reply
+    /// instead.
+    pub async fn call(&self, msg: Message) -> Result<Message> {
+        let reply = Rendezvous::new(None);
+        self.deliver(Envelope {
+            msg,
+            reply: Some(Reply(reply.clone())),
+        })
+        .await;
+        reply.filled().await
+    }
+
+    /// Waits for a message, along with the right to reply to it if it was sent by a call.
+    pub async fn recv(&self) -> (Message, Option<Reply>) {
+        let queued = {
+            let mut queues = self.queues.lock();
+            match queues.senders.pop_front() {
+                Some(sender) => {
+                    let envelope = sender.take().unwrap();
+                    return (envelope.msg, envelope.reply);
+                }
+                None => {
+                    let slot = Rendezvous::new(None);
+                    queues.receivers.push_back(slot.clone());
+                    Queued {
+                        endpoint: self,
+                        slot,
+                        sender: false,
+                    }
+                }
+            }
+        };
+        // receiver slots are never closed
+        let envelope = queued.slot.filled().await.unwrap();
+        (envelope.msg, envelope.reply)
+    }
+
+    async fn deliver(&self, envelope: Envelope) {
+        let queued = {
+            let mut queues = self.queues.lock();
+            match queues.receivers.pop_front() {
+                Some(receiver) => {
+                    receiver.fill(envelope);
+                    return;
+                }
+                None => {
+                    let slot = Rendezvous::new(Some(envelope));
+                    queues.senders.push_back(slot.clone());
+                    Queued {
+                        endpoint: self,
+                        slot,
+                        sender: true,
+                    }
+                }
+            }
+        };
+        queued.slot.emptied().await;
+    }
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        ENDPOINT_REGISTRY.write().remove(&self.id);
+    }
+}
+
+/// A slot waiting in a queue of an endpoint, which leaves the queue if its task stops waiting,
+/// e.g. as it exits, so that no peer hands a message over to a dead task or takes one from it.
+///
+/// A sender returns as soon as its message is in the slot of a receiver, a message the receiver
+/// has not taken yet is therefore handed on to the next receiver instead of being lost.
+struct Queued<'a> {
+    endpoint: &'a Endpoint,
+    slot: Arc<Rendezvous<Envelope>>,
+    sender: bool,
+}
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        let mut queues = self.endpoint.queues.lock();
+        if self.sender {
+            queues.senders.retain(|slot| !Arc::ptr_eq(slot, &self.slot));
+            return;
+        }
+        queues
+            .receivers
+            .retain(|slot| !Arc::ptr_eq(slot, &self.slot));
+
+        // slots are only filled with the queues locked, the envelope cannot arrive after this
+        if let Some(envelope) = self.slot.take() {
+            match queues.receivers.pop_front() {
+                Some(receiver) => receiver.fill(envelope),
+                None => queues.senders.push_front(Rendezvous::new(Some(envelope))),
+            }
+        }
+    }
+}
@@ -0,0 +1,351 @@
+#![no_std]
+
+extern crate alloc;
+#[macro_use]
+extern crate log;
+
+pub mod cap;
+pub mod coredump;
+pub mod fd;
+pub mod ipc;
+pub mod linux;
+pub mod pipe;
+pub mod signal;
+pub mod syscall;
+pub mod timer;
+pub mod uaccess;
+
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::{fmt::Display, mem, sync::atomic::AtomicUsize};
+
+use elf::{
+    abi::{PF_R, PF_W, PF_X},
+    endian::AnyEndian,
+    ElfBytes,
+};
+use jrinx_addr::VirtAddr;
+use jrinx_config::{PAGE_SIZE, USER_STACK_TOP, USTACK_SIZE};
+use jrinx_error::{InternalError, Result};
+use jrinx_hal::{hal, Cache, Hal, Interrupt};
+use jrinx_loader::ElfLoader;
+use jrinx_paging::{GenericPagePerm, GenericPageTable, PagePerm};
+use jrinx_phys_frame::PhysFrame;
+use jrinx_serial_id_macro::SerialId;
+use jrinx_trap::{arch::Context, GenericContext, TrapReason};
+use jrinx_vmm::addr_space::AddrSpace;
+use spin::{Mutex, Once, RwLock};
+
+use crate::{cap::HandleTable, fd::FdTable, ipc::Reply, signal::Signals};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, SerialId)]
+pub struct ProcessId(u64);
+
+impl Display for ProcessId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<ProcessId> for usize {
+    fn from(value: ProcessId) -> Self {
+        value.0 as usize
+    }
+}
+
+impl From<usize> for ProcessId {
+    fn from(value: usize) -> Self {
+        Self(value as u64)
+    }
+}
+
+/// The system call ABI a process is started with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Personality {
+    Native,
+    Linux,
+}
+
+static PROCESS_REGISTRY: RwLock<BTreeMap<ProcessId, Arc<Process>>> = RwLock::new(BTreeMap::new());
+
+pub struct Process {
+    id: ProcessId,
+    parent: Option<ProcessId>,
+    personality: Personality,
+    addr_space: Mutex<AddrSpace>,
+    context: Mutex<Context>,
+    fd_table: Mutex<FdTable>,
+    handles: Mutex<HandleTable>,
+    signals: Signals,
+    reply: Mutex<Option<Reply>>,
+    /// Where a Linux process wants its thread id cleared once it exits, 0 if nowhere.
+    clear_child_tid: AtomicUsize,
+    exit_code: Once<usize>,
+}
+
+impl Process {
+    pub fn create(elf: &ElfBytes<'_, AnyEndian>, personality: Personality) -> Result<Arc<Self>> {
+        let mut addr_space = AddrSpace::new()?;
+        load_elf(&mut addr_space, elf)?;
+        addr_space.map(
+            Some(VirtAddr::new(USER_STACK_TOP - USTACK_SIZE)),
+            USTACK_SIZE,
+            PagePerm::R | PagePerm::W,
+        )?;
+
+        let stack_top = match personality {
+            Personality::Native => USER_STACK_TOP,
+            Personality::Linux => linux::setup_stack(&mut addr_space, elf)?,
+        };
+
+        let mut context = Context::default();
+        context.user_setup(elf.ehdr.e_entry as usize, stack_top);
+
+        Ok(Self::register(Self {
+            id: ProcessId::new(),
+            parent: None,
+            personality,
+            addr_space: Mutex::new(addr_space),
+            context: Mutex::new(context),
+            fd_table: Mutex::new(FdTable::with_console()),
+            handles: Mutex::new(HandleTable::new()),
+            signals: Signals::default(),
+            reply: Mutex::new(None),
+            clear_child_tid: AtomicUsize::new(0),
+            exit_code: Once::new(),
+        }))
+    }
+
+    pub fn find(id: ProcessId) -> Result<Arc<Self>> {
+        PROCESS_REGISTRY
+            .read()
+            .get(&id)
+            .cloned()
+            .ok_or(InternalError::InvalidProcessId)
+    }
+
+    pub fn id(&self) -> ProcessId {
+        self.id
+    }
+
+    /// Returns the id of the only thread of the process, which is the process id, as it is for
+    /// the main thread of a Linux process.
+    pub fn tid(&self) -> usize {
+        self.id.into()
+    }
+
+    pub fn parent(&self) -> Option<ProcessId> {
+        self.parent
+    }
+
+    pub fn personality(&self) -> Personality {
+        self.personality
+    }
+
+    /// Returns the exit code of the process, if it has exited by itself.
+    pub fn exit_code(&self) -> Option<usize> {
+        self.exit_code.get().copied()
+    }
+
+    pub fn with_context<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Context) -> R,
+    {
+        f(&mut self.context.lock())
+    }
+
+    pub fn with_addr_space<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut AddrSpace) -> R,
+    {
+        f(&mut self.addr_space.lock())
+    }
+
+    pub fn with_fd_table<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut FdTable) -> R,
+    {
+        f(&mut self.fd_table.lock())
+    }
+
+    pub fn with_handles<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut HandleTable) -> R,
+    {
+        f(&mut self.handles.lock())
+    }
+
+    pub fn fork(&self) -> Result<Arc<Self>> {
+        let addr_space = self.with_addr_space(|addr_space| {
+            let child = addr_space.fork();
+            addr_space.flush_tlb();
+            child
+        })?;
+
+        let child = Self::register(Self {
+            id: ProcessId::new(),
+            parent: Some(self.id),
+            personality: self.personality,
+            addr_space: Mutex::new(addr_space),
+            context: Mutex::new(*self.context.lock()),
+            fd_table: Mutex::new(self.fd_table.lock().clone()),
+            handles: Mutex::new(self.handles.lock().clone()),
+            signals: self.signals.fork(),
+            reply: Mutex::new(None),
+            clear_child_tid: AtomicUsize::new(0),
+            exit_code: Once::new(),
+        });
+        debug!("process {} forked into process {}", self.id, child.id);
+        Ok(child)
+    }
+
+    /// Unregisters the process and releases its descriptors, its handles and the call it has yet
+    /// to reply to, as handles to the process held by other processes may keep it alive.
+    pub fn exit(&self) -> Result<()> {
+        PROCESS_REGISTRY
+            .write()
+            .remove(&self.id)
+            .ok_or(InternalError::InvalidProcessId)?;
+        let fd_table = mem::take(&mut *self.fd_table.lock());
+        let handles = mem::take(&mut *self.handles.lock());
+        let reply = self.reply.lock().take();
+        drop((fd_table, handles, reply));
+        Ok(())
+    }
+
+    /// Runs the process in its own address space until it traps for a reason that cannot be
+    /// resolved in the kernel, e.g. an unknown system call or an illegal access the process has no
+    /// signal handler for, the latter leaving a core file behind.
+    ///
+    /// System calls that block, e.g. IPC, park the calling task instead of spinning, the address
+    /// space is therefore activated again every time the process is resumed. Interrupts taken in
+    /// user mode are handled and the process is resumed, pending signals are delivered every time.
+    pub async fn run(&self) -> TrapReason {
+        let reason = loop {
+            self.activate();
+
+            let mut ctx = *self.context.lock();
+            ctx.run();
+
+            let reason = ctx.trap_reason();
+            let resolved = match reason {
+                TrapReason::PageFault { addr, perm } => self.with_addr_space(|addr_space| {
+                    let resolved = addr_space.handle_page_fault(addr, perm).is_ok();
+                    addr_space.flush_tlb();
+                    resolved
+                }),
+                TrapReason::SystemCall => {
+                    let handled = self
+                        .interruptible(async {
+                            match self.personality {
+                                Personality::Native => syscall::handle(self, &mut ctx).await,
+                                Personality::Linux => linux::handle(self, &mut ctx).await,
+                            }
+                        })
+                        .await;
+                    handled.unwrap_or_else(|| {
+                        ctx.set_syscall_ret(match self.personality {
+                            Personality::Native => usize::MAX,
+                            Personality::Linux => linux::EINTR.wrapping_neg(),
+                        });
+                        true
+                    })
+                }
+                TrapReason::TimerInterrupt
+                | TrapReason::ExternalInterrupt
+                | TrapReason::SoftwareInterrupt => {
+                    // the interrupt stays pending until the kernel takes it with interrupts on
+                    hal!().interrupt().with_saved_on(|| {});
+                    true
+                }
+                _ => false,
+            };
+            let resolved = resolved || self.deliver_fault(&mut ctx, reason);
+            if resolved {
+                self.deliver_pending(&mut ctx);
+            }
+
+            *self.context.lock() = ctx;
+            if !resolved {
+                let _ = coredump::dump(self, &ctx, reason);
+            }
+            if !resolved || self.exit_code().is_some() {
+                break reason;
+            }
+        };
+
+        jrinx_vmm::activate_kernel();
+
+        reason
+    }
+
+    fn activate(&self) {
+        self.addr_space.lock().activate();
+    }
+
+    pub(crate) fn set_exit_code(&self, code: usize) {
+        self.exit_code.call_once(|| code);
+    }
+
+    fn register(process: Self) -> Arc<Self> {
+        let process = Arc::new(process);
+        PROCESS_REGISTRY.write().insert(process.id, process.clone());
+        process
+    }
+}
+
+fn load_elf(addr_space: &mut AddrSpace, elf: &ElfBytes<'_, AnyEndian>) -> Result<()> {
+    let mut pages = BTreeMap::new();
+    let page_table = addr_space.page_table_mut();
+    ElfLoader::new(elf).load(|elf, phdr, vaddr, offst, len| {
+        let mut perm = PagePerm::V | PagePerm::U;
+        if phdr.p_flags & PF_R != 0 {
+            perm |= PagePerm::R;
+        }
+        if phdr.p_flags & PF_W != 0 {
+            perm |= PagePerm::W;
+        }
+        if phdr.p_flags & PF_X != 0 {
+            perm |= PagePerm::X;
+        }
+
+        let paddr = if let Ok((phys_frame, old_perm)) = page_table.lookup(vaddr) {
+            let paddr = phys_frame.addr();
+            if !old_perm.contains(perm) {
+                page_table.map(vaddr, phys_frame, perm | old_perm)?;
+            }
+            perm |= old_perm;
+            paddr
+        } else {
+            let phys_frame = PhysFrame::alloc()?;
+            let paddr = phys_frame.addr();
+            page_table.map(vaddr, phys_frame, perm)?;
+            paddr
+        };
+        pages.insert(
+            vaddr.align_page_down(),
+            perm & (PagePerm::R | PagePerm::W | PagePerm::X),
+        );
+        if len != 0 {
+            let data = elf
+                .segment_data(phdr)
+                .map_err(|_| InternalError::ElfParseError)?;
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    data.as_ptr(),
+                    (paddr.to_virt().as_usize() + offst) as *mut u8,
+                    len,
+                );
+            }
+        }
+        Ok(())
+    })?;
+    hal!().cache().sync_all();
+
+    for (&page, &perm) in pages.iter() {
+        addr_space.insert(page, page + PAGE_SIZE, perm)?;
+    }
+    if let Some((&page, _)) = pages.last_key_value() {
+        addr_space.init_brk(page + PAGE_SIZE);
+    }
+    Ok(())
+}
@@ -0,0 +1,222 @@
+//! The Linux RISC-V system call personality, enough for statically linked musl binaries.
+
+use core::{mem::size_of, sync::atomic::Ordering};
+
+use elf::{abi::PT_LOAD, endian::AnyEndian, ElfBytes};
+use jrinx_addr::VirtAddr;
+use jrinx_config::USER_STACK_TOP;
+use jrinx_error::{InternalError, Result};
+use jrinx_hal::{hal, Cpu, Hal};
+use jrinx_trap::{arch::Context, GenericContext};
+use jrinx_vmm::addr_space::AddrSpace;
+
+use crate::{syscall, uaccess::copy_to_user, Process};
+
+pub const SYS_DUP: usize = 23;
+pub const SYS_CLOSE: usize = 57;
+pub const SYS_PIPE2: usize = 59;
+pub const SYS_READ: usize = 63;
+pub const SYS_WRITE: usize = 64;
+pub const SYS_EXIT: usize = 93;
+pub const SYS_EXIT_GROUP: usize = 94;
+pub const SYS_SET_TID_ADDRESS: usize = 96;
+pub const SYS_CLOCK_GETTIME: usize = 113;
+pub const SYS_UNAME: usize = 160;
+pub const SYS_GETPID: usize = 172;
+pub const SYS_BRK: usize = 214;
+pub const SYS_MUNMAP: usize = 215;
+pub const SYS_MMAP: usize = 222;
+pub const SYS_MPROTECT: usize = 226;
+pub const SYS_CLOCK_GETTIME64: usize = 403;
+
+pub const EINTR: usize = 4;
+pub const EBADF: usize = 9;
+pub const EAGAIN: usize = 11;
+pub const ENOMEM: usize = 12;
+pub const EACCES: usize = 13;
+pub const EFAULT: usize = 14;
+pub const ENODEV: usize = 19;
+pub const EINVAL: usize = 22;
+pub const EPIPE: usize = 32;
+pub const ENOSYS: usize = 38;
+
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+pub const CLOCK_MONOTONIC: usize = 1;
+pub const CLOCK_MONOTONIC_RAW: usize = 4;
+pub const CLOCK_MONOTONIC_COARSE: usize = 6;
+pub const CLOCK_BOOTTIME: usize = 7;
+
+pub const O_CLOEXEC: usize = 0x80000;
+
+const AT_NULL: usize = 0;
+const AT_PHDR: usize = 3;
+const AT_PHENT: usize = 4;
+const AT_PHNUM: usize = 5;
+const AT_PAGESZ: usize = 6;
+const AT_ENTRY: usize = 9;
+
+const UTSNAME_FIELD_LEN: usize = 65;
+
+/// Errors are reported to Linux processes as negated errno values.
+type LinuxResult = core::result::Result<usize, usize>;
+
+/// Handles a system call trapped from a Linux process.
+///
+/// Unlike the native personality, unknown system calls fail with `ENOSYS` instead of being left
+/// to the caller, as musl probes several of them during start-up.
+pub(crate) async fn handle(process: &Process, ctx: &mut Context) -> bool {
+    let num = ctx.syscall_num();
+    let [arg0, arg1, arg2, arg3, _, _] = ctx.syscall_args();
+    ctx.pc_advance();
+
+    let ret = match num {
+        SYS_DUP => syscall::sys_dup(process, arg0).map_err(errno),
+        SYS_CLOSE => syscall::sys_close(process, arg0).map_err(errno),
+        // descriptors are never inherited by `execve`, which is not supported either
+        SYS_PIPE2 => syscall::sys_pipe(process, arg0, arg1 & !O_CLOEXEC).map_err(errno),
+        SYS_READ => syscall::sys_read(process, arg0, arg1, arg2)
+            .await
+            .map_err(errno),
+        SYS_WRITE => syscall::sys_write(process, arg0, arg1, arg2)
+            .await
+            .map_err(errno),
+        SYS_EXIT | SYS_EXIT_GROUP => sys_exit_group(process, arg0),
+        SYS_SET_TID_ADDRESS => sys_set_tid_address(process, arg0),
+        SYS_CLOCK_GETTIME | SYS_CLOCK_GETTIME64 => sys_clock_gettime(process, arg0, arg1),
+        SYS_UNAME => sys_uname(process, arg0),
+        SYS_GETPID => Ok(process.id().into()),
+        SYS_BRK => syscall::sys_brk(process, arg0).map_err(errno),
+        SYS_MUNMAP => syscall::sys_munmap(process, arg0, arg1).map_err(errno),
+        SYS_MMAP => sys_mmap(process, arg0, arg1, arg2, arg3),
+        SYS_MPROTECT => syscall::sys_mprotect(process, arg0, arg1, arg2).map_err(errno),
+        _ => {
+            debug!("process {} calls unsupported syscall {}", process.id(), num);
+            Err(ENOSYS)
+        }
+    };
+
+    ctx.set_syscall_ret(ret.unwrap_or_else(|errno| errno.wrapping_neg()));
+    true
+}
+
+/// Pushes an empty argument vector, an empty environment and the auxiliary vector musl expects
+/// onto the user stack, and returns the initial stack pointer.
+pub(crate) fn setup_stack(
+    addr_space: &mut AddrSpace,
+    elf: &ElfBytes<'_, AnyEndian>,
+) -> Result<usize> {
+    let phdr = elf
+        .segments()
+        .ok_or(InternalError::ElfParseError)?
+        .iter()
+        .filter(|phdr| phdr.p_type == PT_LOAD)
+        .find(|phdr| (phdr.p_offset..phdr.p_offset + phdr.p_filesz).contains(&elf.ehdr.e_phoff))
+        .map_or(0, |phdr| {
+            (phdr.p_vaddr + elf.ehdr.e_phoff - phdr.p_offset) as usize
+        });
+
+    let words = [
+        0, // argc
+        0, // end of argv
+        0, // end of envp
+        AT_PHDR,
+        phdr,
+        AT_PHENT,
+        elf.ehdr.e_phentsize as usize,
+        AT_PHNUM,
+        elf.ehdr.e_phnum as usize,
+        AT_PAGESZ,
+        jrinx_config::PAGE_SIZE,
+        AT_ENTRY,
+        elf.ehdr.e_entry as usize,
+        AT_NULL,
+        0,
+    ];
+
+    let sp = (USER_STACK_TOP - words.len() * size_of::<usize>()) & !0xf;
+    for (i, word) in words.iter().enumerate() {
+        addr_space.write_bytes(
+            VirtAddr::new(sp + i * size_of::<usize>()),
+            &word.to_ne_bytes(),
+        )?;
+    }
+    Ok(sp)
+}
+
+fn errno(err: InternalError) -> usize {
+    match err {
+        InternalError::NotEnoughMem => ENOMEM,
+        InternalError::InvalidVirtAddr => EFAULT,
+        InternalError::InvalidFileDescriptor => EBADF,
+        InternalError::WouldBlock => EAGAIN,
+        InternalError::BrokenPipe => EPIPE,
+        InternalError::InsufficientRights => EACCES,
+        _ => EINVAL,
+    }
+}
+
+/// Clears the thread id recorded by [`sys_set_tid_address`], if any, as the thread exits.
+fn sys_exit_group(process: &Process, code: usize) -> LinuxResult {
+    debug!("process {} exits with code {}", process.id(), code as i32);
+    let clear_child_tid = process.clear_child_tid.swap(0, Ordering::SeqCst);
+    if clear_child_tid != 0 {
+        let _ = copy_to_user(process, VirtAddr::new(clear_child_tid), &0u32.to_ne_bytes());
+    }
+    process.set_exit_code(code);
+    Ok(0)
+}
+
+/// Records where to clear the thread id once the thread exits, and returns the thread id.
+fn sys_set_tid_address(process: &Process, tidptr: usize) -> LinuxResult {
+    process.clear_child_tid.store(tidptr, Ordering::SeqCst);
+    Ok(process.tid())
+}
+
+fn sys_clock_gettime(process: &Process, clk_id: usize, tp: usize) -> LinuxResult {
+    // there is no wall clock, so only the clocks counting from boot are supported
+    if !matches!(
+        clk_id,
+        CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_MONOTONIC_COARSE | CLOCK_BOOTTIME
+    ) {
+        return Err(EINVAL);
+    }
+
+    let time = hal!().cpu().get_time();
+
+    // `struct __kernel_timespec`, which is 64-bit on both rv32 and rv64
+    let mut timespec = [0u8; 16];
+    timespec[..8].copy_from_slice(&time.as_secs().to_ne_bytes());
+    timespec[8..].copy_from_slice(&(time.subsec_nanos() as u64).to_ne_bytes());
+    copy_to_user(process, VirtAddr::new(tp), &timespec).map_err(errno)?;
+    Ok(0)
+}
+
+fn sys_uname(process: &Process, buf: usize) -> LinuxResult {
+    let fields: [&[u8]; 6] = [
+        b"Jrinx",
+        b"jrinx",
+        b"6.0.0",
+        b"jrinx",
+        if cfg!(target_arch = "riscv32") {
+            b"riscv32"
+        } else {
+            b"riscv64"
+        },
+        b"(none)",
+    ];
+
+    let mut utsname = [0u8; UTSNAME_FIELD_LEN * 6];
+    for (i, field) in fields.iter().enumerate() {
+        utsname[i * UTSNAME_FIELD_LEN..][..field.len()].copy_from_slice(field);
+    }
+    copy_to_user(process, VirtAddr::new(buf), &utsname).map_err(errno)?;
+    Ok(0)
+}
+
+fn sys_mmap(process: &Process, addr: usize, len: usize, prot: usize, flags: usize) -> LinuxResult {
+    if flags & MAP_ANONYMOUS == 0 {
+        return Err(ENODEV);
+    }
+    syscall::sys_mmap(process, addr, len, prot, flags).map_err(errno)
+}
@@ -0,0 +1,110 @@
+//! Anonymous pipes, a bounded byte queue between a read end and a write end.
+
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use core::{mem, task::Waker};
+
+use jrinx_config::PAGE_SIZE;
+use jrinx_error::{InternalError, Result};
+use spin::Mutex;
+
+use crate::fd::File;
+
+pub const PIPE_CAPACITY: usize = PAGE_SIZE;
+
+#[derive(Default)]
+struct PipeState {
+    buf: VecDeque<u8>,
+    reader_closed: bool,
+    writer_closed: bool,
+    readers: Vec<Waker>,
+    writers: Vec<Waker>,
+}
+
+type Pipe = Mutex<PipeState>;
+
+pub struct PipeReader(Arc<Pipe>);
+
+pub struct PipeWriter(Arc<Pipe>);
+
+/// Creates a pipe and returns its read end and write end.
+///
+/// Reading from a pipe whose write end is dropped returns 0 once it is drained, writing to a pipe
+/// whose read end is dropped fails with `BrokenPipe`.
+pub fn pipe() -> (PipeReader, PipeWriter) {
+    let pipe = Arc::new(Pipe::default());
+    (PipeReader(pipe.clone()), PipeWriter(pipe))
+}
+
+impl File for PipeReader {
+    fn read(&self, buf: &mut [u8], waker: Option<&Waker>) -> Result<usize> {
+        let mut state = self.0.lock();
+        if state.buf.is_empty() && !buf.is_empty() {
+            if state.writer_closed {
+                return Ok(0);
+            }
+            if let Some(waker) = waker {
+                state.readers.push(waker.clone());
+            }
+            return Err(InternalError::WouldBlock);
+        }
+
+        let len = buf.len().min(state.buf.len());
+        for (dst, src) in buf.iter_mut().zip(state.buf.drain(..len)) {
+            *dst = src;
+        }
+        let writers = mem::take(&mut state.writers);
+        drop(state);
+        writers.into_iter().for_each(Waker::wake);
+        Ok(len)
+    }
+
+    fn write(&self, _buf: &[u8], _waker: Option<&Waker>) -> Result<usize> {
+        Err(InternalError::InvalidFileDescriptor)
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.reader_closed = true;
+        let writers = mem::take(&mut state.writers);
+        drop(state);
+        writers.into_iter().for_each(Waker::wake);
+    }
+}
+
+impl File for PipeWriter {
+    fn read(&self, _buf: &mut [u8], _waker: Option<&Waker>) -> Result<usize> {
+        Err(InternalError::InvalidFileDescriptor)
+    }
+
+    fn write(&self, buf: &[u8], waker: Option<&Waker>) -> Result<usize> {
+        let mut state = self.0.lock();
+        if state.reader_closed {
+            return Err(InternalError::BrokenPipe);
+        }
+        let len = buf.len().min(PIPE_CAPACITY - state.buf.len());
+        if len == 0 && !buf.is_empty() {
+            if let Some(waker) = waker {
+                state.writers.push(waker.clone());
+            }
+            return Err(InternalError::WouldBlock);
+        }
+
+        state.buf.extend(&buf[..len]);
+        let readers = mem::take(&mut state.readers);
+        drop(state);
+        readers.into_iter().for_each(Waker::wake);
+        Ok(len)
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.writer_closed = true;
+        let readers = mem::take(&mut state.readers);
+        drop(state);
+        readers.into_iter().for_each(Waker::wake);
+    }
+}
@@ -0,0 +1,261 @@
+//! Signals, which deliver faults, alarms and kill requests to handlers registered by a process.
+//!
+//! A handler is entered with the signal number in `a0`, additional information, e.g. the faulting
+//! address, in `a1` and the address of the saved context in `a2`. The interrupted context is
+//! saved on the user stack and restored by the sigreturn system call, issued by the restorer the
+//! handler returns to. The signal stays blocked while its handler runs.
+//!
+//! Pending signals are delivered whenever the process returns to user mode, a system call blocked
+//! when a signal arrives fails instead, e.g. with `EINTR` for Linux processes.
+
+use alloc::sync::Arc;
+use core::{
+    future::{poll_fn, Future},
+    mem::{self, size_of},
+    pin::pin,
+    ptr, slice,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Poll, Waker},
+    time::Duration,
+};
+
+use jrinx_addr::VirtAddr;
+use jrinx_error::{InternalError, Result};
+use jrinx_hal::{hal, Cpu, Hal, Interrupt};
+use jrinx_timed_event::{TimedEvent, TimedEventHandler, TimedEventTracker};
+use jrinx_trap::{arch::Context, GenericContext, TrapReason};
+use spin::Mutex;
+
+use crate::{
+    uaccess::{copy_from_user, copy_to_user},
+    Process,
+};
+
+pub const SIGILL: usize = 4;
+pub const SIGTRAP: usize = 5;
+pub const SIGKILL: usize = 9;
+pub const SIGSEGV: usize = 11;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+
+pub const NSIG: usize = 32;
+
+/// The exit code of a process terminated by a signal is the signal number plus this base.
+pub const SIG_EXIT_BASE: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SigAction {
+    pub handler: usize,
+    pub restorer: usize,
+}
+
+#[derive(Default)]
+pub(crate) struct Signals {
+    pending: AtomicUsize,
+    state: Mutex<SignalState>,
+    /// The system call the process blocks in, woken by a signal, e.g. from the alarm in interrupt
+    /// context.
+    waiter: Mutex<Option<Waker>>,
+}
+
+#[derive(Default)]
+struct SignalState {
+    actions: [Option<SigAction>; NSIG],
+    blocked: usize,
+    alarm: Option<TimedEventTracker>,
+}
+
+/// The frame pushed onto the user stack when a handler is entered.
+#[repr(C)]
+struct SignalFrame {
+    ctx: Context,
+    blocked: usize,
+}
+
+impl Signals {
+    /// Creates the signals of a forked child, which inherits the actions and the blocked signals
+    /// but neither the pending signals nor the alarm.
+    pub(crate) fn fork(&self) -> Self {
+        let state = self.state.lock();
+        Self {
+            pending: AtomicUsize::new(0),
+            state: Mutex::new(SignalState {
+                actions: state.actions,
+                blocked: state.blocked,
+                alarm: None,
+            }),
+            waiter: Mutex::new(None),
+        }
+    }
+
+    fn raise(&self, sig: usize) {
+        self.pending.fetch_or(1 << sig, Ordering::SeqCst);
+        let waiter = hal!()
+            .interrupt()
+            .with_saved_off(|| self.waiter.lock().take());
+        if let Some(waiter) = waiter {
+            waiter.wake();
+        }
+    }
+}
+
+impl Process {
+    /// Sends `sig` to the process, which receives it as it returns to user mode, interrupting the
+    /// system call it blocks in, if any.
+    pub fn kill(&self, sig: usize) -> Result<()> {
+        check_signal(sig)?;
+        self.signals.raise(sig);
+        Ok(())
+    }
+
+    /// Registers `action` for `sig`, or restores the default action, i.e. termination for
+    /// asynchronous signals and returning the trap to the caller of [`Process::run`] for faults,
+    /// if it is `None`. Returns the previous action.
+    pub fn sigaction(&self, sig: usize, action: Option<SigAction>) -> Result<Option<SigAction>> {
+        check_signal(sig)?;
+        if sig == SIGKILL {
+            return Err(InternalError::InvalidParam);
+        }
+        Ok(mem::replace(
+            &mut self.signals.state.lock().actions[sig],
+            action,
+        ))
+    }
+
+    /// Raises `SIGALRM` after `timeout`, replacing any earlier alarm.
+    pub fn alarm(self: &Arc<Self>, timeout: Duration) {
+        let process = Arc::downgrade(self);
+        // the timed event queue of this CPU is also locked by the timer interrupt
+        hal!().interrupt().with_saved_off(|| {
+            let mut state = self.signals.state.lock();
+            if let Some(alarm) = state.alarm.take() {
+                let _ = alarm.cancel();
+            }
+            state.alarm = Some(TimedEvent::create(
+                hal!().cpu().get_time() + timeout,
+                TimedEventHandler::new(
+                    move || {
+                        if let Some(process) = process.upgrade() {
+                            process.signals.raise(SIGALRM);
+                        }
+                    },
+                    || {},
+                ),
+            ));
+        });
+    }
+
+    /// Runs `syscall` until it completes, or until a signal that is not blocked is pending, in
+    /// which case it is dropped and `None` is returned.
+    pub(crate) async fn interruptible<F: Future>(&self, syscall: F) -> Option<F::Output> {
+        let mut syscall = pin!(syscall);
+        let output = poll_fn(|cx| {
+            if let Poll::Ready(output) = syscall.as_mut().poll(cx) {
+                return Poll::Ready(Some(output));
+            }
+            hal!()
+                .interrupt()
+                .with_saved_off(|| *self.signals.waiter.lock() = Some(cx.waker().clone()));
+            if self.deliverable() != 0 {
+                Poll::Ready(None)
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+        hal!()
+            .interrupt()
+            .with_saved_off(|| self.signals.waiter.lock().take());
+        output
+    }
+
+    /// Enters the handler of the signal raised by the fault `reason`, if the process has one.
+    pub(crate) fn deliver_fault(&self, ctx: &mut Context, reason: TrapReason) -> bool {
+        let (sig, info) = match reason {
+            TrapReason::PageFault { addr, .. } => (SIGSEGV, addr.as_usize()),
+            TrapReason::Breakpoint { addr } => (SIGTRAP, addr.as_usize()),
+            TrapReason::Unknown { code } => (SIGILL, code),
+            _ => return false,
+        };
+        self.deliver(ctx, sig, info)
+    }
+
+    /// Enters the handler of the lowest pending signal that is not blocked, or terminates the
+    /// process if the signal has no handler.
+    pub(crate) fn deliver_pending(&self, ctx: &mut Context) {
+        let pending = self.deliverable();
+        if pending == 0 {
+            return;
+        }
+        let sig = pending.trailing_zeros() as usize;
+        self.signals
+            .pending
+            .fetch_and(!(1 << sig), Ordering::SeqCst);
+
+        if !self.deliver(ctx, sig, 0) {
+            debug!("process {} is terminated by signal {}", self.id, sig);
+            self.set_exit_code(SIG_EXIT_BASE + sig);
+        }
+    }
+
+    /// Returns from a handler, restoring the context saved at the stack pointer of `ctx`.
+    pub(crate) fn sigreturn(&self, ctx: &mut Context) -> Result<()> {
+        let mut bytes = [0u8; size_of::<SignalFrame>()];
+        copy_from_user(self, VirtAddr::new(ctx.sp()), &mut bytes)?;
+        let frame = unsafe { ptr::read_unaligned(bytes.as_ptr() as *const SignalFrame) };
+
+        ctx.restore_user(&frame.ctx);
+        self.signals.state.lock().blocked = frame.blocked & !(1 << SIGKILL);
+        Ok(())
+    }
+
+    /// Returns the pending signals that are not blocked.
+    fn deliverable(&self) -> usize {
+        let blocked = self.signals.state.lock().blocked;
+        self.signals.pending.load(Ordering::SeqCst) & !blocked
+    }
+
+    fn deliver(&self, ctx: &mut Context, sig: usize, info: usize) -> bool {
+        // the frame is copied without the lock, only the process itself changes what it reads
+        let (action, blocked) = {
+            let state = self.signals.state.lock();
+            (state.actions[sig], state.blocked)
+        };
+        let Some(action) = action else {
+            return false;
+        };
+        if blocked & (1 << sig) != 0 {
+            return false;
+        }
+        let Some(sp) = ctx.sp().checked_sub(size_of::<SignalFrame>()) else {
+            return false;
+        };
+        let sp = sp & !0xf;
+
+        let frame = SignalFrame { ctx: *ctx, blocked };
+        let bytes = unsafe {
+            slice::from_raw_parts(
+                &frame as *const SignalFrame as *const u8,
+                size_of::<SignalFrame>(),
+            )
+        };
+        if copy_to_user(self, VirtAddr::new(sp), bytes).is_err() {
+            return false;
+        }
+
+        self.signals.state.lock().blocked |= 1 << sig;
+        ctx.set_sp(sp);
+        ctx.set_pc(action.handler);
+        ctx.set_ra(action.restorer);
+        ctx.set_syscall_args([sig, info, sp, 0, 0, 0]);
+        true
+    }
+}
+
+fn check_signal(sig: usize) -> Result<()> {
+    if (1..NSIG).contains(&sig) {
+        Ok(())
+    } else {
+        Err(InternalError::InvalidParam)
+    }
+}
@@ -0,0 +1,622 @@
+use alloc::{string::String, sync::Arc, vec};
+use core::{
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use jrinx_addr::{PhysAddr, VirtAddr};
+use jrinx_config::PAGE_SIZE;
+use jrinx_driver::{device, irq::user_irq::IrqLine};
+use jrinx_error::{InternalError, Result};
+use jrinx_hal::{hal, Cpu, Hal};
+use jrinx_paging::{GenericPagePerm, PagePerm};
+use jrinx_trap::{arch::Context, GenericContext};
+use jrinx_vmm::shm::SharedMemory;
+
+use crate::{
+    cap::{Handle, KernelObject, Rights, HANDLE_NONE},
+    fd::{FileDesc, Socket},
+    ipc::{Endpoint, Message, MSG_DATA_MAX, MSG_WORDS},
+    pipe,
+    signal::{SigAction, SIGSEGV, SIG_EXIT_BASE},
+    timer::Timer,
+    uaccess::{check_user_writable, copy_from_user, copy_to_user},
+    Process,
+};
+
+pub const SYS_FORK: usize = 0x01;
+pub const SYS_BRK: usize = 0x02;
+pub const SYS_MMAP: usize = 0x03;
+pub const SYS_MUNMAP: usize = 0x04;
+pub const SYS_MPROTECT: usize = 0x05;
+pub const SYS_ENDPOINT_CREATE: usize = 0x06;
+pub const SYS_SEND: usize = 0x07;
+pub const SYS_RECV: usize = 0x08;
+pub const SYS_CALL: usize = 0x09;
+pub const SYS_REPLY: usize = 0x0A;
+pub const SYS_SHM_CREATE: usize = 0x0B;
+pub const SYS_SHM_OPEN: usize = 0x0C;
+pub const SYS_READ: usize = 0x0D;
+pub const SYS_WRITE: usize = 0x0E;
+pub const SYS_CLOSE: usize = 0x0F;
+pub const SYS_DUP: usize = 0x10;
+pub const SYS_PIPE: usize = 0x11;
+pub const SYS_HANDLE_DUP: usize = 0x12;
+pub const SYS_HANDLE_CLOSE: usize = 0x13;
+pub const SYS_MEM_MAP: usize = 0x14;
+pub const SYS_TIMER_CREATE: usize = 0x15;
+pub const SYS_TIMER_SET: usize = 0x16;
+pub const SYS_TIMER_WAIT: usize = 0x17;
+pub const SYS_SIGACTION: usize = 0x18;
+pub const SYS_SIGRETURN: usize = 0x19;
+pub const SYS_KILL: usize = 0x1A;
+pub const SYS_ALARM: usize = 0x1B;
+pub const SYS_MMIO_MAP: usize = 0x1C;
+pub const SYS_IRQ_CLAIM: usize = 0x1D;
+pub const SYS_IRQ_WAIT: usize = 0x1E;
+pub const SYS_IRQ_ACK: usize = 0x1F;
+pub const SYS_TCP_CONNECT: usize = 0x20;
+
+pub const PROT_READ: usize = 0x1;
+pub const PROT_WRITE: usize = 0x2;
+pub const PROT_EXEC: usize = 0x4;
+
+pub const MAP_FIXED: usize = 0x10;
+
+pub const O_NONBLOCK: usize = 0x800;
+
+pub const SHM_NAME_MAX: usize = 64;
+
+/// The most bytes staged in the kernel at once by `read` and `write`, larger reads are cut short
+/// and larger writes are done in chunks.
+pub const IO_BUF_MAX: usize = PAGE_SIZE;
+
+/// Handles a system call trapped from `process`.
+///
+/// Returns `false` if the system call number is unknown, leaving it to the caller.
+pub(crate) async fn handle(process: &Process, ctx: &mut Context) -> bool {
+    let num = ctx.syscall_num();
+    let [arg0, arg1, arg2, arg3, arg4, arg5] = ctx.syscall_args();
+    ctx.pc_advance();
+
+    let ret = match num {
+        SYS_FORK => sys_fork(process, ctx),
+        SYS_BRK => sys_brk(process, arg0),
+        SYS_MMAP => sys_mmap(process, arg0, arg1, arg2, arg3),
+        SYS_MUNMAP => sys_munmap(process, arg0, arg1),
+        SYS_MPROTECT => sys_mprotect(process, arg0, arg1, arg2),
+        SYS_ENDPOINT_CREATE => sys_endpoint_create(process),
+        SYS_SEND => sys_send(process, arg0, arg1, arg2, [arg3, arg4], arg5).await,
+        SYS_RECV => sys_recv(process, ctx, arg0, arg1, arg2).await,
+        SYS_CALL => sys_call(process, ctx, arg0, arg1, arg2, [arg3, arg4], arg5).await,
+        SYS_REPLY => sys_reply(process, arg1, arg2, [arg3, arg4], arg5),
+        SYS_SHM_CREATE => sys_shm_create(process, arg0, arg1, arg2, arg3),
+        SYS_SHM_OPEN => sys_shm_open(process, arg0, arg1, arg2),
+        SYS_READ => sys_read(process, arg0, arg1, arg2).await,
+        SYS_WRITE => sys_write(process, arg0, arg1, arg2).await,
+        SYS_CLOSE => sys_close(process, arg0),
+        SYS_DUP => sys_dup(process, arg0),
+        SYS_PIPE => sys_pipe(process, arg0, arg1),
+        SYS_HANDLE_DUP => sys_handle_dup(process, arg0, arg1),
+        SYS_HANDLE_CLOSE => sys_handle_close(process, arg0),
+        SYS_MEM_MAP => sys_mem_map(process, arg0, arg1, arg2),
+        SYS_TIMER_CREATE => sys_timer_create(process),
+        SYS_TIMER_SET => sys_timer_set(process, arg0, arg1),
+        SYS_TIMER_WAIT => sys_timer_wait(process, arg0).await,
+        SYS_SIGACTION => sys_sigaction(process, arg0, arg1, arg2),
+        SYS_SIGRETURN => sys_sigreturn(process, ctx),
+        SYS_KILL => sys_kill(process, arg0, arg1),
+        SYS_ALARM => sys_alarm(process, arg0),
+        SYS_MMIO_MAP => sys_mmio_map(process, arg0, arg1, arg2, arg3),
+        SYS_IRQ_CLAIM => sys_irq_claim(process, arg0),
+        SYS_IRQ_WAIT => sys_irq_wait(process, arg0).await,
+        SYS_IRQ_ACK => sys_irq_ack(process, arg0),
+        SYS_TCP_CONNECT => sys_tcp_connect(process, arg0, arg1, arg2),
+        _ => return false,
+    };
+
+    ctx.set_syscall_ret(ret.unwrap_or(usize::MAX));
+    true
+}
+
+/// Forks the process, returning the id of the child in `a0` and a handle to it in `a1`.
+fn sys_fork(process: &Process, ctx: &mut Context) -> Result<usize> {
+    process.with_context(|c| *c = *ctx);
+    let child = process.fork()?;
+    child.with_context(|c| c.set_syscall_ret(0));
+
+    let id = child.id().into();
+    let mut args = ctx.syscall_args();
+    args[1] = insert_handle(process, KernelObject::Process(child));
+    ctx.set_syscall_args(args);
+    Ok(id)
+}
+
+pub(crate) fn sys_brk(process: &Process, addr: usize) -> Result<usize> {
+    let brk = process.with_addr_space(|addr_space| {
+        // an invalid break, e.g. 0, only queries the current one
+        let _ = addr_space.set_brk(VirtAddr::new(addr));
+        addr_space.brk()
+    });
+    Ok(brk.as_usize())
+}
+
+pub(crate) fn sys_mmap(
+    process: &Process,
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+) -> Result<usize> {
+    let perm = prot_to_perm(prot)?;
+    let addr = VirtAddr::new(addr);
+    let start = process.with_addr_space(|addr_space| {
+        if flags & MAP_FIXED != 0 {
+            addr_space.map_fixed(addr, len, perm)
+        } else if addr.as_usize() != 0 {
+            addr_space
+                .map(Some(addr), len, perm)
+                .or_else(|_| addr_space.map(None, len, perm))
+        } else {
+            addr_space.map(None, len, perm)
+        }
+    })?;
+    Ok(start.as_usize())
+}
+
+pub(crate) fn sys_munmap(process: &Process, addr: usize, len: usize) -> Result<usize> {
+    process.with_addr_space(|addr_space| addr_space.unmap(VirtAddr::new(addr), len))?;
+    Ok(0)
+}
+
+pub(crate) fn sys_mprotect(
+    process: &Process,
+    addr: usize,
+    len: usize,
+    prot: usize,
+) -> Result<usize> {
+    let perm = prot_to_perm(prot)?;
+    process.with_addr_space(|addr_space| addr_space.protect(VirtAddr::new(addr), len, perm))?;
+    Ok(0)
+}
+
+fn sys_endpoint_create(process: &Process) -> Result<usize> {
+    Ok(insert_handle(
+        process,
+        KernelObject::Endpoint(Endpoint::create()),
+    ))
+}
+
+/// Sends the words in `a3..a4`, `len` bytes at `buf` and the handle in `a5` unless it is
+/// [`HANDLE_NONE`], blocking until a receiver takes them. The handle is moved out of the process.
+async fn sys_send(
+    process: &Process,
+    endpoint: usize,
+    buf: usize,
+    len: usize,
+    words: [usize; MSG_WORDS],
+    handle: usize,
+) -> Result<usize> {
+    let endpoint = find_object(process, endpoint, Rights::WRITE)?.endpoint()?;
+    let msg = read_msg(process, buf, len, words, handle)?;
+    endpoint.send(msg).await;
+    Ok(0)
+}
+
+/// Receives a message into `buf`, truncated to `cap` bytes.
+///
+/// Returns the length of the received buffer in `a0`, the badge of the sender in `a1`, whether
+/// the message expects a reply in `a2`, the message words in `a3..a4` and the received handle in
+/// `a5`.
+async fn sys_recv(
+    process: &Process,
+    ctx: &mut Context,
+    endpoint: usize,
+    buf: usize,
+    cap: usize,
+) -> Result<usize> {
+    let endpoint = find_object(process, endpoint, Rights::READ)?.endpoint()?;
+    let (msg, reply) = endpoint.recv().await;
+    let expects_reply = reply.is_some();
+    *process.reply.lock() = reply;
+    let (len, mut regs) = write_msg(process, buf, cap, msg)?;
+    regs[2] = expects_reply as usize;
+    ctx.set_syscall_args(regs);
+    Ok(len)
+}
+
+/// Sends a message like [`sys_send`] and blocks until the reply is received into the same
+/// buffer, truncated to `len` bytes. Fails if the receiver exits or receives another message
+/// without replying.
+async fn sys_call(
+    process: &Process,
+    ctx: &mut Context,
+    endpoint: usize,
+    buf: usize,
+    len: usize,
+    words: [usize; MSG_WORDS],
+    handle: usize,
+) -> Result<usize> {
+    let endpoint = find_object(process, endpoint, Rights::WRITE)?.endpoint()?;
+    let msg = read_msg(process, buf, len, words, handle)?;
+    let msg = endpoint.call(msg).await?;
+    let (len, regs) = write_msg(process, buf, len, msg)?;
+    ctx.set_syscall_args(regs);
+    Ok(len)
+}
+
+/// Replies to the last call received by the process.
+fn sys_reply(
+    process: &Process,
+    buf: usize,
+    len: usize,
+    words: [usize; MSG_WORDS],
+    handle: usize,
+) -> Result<usize> {
+    let reply = process
+        .reply
+        .lock()
+        .take()
+        .ok_or(InternalError::InvalidParam)?;
+    reply.reply(read_msg(process, buf, len, words, handle)?);
+    Ok(0)
+}
+
+/// Creates a shared memory object of `len` bytes named by the string at `name`, and returns a
+/// handle to it. Whoever opens it by name may map it with at most the access `prot` grants.
+fn sys_shm_create(
+    process: &Process,
+    name: usize,
+    name_len: usize,
+    len: usize,
+    prot: usize,
+) -> Result<usize> {
+    let name = read_name(process, name, name_len)?;
+    let shm = SharedMemory::create(&name, len, prot_to_perm(prot)?)?;
+    Ok(insert_handle(process, KernelObject::Memory(shm)))
+}
+
+/// Opens the shared memory object named by the string at `name`, and returns a handle to it with
+/// `rights`, which must not exceed the right to map it and the rights its creator shares.
+fn sys_shm_open(process: &Process, name: usize, name_len: usize, rights: usize) -> Result<usize> {
+    let rights = Rights::from_bits(rights).ok_or(InternalError::InvalidParam)?;
+    let shm = SharedMemory::open(&read_name(process, name, name_len)?)?;
+    if !(Rights::MAP | perm_rights(shm.shm().shared_perm())).contains(rights) {
+        return Err(InternalError::InsufficientRights);
+    }
+    let handle = Handle::new(KernelObject::Memory(shm), rights);
+    Ok(process.with_handles(|handles| handles.insert(handle)))
+}
+
+/// Maps the whole memory object behind `handle`, at `addr` if it is not 0, and returns its start
+/// address. This requires the right to map it as well as the rights to access it as requested by
+/// `prot`, which also bound what [`sys_mprotect`] may later grant. The mapping is removed by
+/// [`sys_munmap`].
+fn sys_mem_map(process: &Process, handle: usize, addr: usize, prot: usize) -> Result<usize> {
+    let perm = prot_to_perm(prot)?;
+    let (shm, rights) = process.with_handles(|handles| -> Result<_> {
+        let handle = handles.get(handle)?;
+        let shm = handle
+            .check(Rights::MAP | perm_rights(perm))?
+            .clone()
+            .memory()?;
+        Ok((shm, handle.rights()))
+    })?;
+    let addr = (addr != 0).then_some(VirtAddr::new(addr));
+    let start = process.with_addr_space(|addr_space| {
+        addr_space.map_shared(addr, shm.shm().clone(), perm, rights_perm(rights))
+    })?;
+    Ok(start.as_usize())
+}
+
+/// Duplicates `handle` with `rights`, which must not exceed the rights of `handle`.
+fn sys_handle_dup(process: &Process, handle: usize, rights: usize) -> Result<usize> {
+    let rights = Rights::from_bits(rights).ok_or(InternalError::InvalidParam)?;
+    process.with_handles(|handles| handles.duplicate(handle, rights))
+}
+
+fn sys_handle_close(process: &Process, handle: usize) -> Result<usize> {
+    let handle = process.with_handles(|handles| handles.remove(handle))?;
+    drop(handle);
+    Ok(0)
+}
+
+fn sys_timer_create(process: &Process) -> Result<usize> {
+    Ok(insert_handle(process, KernelObject::Timer(Timer::create())))
+}
+
+/// Arms the timer behind `handle` to fire `timeout` microseconds from now.
+fn sys_timer_set(process: &Process, handle: usize, timeout: usize) -> Result<usize> {
+    let timer = find_object(process, handle, Rights::WRITE)?.timer()?;
+    timer.set(hal!().cpu().get_time() + Duration::from_micros(timeout as u64));
+    Ok(0)
+}
+
+/// Blocks until the timer behind `handle` fires.
+async fn sys_timer_wait(process: &Process, handle: usize) -> Result<usize> {
+    let timer = find_object(process, handle, Rights::READ)?.timer()?;
+    timer.wait().await;
+    Ok(0)
+}
+
+/// Reads at most [`IO_BUF_MAX`] bytes from `fd` into `buf`.
+///
+/// `buf` is checked before anything is read, so no data is consumed only to be lost. Processes are
+/// single-threaded, nothing can unmap it while the read blocks.
+pub(crate) async fn sys_read(
+    process: &Process,
+    fd: usize,
+    buf: usize,
+    len: usize,
+) -> Result<usize> {
+    let desc = process.with_fd_table(|fd_table| fd_table.get(fd))?;
+    let mut data = vec![0u8; len.min(IO_BUF_MAX)];
+    check_user_writable(process, VirtAddr::new(buf), data.len())?;
+    let len = desc.read(&mut data).await?;
+    copy_to_user(process, VirtAddr::new(buf), &data[..len])?;
+    Ok(len)
+}
+
+/// Writes the `len` bytes at `buf` to `fd` in chunks of at most [`IO_BUF_MAX`] bytes, and returns
+/// how many have been written, which falls short if a chunk is only partly written or fails after
+/// another has been written.
+pub(crate) async fn sys_write(
+    process: &Process,
+    fd: usize,
+    buf: usize,
+    len: usize,
+) -> Result<usize> {
+    let desc = process.with_fd_table(|fd_table| fd_table.get(fd))?;
+    let mut data = vec![0u8; len.min(IO_BUF_MAX)];
+    let mut written = 0;
+    while written < len {
+        let chunk = &mut data[..(len - written).min(IO_BUF_MAX)];
+        let result = match copy_from_user(process, VirtAddr::new(buf.wrapping_add(written)), chunk)
+        {
+            Ok(()) => desc.write(chunk).await,
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(n) => {
+                written += n;
+                if n < chunk.len() {
+                    break;
+                }
+            }
+            Err(err) if written == 0 => return Err(err),
+            Err(_) => break,
+        }
+    }
+    Ok(written)
+}
+
+pub(crate) fn sys_close(process: &Process, fd: usize) -> Result<usize> {
+    process.with_fd_table(|fd_table| fd_table.close(fd))?;
+    Ok(0)
+}
+
+pub(crate) fn sys_dup(process: &Process, fd: usize) -> Result<usize> {
+    process.with_fd_table(|fd_table| fd_table.dup(fd))
+}
+
+/// Creates a pipe and stores the descriptors of its read end and write end as two 32-bit
+/// integers at `fds`.
+pub(crate) fn sys_pipe(process: &Process, fds: usize, flags: usize) -> Result<usize> {
+    if flags & !O_NONBLOCK != 0 {
+        return Err(InternalError::InvalidParam);
+    }
+    let nonblock = flags & O_NONBLOCK != 0;
+    let (reader, writer) = pipe::pipe();
+    let (read_fd, write_fd) = process.with_fd_table(|fd_table| {
+        (
+            fd_table.insert(FileDesc::new(Arc::new(reader), nonblock)),
+            fd_table.insert(FileDesc::new(Arc::new(writer), nonblock)),
+        )
+    });
+
+    let mut bytes = [0u8; 8];
+    bytes[..4].copy_from_slice(&(read_fd as u32).to_ne_bytes());
+    bytes[4..].copy_from_slice(&(write_fd as u32).to_ne_bytes());
+    let result = copy_to_user(process, VirtAddr::new(fds), &bytes);
+    if let Err(err) = result {
+        process.with_fd_table(|fd_table| {
+            let _ = fd_table.close(read_fd);
+            let _ = fd_table.close(write_fd);
+        });
+        return Err(err);
+    }
+    Ok(0)
+}
+
+/// Connects a TCP socket to port `port` of the IPv4 address `addr`, in host byte order, and
+/// returns its descriptor. The connection is established in the background, a read or write
+/// waits for it unless `flags` has `O_NONBLOCK`.
+fn sys_tcp_connect(process: &Process, addr: usize, port: usize, flags: usize) -> Result<usize> {
+    if flags & !O_NONBLOCK != 0 {
+        return Err(InternalError::InvalidParam);
+    }
+    let addr = u32::try_from(addr).map_err(|_| InternalError::InvalidParam)?;
+    let port = u16::try_from(port).map_err(|_| InternalError::InvalidParam)?;
+    let socket = Socket::connect(SocketAddr::new(Ipv4Addr::from(addr).into(), port))?;
+    let desc = FileDesc::new(Arc::new(socket), flags & O_NONBLOCK != 0);
+    Ok(process.with_fd_table(|fd_table| fd_table.insert(desc)))
+}
+
+fn read_name(process: &Process, name: usize, len: usize) -> Result<String> {
+    if len > SHM_NAME_MAX {
+        return Err(InternalError::InvalidSharedMemoryName);
+    }
+    let mut buf = vec![0u8; len];
+    copy_from_user(process, VirtAddr::new(name), &mut buf)?;
+    String::from_utf8(buf).map_err(|_| InternalError::InvalidSharedMemoryName)
+}
+
+/// Registers `handler` for `sig`, or restores the default action if it is 0, and returns the
+/// previous handler. The handler returns to `restorer`, which is expected to call sigreturn.
+fn sys_sigaction(process: &Process, sig: usize, handler: usize, restorer: usize) -> Result<usize> {
+    let action = (handler != 0).then_some(SigAction { handler, restorer });
+    let old = process.sigaction(sig, action)?;
+    Ok(old.map_or(0, |action| action.handler))
+}
+
+/// Returns from a signal handler, a process with a corrupted signal frame is terminated.
+fn sys_sigreturn(process: &Process, ctx: &mut Context) -> Result<usize> {
+    if process.sigreturn(ctx).is_err() {
+        process.set_exit_code(SIG_EXIT_BASE + SIGSEGV);
+    }
+    // the restored `a0` must survive setting the return value
+    Ok(ctx.syscall_args()[0])
+}
+
+/// Sends `sig` to the process behind `handle`, which requires the right to signal it.
+fn sys_kill(process: &Process, handle: usize, sig: usize) -> Result<usize> {
+    find_object(process, handle, Rights::SIGNAL)?
+        .process()?
+        .kill(sig)?;
+    Ok(0)
+}
+
+/// Raises `SIGALRM` in the process `timeout` microseconds from now.
+fn sys_alarm(process: &Process, timeout: usize) -> Result<usize> {
+    Process::find(process.id())?.alarm(Duration::from_micros(timeout as u64));
+    Ok(0)
+}
+
+/// Maps the `len` bytes of device registers at physical address `paddr` at `addr`, or anywhere
+/// if `addr` is 0. The whole pages spanned must belong to a device left for user space.
+fn sys_mmio_map(
+    process: &Process,
+    paddr: usize,
+    len: usize,
+    addr: usize,
+    prot: usize,
+) -> Result<usize> {
+    let perm = prot_to_perm(prot)?;
+    if perm.contains(PagePerm::X) {
+        return Err(InternalError::InvalidParam);
+    }
+    let paddr = PhysAddr::new(paddr);
+    let len = len
+        .checked_next_multiple_of(PAGE_SIZE)
+        .ok_or(InternalError::InvalidParam)?;
+    let dev = device::find_region(paddr, len)?;
+    debug!("process {} maps registers of {}", process.id(), dev.name());
+
+    let addr = (addr != 0).then_some(VirtAddr::new(addr));
+    let start =
+        process.with_addr_space(|addr_space| addr_space.map_device(addr, paddr, len, perm))?;
+    Ok(start.as_usize())
+}
+
+/// Claims the interrupt line `irq_num` of a device left for user space, returning a handle to it.
+fn sys_irq_claim(process: &Process, irq_num: usize) -> Result<usize> {
+    let line = IrqLine::claim(irq_num)?;
+    Ok(insert_handle(process, KernelObject::Irq(line)))
+}
+
+/// Blocks until the interrupt line behind `handle` is raised. The line stays masked until it is
+/// acknowledged.
+async fn sys_irq_wait(process: &Process, handle: usize) -> Result<usize> {
+    let line = find_object(process, handle, Rights::READ)?.irq()?;
+    line.wait().await;
+    Ok(0)
+}
+
+fn sys_irq_ack(process: &Process, handle: usize) -> Result<usize> {
+    let line = find_object(process, handle, Rights::WRITE)?.irq()?;
+    line.ack()?;
+    Ok(0)
+}
+
+fn read_msg(
+    process: &Process,
+    buf: usize,
+    len: usize,
+    words: [usize; MSG_WORDS],
+    handle: usize,
+) -> Result<Message> {
+    if len > MSG_DATA_MAX {
+        return Err(InternalError::InvalidParam);
+    }
+    let mut data = vec![0u8; len];
+    copy_from_user(process, VirtAddr::new(buf), &mut data)?;
+    let handle = if handle == HANDLE_NONE {
+        None
+    } else {
+        Some(process.with_handles(|handles| handles.remove(handle))?)
+    };
+    Ok(Message {
+        badge: process.id().into(),
+        words,
+        data,
+        handle,
+    })
+}
+
+/// Copies the buffer of `msg` to `buf`, truncated to `cap` bytes, and installs its handle, if any.
+///
+/// Returns the copied length along with the registers carrying the rest of the message: the badge
+/// in `a1`, the words in `a3..a4` and the installed handle in `a5`.
+fn write_msg(
+    process: &Process,
+    buf: usize,
+    cap: usize,
+    msg: Message,
+) -> Result<(usize, [usize; 6])> {
+    let len = msg.data.len().min(cap);
+    copy_to_user(process, VirtAddr::new(buf), &msg.data[..len])?;
+    let handle = msg.handle.map_or(HANDLE_NONE, |handle| {
+        process.with_handles(|handles| handles.insert(handle))
+    });
+    Ok((len, [0, msg.badge, 0, msg.words[0], msg.words[1], handle]))
+}
+
+fn insert_handle(process: &Process, object: KernelObject) -> usize {
+    process.with_handles(|handles| handles.insert(Handle::new(object, Rights::all())))
+}
+
+fn find_object(process: &Process, handle: usize, rights: Rights) -> Result<KernelObject> {
+    process.with_handles(|handles| handles.object(handle, rights))
+}
+
+/// Returns the rights needed to access memory with `perm`.
+fn perm_rights(perm: PagePerm) -> Rights {
+    let mut rights = Rights::empty();
+    if perm.intersects(PagePerm::R | PagePerm::X) {
+        rights |= Rights::READ;
+    }
+    if perm.contains(PagePerm::W) {
+        rights |= Rights::WRITE;
+    }
+    rights
+}
+
+/// Returns the permission memory may at most be accessed with under `rights`, the inverse of
+/// [`perm_rights`].
+fn rights_perm(rights: Rights) -> PagePerm {
+    let mut perm = PagePerm::empty();
+    if rights.contains(Rights::READ) {
+        perm |= PagePerm::R | PagePerm::X;
+    }
+    if rights.contains(Rights::WRITE) {
+        perm |= PagePerm::W;
+    }
+    perm
+}
+
+fn prot_to_perm(prot: usize) -> Result<PagePerm> {
+    if prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+        return Err(InternalError::InvalidParam);
+    }
+    let mut perm = PagePerm::empty();
+    if prot & PROT_READ != 0 {
+        perm |= PagePerm::R;
+    }
+    if prot & PROT_WRITE != 0 {
+        perm |= PagePerm::R | PagePerm::W;
+    }
+    if prot & PROT_EXEC != 0 {
+        perm |= PagePerm::X;
+    }
+    Ok(perm)
+}
@@ -0,0 +1,88 @@
+//! One-shot timers processes can arm and wait on.
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    future::poll_fn,
+    mem,
+    task::{Poll, Waker},
+    time::Duration,
+};
+
+use jrinx_hal::{hal, Hal, Interrupt};
+use jrinx_timed_event::{TimedEvent, TimedEventHandler, TimedEventTracker};
+use spin::Mutex;
+
+pub struct Timer {
+    state: Mutex<TimerState>,
+}
+
+#[derive(Default)]
+struct TimerState {
+    tracker: Option<TimedEventTracker>,
+    fired: bool,
+    waiters: Vec<Waker>,
+}
+
+impl Timer {
+    pub fn create() -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(TimerState::default()),
+        })
+    }
+
+    /// Arms the timer to fire at `deadline`, disarming it first if it is already armed.
+    pub fn set(self: &Arc<Self>, deadline: Duration) {
+        // the timer fires in interrupt context, which must not find the state locked
+        hal!().interrupt().with_saved_off(|| {
+            let mut state = self.state.lock();
+            if let Some(tracker) = state.tracker.take() {
+                let _ = tracker.cancel();
+            }
+            state.fired = false;
+
+            let timer = Arc::downgrade(self);
+            state.tracker = Some(TimedEvent::create(
+                deadline,
+                TimedEventHandler::new(move || Self::fire(timer), || {}),
+            ));
+        });
+    }
+
+    pub fn fired(&self) -> bool {
+        hal!()
+            .interrupt()
+            .with_saved_off(|| self.state.lock().fired)
+    }
+
+    /// Waits until the timer fires, returning at once if it has already fired since it was set.
+    pub async fn wait(&self) {
+        poll_fn(|cx| {
+            hal!().interrupt().with_saved_off(|| {
+                let mut state = self.state.lock();
+                if state.fired {
+                    Poll::Ready(())
+                } else {
+                    state.waiters.push(cx.waker().clone());
+                    Poll::Pending
+                }
+            })
+        })
+        .await
+    }
+
+    fn fire(timer: Weak<Self>) {
+        let Some(timer) = timer.upgrade() else {
+            return;
+        };
+        let waiters = {
+            let mut state = timer.state.lock();
+            state.fired = true;
+            state.tracker = None;
+            mem::take(&mut state.waiters)
+        };
+        waiters.into_iter().for_each(Waker::wake);
+    }
+}
@@ -0,0 +1,71 @@
+//! Copies between the kernel and the memory of a process through its own user addresses.
+//!
+//! Ranges are checked against the address space of the process first, a fault that slips through
+//! nevertheless fails the copy instead of faulting the kernel.
+
+use jrinx_addr::VirtAddr;
+use jrinx_config::PAGE_SIZE;
+use jrinx_error::Result;
+use jrinx_paging::{GenericPagePerm, GenericPageTable, PagePerm};
+use jrinx_trap::uaccess;
+use jrinx_vmm::addr_space::AddrSpace;
+
+use crate::Process;
+
+/// Copies `buf.len()` bytes at `addr` of `process` into `buf`.
+pub fn copy_from_user(process: &Process, addr: VirtAddr, buf: &mut [u8]) -> Result<()> {
+    with_user_access(process, |addr_space| {
+        addr_space.check_access(addr, buf.len(), PagePerm::R)?;
+        unsafe { uaccess::copy(buf.as_mut_ptr(), addr.as_usize() as *const u8, buf.len()) }
+    })
+}
+
+/// Copies `data` to `addr` of `process`.
+pub fn copy_to_user(process: &Process, addr: VirtAddr, data: &[u8]) -> Result<()> {
+    with_user_access(process, |addr_space| {
+        addr_space.check_access(addr, data.len(), PagePerm::W)?;
+        unsafe { uaccess::copy(addr.as_usize() as *mut u8, data.as_ptr(), data.len()) }
+    })
+}
+
+/// Checks that `len` bytes at `addr` of `process` can be copied to, before something is consumed
+/// to be copied there.
+pub fn check_user_writable(process: &Process, addr: VirtAddr, len: usize) -> Result<()> {
+    process.with_addr_space(|addr_space| addr_space.check_access(addr, len, PagePerm::W))
+}
+
+/// Copies the NUL-terminated string at `addr` of `process` into `buf`, returning its length
+/// without the NUL, or `buf.len()` if it does not fit.
+pub fn strncpy_from_user(process: &Process, addr: VirtAddr, buf: &mut [u8]) -> Result<usize> {
+    with_user_access(process, |addr_space| {
+        // the length is unknown up front, every page is checked before it is read
+        let mut copied = 0;
+        while copied < buf.len() {
+            let cur = addr + copied;
+            let len = (buf.len() - copied).min(PAGE_SIZE - (cur.as_usize() & (PAGE_SIZE - 1)));
+            addr_space.check_access(cur, len, PagePerm::R)?;
+            let found = unsafe {
+                uaccess::strncpy(&mut buf[copied..copied + len], cur.as_usize() as *const u8)
+            }?;
+            copied += found;
+            if found < len {
+                break;
+            }
+        }
+        Ok(copied)
+    })
+}
+
+/// Runs `f` with the address space of `process` active, as the caller may have been parked and
+/// another one activated in the meantime. The kernel page table is active again afterwards.
+fn with_user_access<F, R>(process: &Process, f: F) -> Result<R>
+where
+    F: FnOnce(&mut AddrSpace) -> Result<R>,
+{
+    process.with_addr_space(|addr_space| {
+        addr_space.activate();
+        let result = f(addr_space);
+        jrinx_vmm::activate_kernel();
+        result
+    })
+}
@@ -290,6 +290,9 @@ core::arch::global_asm! {
         j trap_exit
 
     trap_from_user_ed:
+        li t0, 1 << 18
+        csrc sstatus, t0
+
         POP_REG sp, 0
 
         POP_REG s0, 0 * XLENB
@@ -1,5 +1,8 @@
 mod entry;
-use crate::{breakpoint, soft_int, timer_int, GenericContext, TrapReason};
+mod uaccess;
+pub(crate) use uaccess::{copy_user, exception_fixup, strncpy_user};
+
+use crate::{breakpoint, soft_int, timer_int, uaccess as user, GenericContext, TrapReason};
 use jrinx_addr::VirtAddr;
 use jrinx_hal::{hal, Cpu, Hal};
 use jrinx_paging::{GenericPagePerm, PagePerm};
@@ -133,7 +136,7 @@ impl GenericContext for Context {
 
     fn user_setup(&mut self, entry_point: usize, stack_top: usize) {
         self.regs.sp = stack_top;
-        self.sstatus = 1 << 18 | (FS::Initial as usize) << 13 | (SPP::User as usize) << 8 | 1 << 5; // sum | fs | spp | spie
+        self.sstatus = (FS::Initial as usize) << 13 | (SPP::User as usize) << 8 | 1 << 5; // fs | spp | spie
         self.sepc = entry_point;
         self.enable_int();
     }
@@ -147,7 +150,10 @@ impl GenericContext for Context {
     }
 
     fn pc_advance(&mut self) {
-        let is_rvc = (unsafe { (self.sepc as *const u8).read() & 0b11 }) != 0b11;
+        // the trapping instruction may be in user memory
+        let mut insn = 0u8;
+        let is_rvc = unsafe { user::copy(&mut insn, self.sepc as *const u8, 1) }.is_ok()
+            && insn & 0b11 != 0b11;
         if is_rvc {
             self.sepc += 2;
         } else {
@@ -159,6 +165,67 @@ impl GenericContext for Context {
         self.regs.a7
     }
 
+    fn syscall_args(&self) -> [usize; 6] {
+        [
+            self.regs.a0,
+            self.regs.a1,
+            self.regs.a2,
+            self.regs.a3,
+            self.regs.a4,
+            self.regs.a5,
+        ]
+    }
+
+    fn set_syscall_args(&mut self, args: [usize; 6]) {
+        [
+            self.regs.a0,
+            self.regs.a1,
+            self.regs.a2,
+            self.regs.a3,
+            self.regs.a4,
+            self.regs.a5,
+        ] = args;
+    }
+
+    fn set_syscall_ret(&mut self, ret: usize) {
+        self.regs.a0 = ret;
+    }
+
+    fn pc(&self) -> usize {
+        self.sepc
+    }
+
+    fn set_pc(&mut self, pc: usize) {
+        self.sepc = pc;
+    }
+
+    fn sp(&self) -> usize {
+        self.regs.sp
+    }
+
+    fn set_sp(&mut self, sp: usize) {
+        self.regs.sp = sp;
+    }
+
+    fn set_ra(&mut self, ra: usize) {
+        self.regs.ra = ra;
+    }
+
+    fn gregs(&self) -> [usize; 32] {
+        let mut gregs: [usize; 32] = unsafe { core::mem::transmute(self.regs) };
+        gregs[0] = self.sepc;
+        gregs
+    }
+
+    fn restore_user(&mut self, saved: &Self) {
+        self.regs = Register {
+            zero: 0,
+            ..saved.regs
+        };
+        self.fregs = saved.fregs;
+        self.sepc = saved.sepc;
+    }
+
     fn run(&mut self) {
         extern "C" {
             fn run_user(ctx: &mut Context);
@@ -184,6 +251,10 @@ extern "C" fn handle_kern_trap(ctx: &mut Context) {
         TrapReason::Breakpoint { addr: _ } => breakpoint::handle(ctx),
         TrapReason::SoftwareInterrupt => soft_int::handle(ctx),
         TrapReason::TimerInterrupt => timer_int::handle(ctx),
+        TrapReason::PageFault { addr, .. } => match user::fixup(ctx.sepc) {
+            Some(fixup) => ctx.sepc = fixup,
+            None => panic!("kernel page fault at {:#x} accessing {}", ctx.sepc, addr),
+        },
         _ => {
             let handle_start_time = GLOBAL_INTC.get().unwrap().handle_irq(0);
             let trap_finished_time = hal!().cpu().get_time();
@@ -0,0 +1,91 @@
+//! Copy routines that touch user memory with `sstatus.SUM` set only for the duration of the copy.
+//!
+//! Every instruction that may fault on a user address is listed in [`EXCEPTION_TABLE`] together
+//! with the address the kernel trap handler resumes at, so that a bad user pointer makes the
+//! routine fail instead of faulting the kernel.
+
+core::arch::global_asm! {
+    r#"
+    .equ SSTATUS_SUM, 1 << 18
+
+    .pushsection .text.uaccess, "ax"
+    .global copy_user
+    copy_user:
+        li t6, SSTATUS_SUM
+        csrs sstatus, t6
+        beqz a2, copy_user_done
+    copy_user_loop:
+    .global copy_user_load
+    copy_user_load:
+        lb t0, 0(a1)
+    .global copy_user_store
+    copy_user_store:
+        sb t0, 0(a0)
+        addi a0, a0, 1
+        addi a1, a1, 1
+        addi a2, a2, -1
+        bnez a2, copy_user_loop
+    .global copy_user_done
+    copy_user_done:
+        csrc sstatus, t6
+        mv a0, a2
+        ret
+
+    .global strncpy_user
+    strncpy_user:
+        li t6, SSTATUS_SUM
+        csrs sstatus, t6
+        li t1, 0
+        beqz a2, strncpy_user_done
+    strncpy_user_loop:
+    .global strncpy_user_load
+    strncpy_user_load:
+        lb t0, 0(a1)
+        sb t0, 0(a0)
+        beqz t0, strncpy_user_done
+        addi a0, a0, 1
+        addi a1, a1, 1
+        addi t1, t1, 1
+        bne t1, a2, strncpy_user_loop
+    strncpy_user_done:
+        csrc sstatus, t6
+        mv a0, t1
+        ret
+    .global strncpy_user_fault
+    strncpy_user_fault:
+        csrc sstatus, t6
+        li a0, -1
+        ret
+    .popsection
+    "#
+}
+
+extern "C" {
+    /// Copies `len` bytes from `src` to `dst`, returning the number of bytes left uncopied.
+    pub(crate) fn copy_user(dst: *mut u8, src: *const u8, len: usize) -> usize;
+
+    /// Copies a NUL-terminated string of at most `max` bytes from `src` to `dst`, returning its
+    /// length, `max` if no NUL is found, or `usize::MAX` on a fault.
+    pub(crate) fn strncpy_user(dst: *mut u8, src: *const u8, max: usize) -> usize;
+
+    fn copy_user_load();
+    fn copy_user_store();
+    fn copy_user_done();
+    fn strncpy_user_load();
+    fn strncpy_user_fault();
+}
+
+/// Pairs of a faulting instruction and the address to resume at.
+static EXCEPTION_TABLE: [(unsafe extern "C" fn(), unsafe extern "C" fn()); 3] = [
+    (copy_user_load, copy_user_done),
+    (copy_user_store, copy_user_done),
+    (strncpy_user_load, strncpy_user_fault),
+];
+
+/// Returns the address to resume at after a fault at `pc`, if the fault is recoverable.
+pub(crate) fn exception_fixup(pc: usize) -> Option<usize> {
+    EXCEPTION_TABLE
+        .iter()
+        .find(|&&(insn, _)| insn as usize == pc)
+        .map(|&(_, fixup)| fixup as usize)
+}
@@ -9,6 +9,7 @@ pub mod arch;
 pub mod breakpoint;
 pub mod soft_int;
 pub mod timer_int;
+pub mod uaccess;
 
 use core::fmt::Debug;
 
@@ -31,6 +32,30 @@ pub trait GenericContext: Debug + Clone + Copy {
 
     fn syscall_num(&self) -> usize;
 
+    fn syscall_args(&self) -> [usize; 6];
+
+    fn set_syscall_args(&mut self, args: [usize; 6]);
+
+    fn set_syscall_ret(&mut self, ret: usize);
+
+    fn pc(&self) -> usize;
+
+    fn set_pc(&mut self, pc: usize);
+
+    fn sp(&self) -> usize;
+
+    fn set_sp(&mut self, sp: usize);
+
+    fn set_ra(&mut self, ra: usize);
+
+    /// Returns the program counter followed by every general purpose register but the hardwired
+    /// zero, in the order of `elf_gregset_t`.
+    fn gregs(&self) -> [usize; 32];
+
+    /// Restores the registers a user program may change from `saved`, leaving the privileged
+    /// state, e.g. the status and interrupt enable registers, untouched.
+    fn restore_user(&mut self, saved: &Self);
+
     fn user_setup(&mut self, entry_point: usize, stack_top: usize);
 
     fn enable_int(&mut self);
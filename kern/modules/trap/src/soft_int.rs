@@ -12,7 +12,9 @@ pub fn handle(ctx: &mut impl GenericContext) {
 
     *SOFT_INT_COUNTER.write() += 1;
 
+    // cleared first, so that a shootdown requested while handling the others raises it again
     hal!().interrupt().clr_soft();
+    jrinx_vmm::tlb::handle();
 }
 pub fn software_interrupt_handler() {
     let mut counter = SOFT_INT_COUNTER.write();
@@ -0,0 +1,40 @@
+//! Raw accesses to user memory that fail instead of faulting the kernel.
+//!
+//! These neither check the addresses they are given nor activate any address space, the caller is
+//! expected to do both.
+
+use jrinx_error::{InternalError, Result};
+
+use crate::arch;
+
+/// Copies `len` bytes from `src` to `dst`, either of which may be a user address.
+///
+/// # Safety
+///
+/// Kernel addresses among `src` and `dst` must be valid for `len` bytes.
+pub unsafe fn copy(dst: *mut u8, src: *const u8, len: usize) -> Result<()> {
+    if arch::copy_user(dst, src, len) == 0 {
+        Ok(())
+    } else {
+        Err(InternalError::InvalidVirtAddr)
+    }
+}
+
+/// Copies a NUL-terminated string at user address `src` into `dst`, returning its length without
+/// the NUL, or `dst.len()` if it does not fit.
+///
+/// # Safety
+///
+/// `src` must not be a kernel address.
+pub unsafe fn strncpy(dst: &mut [u8], src: *const u8) -> Result<usize> {
+    match arch::strncpy_user(dst.as_mut_ptr(), src, dst.len()) {
+        usize::MAX => Err(InternalError::InvalidVirtAddr),
+        len => Ok(len),
+    }
+}
+
+/// Returns the address to resume at after the kernel faults at `pc`, if it faulted while
+/// accessing user memory.
+pub(crate) fn fixup(pc: usize) -> Option<usize> {
+    arch::exception_fixup(pc)
+}
@@ -0,0 +1,34 @@
+//! Base64 encoding with the standard alphabet and padding, as described in RFC 4648.
+
+use alloc::string::String;
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Returns the length of the encoding of `len` bytes.
+pub const fn encoded_len(len: usize) -> usize {
+    (len + 2) / 3 * 4
+}
+
+/// Appends the encoding of `data` to `out`.
+pub fn encode_into(data: &[u8], out: &mut String) {
+    out.reserve(encoded_len(data.len()));
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+}
+
+pub fn encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    encode_into(data, &mut out);
+    out
+}
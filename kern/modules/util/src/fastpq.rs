@@ -1,4 +1,4 @@
-use alloc::collections::VecDeque;
+use alloc::collections::{TryReserveError, VecDeque};
 use spin::Mutex;
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -42,6 +42,14 @@ impl<P: Clone + Copy + Into<FastPriority>, I> FastPriorityQueue<P, I> {
         self.queues[pri as usize].push_back((priority, item));
     }
 
+    /// Enqueues like [`Self::enqueue`], but fails instead of aborting if the queue of `priority`
+    /// cannot grow.
+    pub fn try_enqueue(&mut self, priority: P, item: I) -> Result<(), TryReserveError> {
+        self.queues[priority.into().0 as usize].try_reserve(1)?;
+        self.enqueue(priority, item);
+        Ok(())
+    }
+
     pub fn dequeue(&mut self) -> Option<(P, I)> {
         if self.bits == 0 {
             return None;
@@ -73,6 +81,10 @@ impl<P: Clone + Copy + Into<FastPriority>, I> FastPriorityQueueWithLock<P, I> {
         self.inner.lock().enqueue(priority, item);
     }
 
+    pub fn try_enqueue(&self, priority: P, item: I) -> Result<(), TryReserveError> {
+        self.inner.lock().try_enqueue(priority, item)
+    }
+
     pub fn dequeue(&self) -> Option<(P, I)> {
         self.inner.lock().dequeue()
     }
@@ -3,6 +3,7 @@
 
 extern crate alloc;
 
+pub mod base64;
 pub mod color;
 pub mod fastpq;
 pub mod interval;
@@ -0,0 +1,584 @@
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+
+use jrinx_addr::{PhysAddr, VirtAddr};
+use jrinx_config::{PAGE_SIZE, USER_MMAP_REGION, USER_STACK_TOP};
+use jrinx_error::{InternalError, Result};
+use jrinx_hal::{hal, Hal, Vm};
+use jrinx_paging::{common::PageTable, GenericPagePerm, GenericPageTable, PagePerm};
+use jrinx_phys_frame::PhysFrame;
+use jrinx_util::interval::{Bound, ExclusiveIntervals};
+
+use crate::{
+    asid::Asid,
+    shm::{SharedMapping, SharedMemory},
+    KERN_PAGE_TABLE,
+};
+
+#[derive(Debug, Clone)]
+pub struct VirtMemArea {
+    start: VirtAddr,
+    end: VirtAddr,
+    perm: PagePerm,
+    max_perm: PagePerm,
+    shared: Option<Arc<SharedMapping>>,
+    device: bool,
+}
+
+impl VirtMemArea {
+    pub fn start(&self) -> VirtAddr {
+        self.start
+    }
+
+    pub fn end(&self) -> VirtAddr {
+        self.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn perm(&self) -> PagePerm {
+        self.perm
+    }
+
+    /// Returns the permission the area may at most be given by [`AddrSpace::protect`].
+    pub fn max_perm(&self) -> PagePerm {
+        self.max_perm
+    }
+
+    pub fn shared(&self) -> Option<&Arc<SharedMemory>> {
+        self.shared.as_ref().map(|mapping| mapping.shm())
+    }
+
+    /// Returns whether the area maps device registers rather than memory.
+    pub fn device(&self) -> bool {
+        self.device
+    }
+
+    fn contains(&self, addr: VirtAddr) -> bool {
+        self.start <= addr && addr < self.end
+    }
+
+    fn mergeable(&self, other: &Self) -> bool {
+        self.perm == other.perm
+            && self.max_perm == other.max_perm
+            && self.shared.is_none()
+            && other.shared.is_none()
+            && !self.device
+            && !other.device
+    }
+}
+
+/// A user address space, made up of non-overlapping virtual memory areas backed by a page table.
+pub struct AddrSpace {
+    page_table: PageTable,
+    areas: BTreeMap<VirtAddr, VirtMemArea>,
+    heap_start: VirtAddr,
+    brk: VirtAddr,
+    asid: Asid,
+}
+
+impl AddrSpace {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            page_table: PageTable::new()?,
+            areas: BTreeMap::new(),
+            heap_start: VirtAddr::new(0),
+            brk: VirtAddr::new(0),
+            asid: Asid::default(),
+        })
+    }
+
+    /// Switches to this address space, bringing its kernel half up to date first.
+    pub fn activate(&mut self) {
+        let stale = self.page_table.sync_kernel(&KERN_PAGE_TABLE.read());
+        let asid = self.asid.activate(stale);
+        hal!().vm().enable(self.page_table.addr(), asid);
+    }
+
+    /// Flushes the translations of this address space from the TLB, which is needed after its page
+    /// table has been changed other than by mapping, unmapping or protecting areas, which flush
+    /// what they change themselves.
+    pub fn flush_tlb(&mut self) {
+        self.asid.flush();
+    }
+
+    /// Returns the ASID this address space was last activated with, 0 if it has none.
+    pub fn asid(&self) -> usize {
+        self.asid.asid()
+    }
+
+    pub fn page_table(&self) -> &PageTable {
+        &self.page_table
+    }
+
+    pub fn page_table_mut(&mut self) -> &mut PageTable {
+        &mut self.page_table
+    }
+
+    pub fn areas(&self) -> impl Iterator<Item = &VirtMemArea> {
+        self.areas.values()
+    }
+
+    pub fn area(&self, addr: VirtAddr) -> Option<&VirtMemArea> {
+        self.areas
+            .range(..=addr)
+            .next_back()
+            .map(|(_, area)| area)
+            .filter(|area| area.contains(addr))
+    }
+
+    /// Records an area whose pages have already been mapped into the page table, e.g. a loaded
+    /// ELF image.
+    pub fn insert(&mut self, start: VirtAddr, end: VirtAddr, perm: PagePerm) -> Result<()> {
+        let end = end.align_page_up();
+        Self::check_range(start, end)?;
+        if !self.is_free(start, end) {
+            return Err(InternalError::InvalidVirtAddr);
+        }
+        self.insert_area(VirtMemArea {
+            start,
+            end,
+            perm,
+            max_perm: PagePerm::R | PagePerm::W | PagePerm::X,
+            shared: None,
+            device: false,
+        });
+        Ok(())
+    }
+
+    /// Maps an anonymous zero-filled area of `len` bytes at `addr`, or anywhere in the mmap region
+    /// if `addr` is `None`, and returns its start address.
+    pub fn map(&mut self, addr: Option<VirtAddr>, len: usize, perm: PagePerm) -> Result<VirtAddr> {
+        let (start, end) = match addr {
+            Some(addr) => {
+                let (start, end) = Self::page_range(addr, len)?;
+                if !self.is_free(start, end) {
+                    return Err(InternalError::InvalidVirtAddr);
+                }
+                (start, end)
+            }
+            None => {
+                let len = Self::page_len(len)?;
+                let start = self.find_free(len)?;
+                (start, start + len)
+            }
+        };
+
+        self.map_pages(start, end, perm)?;
+        self.insert_area(VirtMemArea {
+            start,
+            end,
+            perm,
+            max_perm: PagePerm::R | PagePerm::W | PagePerm::X,
+            shared: None,
+            device: false,
+        });
+        Ok(start)
+    }
+
+    /// Maps an anonymous zero-filled area of `len` bytes at `addr`, replacing whatever is mapped in
+    /// the range. Nothing is changed if it fails.
+    pub fn map_fixed(&mut self, addr: VirtAddr, len: usize, perm: PagePerm) -> Result<VirtAddr> {
+        let (start, end) = Self::page_range(addr, len)?;
+
+        // the frames and tables are allocated before the old pages are overwritten
+        self.map_pages(start, end, perm)?;
+        self.remove_areas(start, end);
+        self.insert_area(VirtMemArea {
+            start,
+            end,
+            perm,
+            max_perm: PagePerm::R | PagePerm::W | PagePerm::X,
+            shared: None,
+            device: false,
+        });
+        Ok(start)
+    }
+
+    /// Maps the whole of `shm` at `addr`, or anywhere in the mmap region if `addr` is `None`, and
+    /// returns its start address. Every mapping of a shared memory object has its own permission,
+    /// which can never be raised beyond `max_perm`, e.g. the access granted by a handle.
+    pub fn map_shared(
+        &mut self,
+        addr: Option<VirtAddr>,
+        shm: Arc<SharedMemory>,
+        perm: PagePerm,
+        max_perm: PagePerm,
+    ) -> Result<VirtAddr> {
+        if !max_perm.contains(perm) {
+            return Err(InternalError::InsufficientRights);
+        }
+        let start = match addr {
+            Some(addr) => {
+                let (start, end) = Self::page_range(addr, shm.len())?;
+                if !self.is_free(start, end) {
+                    return Err(InternalError::InvalidVirtAddr);
+                }
+                start
+            }
+            None => self.find_free(shm.len())?,
+        };
+        let end = start + shm.len();
+
+        let flush = self
+            .page_table
+            .map_range(start, shm.frames(), perm | PagePerm::U)?;
+        self.asid.flush_pages(flush);
+        self.insert_area(VirtMemArea {
+            start,
+            end,
+            perm,
+            max_perm,
+            shared: Some(Arc::new(SharedMapping::new(shm))),
+            device: false,
+        });
+        Ok(start)
+    }
+
+    /// Maps the `len` bytes of device registers at `paddr` at `addr`, or anywhere in the mmap
+    /// region if `addr` is `None`, and returns its start address. Device registers can never be
+    /// made executable.
+    ///
+    /// The caller is responsible for checking that the registers may be handed out.
+    pub fn map_device(
+        &mut self,
+        addr: Option<VirtAddr>,
+        paddr: PhysAddr,
+        len: usize,
+        perm: PagePerm,
+    ) -> Result<VirtAddr> {
+        if paddr.as_usize() & (PAGE_SIZE - 1) != 0 {
+            return Err(InternalError::InvalidParam);
+        }
+        let start = match addr {
+            Some(addr) => {
+                let (start, end) = Self::page_range(addr, len)?;
+                if !self.is_free(start, end) {
+                    return Err(InternalError::InvalidVirtAddr);
+                }
+                start
+            }
+            None => self.find_free(Self::page_len(len)?)?,
+        };
+        let end = start + Self::page_len(len)?;
+
+        let frames = (0..end - start)
+            .step_by(PAGE_SIZE)
+            .map(|offset| PhysFrame::device(paddr + offset))
+            .collect::<Vec<_>>();
+        let flush = self
+            .page_table
+            .map_range(start, &frames, perm | PagePerm::U)?;
+        self.asid.flush_pages(flush);
+        self.insert_area(VirtMemArea {
+            start,
+            end,
+            perm,
+            max_perm: PagePerm::R | PagePerm::W,
+            shared: None,
+            device: true,
+        });
+        Ok(start)
+    }
+
+    /// Unmaps every page in `[addr, addr + len)`, splitting the areas that straddle its bounds.
+    pub fn unmap(&mut self, addr: VirtAddr, len: usize) -> Result<()> {
+        let (addr, end) = Self::page_range(addr, len)?;
+
+        // the areas are kept if the pages cannot be unmapped
+        let flush = self.page_table.unmap_range(addr, end - addr)?;
+        self.asid.flush_pages(flush);
+        self.remove_areas(addr, end);
+        Ok(())
+    }
+
+    /// Changes the permission of every page in `[addr, addr + len)`, which must be fully covered
+    /// by areas whose maximum permission includes `perm`.
+    pub fn protect(&mut self, addr: VirtAddr, len: usize, perm: PagePerm) -> Result<()> {
+        let (addr, end) = Self::page_range(addr, len)?;
+
+        let mut cursor = addr;
+        while cursor < end {
+            let area = self.area(cursor).ok_or(InternalError::InvalidVirtAddr)?;
+            if !area.max_perm.contains(perm) {
+                return Err(InternalError::InsufficientRights);
+            }
+            cursor = area.end;
+        }
+
+        // nothing is changed if any page cannot be protected, so the areas are updated afterwards
+        let flush = self
+            .page_table
+            .protect_range(addr, end - addr, perm | PagePerm::U)?;
+        self.split_at(addr);
+        self.split_at(end);
+        for (_, area) in self.areas.range_mut(addr..end) {
+            area.perm = perm;
+        }
+        self.asid.flush_pages(flush);
+        Ok(())
+    }
+
+    /// Sets the start of the heap, usually right after the loaded image.
+    pub fn init_brk(&mut self, addr: VirtAddr) {
+        self.heap_start = addr.align_page_up();
+        self.brk = self.heap_start;
+    }
+
+    pub fn brk(&self) -> VirtAddr {
+        self.brk
+    }
+
+    /// Moves the program break to `brk`, mapping or unmapping the pages in between.
+    pub fn set_brk(&mut self, brk: VirtAddr) -> Result<VirtAddr> {
+        if brk < self.heap_start {
+            return Err(InternalError::InvalidParam);
+        } else if brk.as_usize() > USER_MMAP_REGION.addr {
+            return Err(InternalError::NotEnoughMem);
+        }
+        let old_end = self.brk.align_page_up();
+        let new_end = brk.align_page_up();
+        if new_end > old_end {
+            self.map(Some(old_end), new_end - old_end, PagePerm::R | PagePerm::W)?;
+        } else if new_end < old_end {
+            self.unmap(new_end, old_end - new_end)?;
+        }
+        self.brk = brk;
+        Ok(brk)
+    }
+
+    /// Creates a copy-on-write duplicate of this address space.
+    ///
+    /// The caller is responsible for flushing the TLB of this address space.
+    pub fn fork(&mut self) -> Result<Self> {
+        let mut page_table = self.page_table.fork()?;
+
+        // shared memory and device registers stay shared instead of being copied on write
+        for area in self
+            .areas
+            .values()
+            .filter(|area| area.shared.is_some() || area.device)
+        {
+            for page in (area.start.as_usize()..area.end.as_usize()).step_by(PAGE_SIZE) {
+                let page = VirtAddr::new(page);
+                self.page_table.protect(page, area.perm | PagePerm::U)?;
+                page_table.protect(page, area.perm | PagePerm::U)?;
+            }
+        }
+
+        Ok(Self {
+            page_table,
+            areas: self.areas.clone(),
+            heap_start: self.heap_start,
+            brk: self.brk,
+            asid: Asid::default(),
+        })
+    }
+
+    /// Resolves a page fault at `addr`, only write faults on copy-on-write pages of writable areas
+    /// can be resolved.
+    pub fn handle_page_fault(&mut self, addr: VirtAddr, perm: PagePerm) -> Result<()> {
+        let area = self.area(addr).ok_or(InternalError::InvalidVirtAddr)?;
+        if perm == PagePerm::W && area.perm.contains(PagePerm::W) {
+            self.page_table.resolve_cow(addr)
+        } else {
+            Err(InternalError::InvalidVirtAddr)
+        }
+    }
+
+    /// Checks that `[addr, addr + len)` lies in memory areas granting `perm`, so that the kernel
+    /// may access the range through its user addresses.
+    ///
+    /// If `perm` includes `W`, copy-on-write sharing of the pages in the range is broken up front.
+    pub fn check_access(&mut self, addr: VirtAddr, len: usize, perm: PagePerm) -> Result<()> {
+        let end = addr
+            .as_usize()
+            .checked_add(len)
+            .ok_or(InternalError::InvalidVirtAddr)?;
+        let mut cursor = addr;
+        while cursor.as_usize() < end {
+            let area = self.area(cursor).ok_or(InternalError::InvalidVirtAddr)?;
+            if area.device || !area.perm.contains(perm) {
+                return Err(InternalError::InvalidVirtAddr);
+            }
+            cursor = area.end;
+        }
+
+        if perm.contains(PagePerm::W) {
+            for page in (addr.align_page_down().as_usize()..end).step_by(PAGE_SIZE) {
+                let page = VirtAddr::new(page);
+                let (_, pte_perm) = self.page_table.translate(page)?;
+                if !pte_perm.contains(PagePerm::W) {
+                    self.handle_page_fault(page, PagePerm::W)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies `buf.len()` bytes of readable user memory at `addr` into `buf`.
+    pub fn read_bytes(&self, addr: VirtAddr, buf: &mut [u8]) -> Result<()> {
+        let mut copied = 0;
+        while copied < buf.len() {
+            let cur = addr + copied;
+            self.check_memory(cur)?;
+            let (paddr, perm) = self.page_table.translate(cur)?;
+            if !perm.contains(PagePerm::U | PagePerm::R) {
+                return Err(InternalError::InvalidVirtAddr);
+            }
+            let len = (buf.len() - copied).min(PAGE_SIZE - (cur.as_usize() & (PAGE_SIZE - 1)));
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    paddr.to_virt().as_usize() as *const u8,
+                    buf[copied..].as_mut_ptr(),
+                    len,
+                );
+            }
+            copied += len;
+        }
+        Ok(())
+    }
+
+    /// Copies `data` into writable user memory at `addr`, breaking copy-on-write sharing on the
+    /// way.
+    pub fn write_bytes(&mut self, addr: VirtAddr, data: &[u8]) -> Result<()> {
+        let mut copied = 0;
+        while copied < data.len() {
+            let cur = addr + copied;
+            self.check_memory(cur)?;
+            let (_, perm) = self.page_table.translate(cur)?;
+            if !perm.contains(PagePerm::U) {
+                return Err(InternalError::InvalidVirtAddr);
+            } else if !perm.contains(PagePerm::W) {
+                self.handle_page_fault(cur, PagePerm::W)?;
+            }
+            let (paddr, _) = self.page_table.translate(cur)?;
+            let len = (data.len() - copied).min(PAGE_SIZE - (cur.as_usize() & (PAGE_SIZE - 1)));
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    data[copied..].as_ptr(),
+                    paddr.to_virt().as_usize() as *mut u8,
+                    len,
+                );
+            }
+            copied += len;
+        }
+        Ok(())
+    }
+
+    /// Device registers are not reachable through the linear mapping, the kernel therefore never
+    /// copies from or to them on behalf of a process.
+    fn check_memory(&self, addr: VirtAddr) -> Result<()> {
+        match self.area(addr) {
+            Some(area) if area.device => Err(InternalError::InvalidVirtAddr),
+            _ => Ok(()),
+        }
+    }
+
+    fn page_len(len: usize) -> Result<usize> {
+        match len.checked_add(PAGE_SIZE - 1) {
+            Some(len) if len >= PAGE_SIZE => Ok(len & !(PAGE_SIZE - 1)),
+            _ => Err(InternalError::InvalidParam),
+        }
+    }
+
+    fn page_range(addr: VirtAddr, len: usize) -> Result<(VirtAddr, VirtAddr)> {
+        let end = addr
+            .as_usize()
+            .checked_add(Self::page_len(len)?)
+            .ok_or(InternalError::InvalidVirtAddr)?;
+        let end = VirtAddr::new(end);
+        Self::check_range(addr, end)?;
+        Ok((addr, end))
+    }
+
+    fn check_range(start: VirtAddr, end: VirtAddr) -> Result<()> {
+        if start != start.align_page_down() || start >= end {
+            Err(InternalError::InvalidParam)
+        } else if start.as_usize() < PAGE_SIZE || end.as_usize() > USER_STACK_TOP {
+            Err(InternalError::InvalidVirtAddr)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn is_free(&self, start: VirtAddr, end: VirtAddr) -> bool {
+        self.areas
+            .range(..end)
+            .next_back()
+            .map_or(true, |(_, area)| area.end <= start)
+    }
+
+    fn find_free(&self, len: usize) -> Result<VirtAddr> {
+        let mut free =
+            ExclusiveIntervals::new([Bound::new(USER_MMAP_REGION.addr, USER_MMAP_REGION.len)]);
+        for area in self.areas.values() {
+            free -= Bound::new(area.start.as_usize(), area.len());
+        }
+        free.into_iter()
+            .map(<(usize, usize)>::from)
+            .find(|&(_, free_len)| free_len >= len)
+            .map(|(start, _)| VirtAddr::new(start))
+            .ok_or(InternalError::NotEnoughMem)
+    }
+
+    fn map_pages(&mut self, start: VirtAddr, end: VirtAddr, perm: PagePerm) -> Result<()> {
+        let frames = (0..end - start)
+            .step_by(PAGE_SIZE)
+            .map(|_| PhysFrame::alloc())
+            .collect::<Result<Vec<_>>>()?;
+        let flush = self
+            .page_table
+            .map_range(start, &frames, perm | PagePerm::U)?;
+        self.asid.flush_pages(flush);
+        Ok(())
+    }
+
+    fn insert_area(&mut self, area: VirtMemArea) {
+        let mut area = area;
+        if let Some((&start, prev)) = self.areas.range(..area.start).next_back() {
+            if prev.end == area.start && prev.mergeable(&area) {
+                area.start = start;
+                self.areas.remove(&start);
+            }
+        }
+        if let Some(next) = self.areas.get(&area.end).cloned() {
+            if next.mergeable(&area) {
+                area.end = next.end;
+                self.areas.remove(&next.start);
+            }
+        }
+        self.areas.insert(area.start, area);
+    }
+
+    fn remove_areas(&mut self, start: VirtAddr, end: VirtAddr) {
+        self.split_at(start);
+        self.split_at(end);
+        let starts = self
+            .areas
+            .range(start..end)
+            .map(|(&start, _)| start)
+            .collect::<Vec<_>>();
+        for start in starts {
+            self.areas.remove(&start);
+        }
+    }
+
+    fn split_at(&mut self, addr: VirtAddr) {
+        let Some(area) = self.area(addr).cloned() else {
+            return;
+        };
+        if area.start == addr {
+            return;
+        }
+        self.areas.get_mut(&area.start).unwrap().end = addr;
+        self.areas.insert(
+            addr,
+            VirtMemArea {
+                start: addr,
+                ..area
+            },
+        );
+    }
+}
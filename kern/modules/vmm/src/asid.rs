@@ -0,0 +1,174 @@
+//! Address space identifiers, which tag TLB entries so that switching address spaces does not
+//! flush the whole TLB.
+//!
+//! ASIDs are handed out in order within a generation. Once they run out, the generation is bumped
+//! and every CPU flushes its whole TLB before it activates an address space again, whose ASID is
+//! then reallocated if it belongs to an earlier generation. ASID 0 belongs to the kernel page
+//! table.
+//!
+//! Without ASIDs, every address space shares ASID 0 with the kernel, whose mappings are global.
+//! The TLB is then flushed as an address space is activated only if another one has been active on
+//! the CPU since, so that switching to it and back, e.g. to copy from user memory, costs nothing.
+
+use alloc::collections::BTreeMap;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use jrinx_hal::{hal, Cpu, Hal, Vm};
+use jrinx_paging::TlbFlush;
+use spin::{Mutex, Once};
+
+use crate::tlb::MAX_PAGES;
+
+/// The ASID of the kernel page table.
+pub const KERNEL: usize = 0;
+
+static ASID_BITS: Once<usize> = Once::new();
+
+static ALLOCATOR: Mutex<Allocator> = Mutex::new(Allocator {
+    generation: 0,
+    next: KERNEL + 1,
+});
+
+/// CPUs which have yet to flush their TLB since the last rollover, one bit each.
+static FLUSH_PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// The serial of the address space each CPU activated last, if there are no ASIDs.
+static ACTIVE: Mutex<BTreeMap<usize, u64>> = Mutex::new(BTreeMap::new());
+
+static NEXT_SERIAL: AtomicU64 = AtomicU64::new(1);
+
+struct Allocator {
+    generation: u64,
+    next: usize,
+}
+
+impl Allocator {
+    fn alloc(&mut self) -> (u64, usize) {
+        if self.next >> bits() != 0 {
+            self.generation += 1;
+            self.next = KERNEL + 1;
+            FLUSH_PENDING.store(usize::MAX, Ordering::SeqCst);
+        }
+        let asid = self.next;
+        self.next += 1;
+        (self.generation, asid)
+    }
+}
+
+/// The ASID of an address space, along with the CPU it was last activated on.
+#[derive(Debug, Default)]
+pub struct Asid {
+    generation: u64,
+    asid: usize,
+    cpu: Option<usize>,
+    /// Tells address spaces apart if there are no ASIDs, 0 until it is first activated.
+    serial: u64,
+}
+
+impl Asid {
+    /// Returns the ASID to activate the address space with on the current CPU, flushing what the
+    /// TLB of this CPU may hold for it that is out of date.
+    ///
+    /// `stale` tells that the page table has changed since it was last activated without being
+    /// flushed, e.g. its kernel half has just been brought up to date.
+    pub fn activate(&mut self, stale: bool) -> usize {
+        if bits() == 0 {
+            if self.serial == 0 {
+                self.serial = NEXT_SERIAL.fetch_add(1, Ordering::SeqCst);
+            }
+            let this = hal!().cpu().id();
+            let last = ACTIVE.lock().insert(this, self.serial);
+            if stale || last != Some(self.serial) {
+                hal!().vm().sync_all();
+            }
+            return KERNEL;
+        }
+
+        {
+            let mut allocator = ALLOCATOR.lock();
+            if self.asid == KERNEL || self.generation != allocator.generation {
+                (self.generation, self.asid) = allocator.alloc();
+                self.cpu = None;
+            }
+        }
+
+        let this = hal!().cpu().id();
+        if FLUSH_PENDING.fetch_and(!(1 << this), Ordering::SeqCst) & 1 << this != 0 {
+            hal!().vm().sync_all();
+        } else if stale || self.cpu.is_some_and(|cpu| cpu != this) {
+            hal!().vm().sync_asid(self.asid);
+        }
+        self.cpu = Some(this);
+        self.asid
+    }
+
+    /// Flushes the translations of the address space after its page table has changed.
+    ///
+    /// Only the CPU it was last activated on is flushed, others flush it once they activate it.
+    /// If that is not the current CPU, the ASID is given up instead, as a fresh one has nothing
+    /// cached anywhere.
+    pub fn flush(&mut self) {
+        let asid = self.asid;
+        self.invalidate(|| {
+            if bits() == 0 {
+                hal!().vm().sync_all();
+            } else {
+                hal!().vm().sync_asid(asid);
+            }
+        });
+    }
+
+    /// Flushes the translations listed in `flush` the way [`Self::flush`] flushes all of them,
+    /// which it falls back to if tables have been freed or there are too many pages.
+    pub fn flush_pages(&mut self, flush: TlbFlush) {
+        if flush.is_empty() {
+            return;
+        } else if flush.tables_freed || flush.addrs.len() > MAX_PAGES {
+            return self.flush();
+        }
+        self.invalidate(|| {
+            for &page in &flush.addrs {
+                hal!().vm().sync(page);
+            }
+        });
+    }
+
+    /// Runs `sync` if the TLB of the current CPU may hold translations of the address space, and
+    /// makes sure other CPUs drop theirs before they activate it again.
+    fn invalidate(&mut self, sync: impl FnOnce()) {
+        if bits() == 0 {
+            // other CPUs that activated it last flush once they activate it again
+            let this = hal!().cpu().id();
+            ACTIVE
+                .lock()
+                .retain(|&cpu, serial| cpu == this || *serial != self.serial);
+            sync();
+            return;
+        }
+
+        match self.cpu {
+            None => {}
+            Some(cpu) if cpu == hal!().cpu().id() => sync(),
+            Some(_) => *self = Self::default(),
+        }
+    }
+
+    pub fn asid(&self) -> usize {
+        self.asid
+    }
+}
+
+/// Probes the ASID bits of the CPU, called once the kernel page table is active.
+pub(crate) fn init() {
+    ASID_BITS.call_once(|| {
+        let bits = hal!().vm().asid_bits();
+        // probing runs with every ASID bit set for a moment
+        hal!().vm().sync_all();
+        bits
+    });
+}
+
+/// Returns the number of ASID bits, which may be none.
+pub fn bits() -> usize {
+    *ASID_BITS.get().unwrap_or(&0)
+}
@@ -1,12 +1,60 @@
 #![no_std]
 
+extern crate alloc;
+
+#[macro_use]
+extern crate log;
+
+pub mod addr_space;
+pub mod asid;
+pub mod shm;
+pub mod tlb;
+
+use alloc::vec::Vec;
+use jrinx_error::Result;
 use jrinx_hal::{hal, Hal, Vm};
-use jrinx_paging::{common::PageTable, GenericPageTable};
+use jrinx_paging::{common::PageTable, GenericPageTable, TlbFlush};
 use spin::{Lazy, RwLock};
 
 pub static KERN_PAGE_TABLE: Lazy<RwLock<PageTable>> =
     Lazy::new(|| RwLock::new(PageTable::new().unwrap()));
 
 pub fn init() {
-    hal!().vm().enable(KERN_PAGE_TABLE.read().addr());
+    activate_kernel();
+    asid::init();
+    tlb::online();
+}
+
+/// Switches to the kernel page table.
+///
+/// Nothing is flushed, as changes to the kernel page table are shot down on every CPU, whatever
+/// the address space active there.
+pub fn activate_kernel() {
+    hal!()
+        .vm()
+        .enable(KERN_PAGE_TABLE.read().addr(), asid::KERNEL);
+}
+
+/// Changes the kernel page table with `f`, then shoots down what it has changed on every CPU.
+///
+/// The shootdown waits until the page table is unlocked, as a CPU may need it before it can
+/// acknowledge.
+pub fn update_kern_page_table<F>(f: F) -> Result<()>
+where
+    F: FnOnce(&mut PageTable) -> Result<TlbFlush>,
+{
+    let flush = f(&mut KERN_PAGE_TABLE.write())?;
+    tlb::shootdown(flush.into());
+    Ok(())
+}
+
+/// Logs every mapping of the kernel page table, a line each in the style of `/proc/self/maps`.
+///
+/// The mappings are collected first, so that the page table is not locked while they are logged.
+pub fn dump_kern_maps() {
+    let mappings = KERN_PAGE_TABLE.read().mappings().collect::<Vec<_>>();
+    info!("kernel mappings:");
+    for mapping in mappings {
+        info!("{}", mapping);
+    }
 }
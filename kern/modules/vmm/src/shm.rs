@@ -0,0 +1,171 @@
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use jrinx_config::PAGE_SIZE;
+use jrinx_error::{InternalError, Result};
+use jrinx_paging::PagePerm;
+use jrinx_phys_frame::PhysFrame;
+use spin::RwLock;
+
+static SHARED_MEMORY_REGISTRY: RwLock<BTreeMap<String, Arc<SharedMemory>>> =
+    RwLock::new(BTreeMap::new());
+
+/// A named, zero-filled set of frames that can be mapped into several address spaces.
+///
+/// The object is released once neither a handle nor a mapping refers to it any more, which both
+/// count explicitly, its name may be released earlier by `unlink`.
+#[derive(Debug)]
+pub struct SharedMemory {
+    name: String,
+    frames: Vec<Arc<PhysFrame>>,
+    shared_perm: PagePerm,
+    maps: AtomicUsize,
+    refs: AtomicUsize,
+}
+
+impl SharedMemory {
+    /// Creates an object of `len` bytes named `name`, which whoever opens it by name may map with
+    /// at most `shared_perm`, and returns the first reference to it.
+    pub fn create(name: &str, len: usize, shared_perm: PagePerm) -> Result<SharedMemoryRef> {
+        if name.is_empty() {
+            return Err(InternalError::InvalidSharedMemoryName);
+        }
+        if len == 0 {
+            return Err(InternalError::InvalidParam);
+        }
+
+        let mut registry = SHARED_MEMORY_REGISTRY.write();
+        if registry.contains_key(name) {
+            return Err(InternalError::DuplicateSharedMemoryName);
+        }
+
+        let frames = (0..len.div_ceil(PAGE_SIZE))
+            .map(|_| PhysFrame::alloc())
+            .collect::<Result<Vec<_>>>()?;
+        let shm = Arc::new(Self {
+            name: String::from(name),
+            frames,
+            shared_perm,
+            maps: AtomicUsize::new(0),
+            refs: AtomicUsize::new(1),
+        });
+        registry.insert(shm.name.clone(), shm.clone());
+        Ok(SharedMemoryRef(shm))
+    }
+
+    /// Returns a new reference to the object named `name`.
+    pub fn open(name: &str) -> Result<SharedMemoryRef> {
+        // the count is taken under the registry lock, which releasing the object needs as well
+        let registry = SHARED_MEMORY_REGISTRY.read();
+        let shm = registry
+            .get(name)
+            .ok_or(InternalError::InvalidSharedMemoryName)?;
+        shm.refs.fetch_add(1, Ordering::SeqCst);
+        Ok(SharedMemoryRef(shm.clone()))
+    }
+
+    /// Returns the object named `name`, without keeping it alive.
+    pub fn find(name: &str) -> Result<Arc<Self>> {
+        SHARED_MEMORY_REGISTRY
+            .read()
+            .get(name)
+            .cloned()
+            .ok_or(InternalError::InvalidSharedMemoryName)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len() * PAGE_SIZE
+    }
+
+    pub fn frames(&self) -> &[Arc<PhysFrame>] {
+        &self.frames
+    }
+
+    /// Returns the permissions the object may at most be mapped with through a reference opened by
+    /// name.
+    pub fn shared_perm(&self) -> PagePerm {
+        self.shared_perm
+    }
+
+    /// Returns the number of mappings of the object.
+    pub fn maps(&self) -> usize {
+        self.maps.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of references to the object, i.e. of handles to it.
+    pub fn refs(&self) -> usize {
+        self.refs.load(Ordering::SeqCst)
+    }
+
+    /// Removes the name from the registry, existing mappings stay valid.
+    pub fn unlink(name: &str) -> Result<()> {
+        SHARED_MEMORY_REGISTRY
+            .write()
+            .remove(name)
+            .ok_or(InternalError::InvalidSharedMemoryName)?;
+        Ok(())
+    }
+
+    /// Drops a count of the object, and its name once neither count is left.
+    fn release(self: &Arc<Self>, count: &AtomicUsize) {
+        let mut registry = SHARED_MEMORY_REGISTRY.write();
+        count.fetch_sub(1, Ordering::SeqCst);
+        if self.maps() == 0
+            && self.refs() == 0
+            && registry
+                .get(&self.name)
+                .is_some_and(|shm| Arc::ptr_eq(shm, self))
+        {
+            registry.remove(&self.name);
+        }
+    }
+}
+
+/// A counted reference to a shared memory object, which handles to the object hold.
+#[derive(Debug)]
+pub struct SharedMemoryRef(Arc<SharedMemory>);
+
+impl SharedMemoryRef {
+    pub fn shm(&self) -> &Arc<SharedMemory> {
+        &self.0
+    }
+}
+
+impl Clone for SharedMemoryRef {
+    fn clone(&self) -> Self {
+        self.0.refs.fetch_add(1, Ordering::SeqCst);
+        Self(self.0.clone())
+    }
+}
+
+impl Drop for SharedMemoryRef {
+    fn drop(&mut self) {
+        self.0.release(&self.0.refs);
+    }
+}
+
+/// A single mapping of a shared memory object, shared by all split parts of the mapping area and
+/// by its forked copies.
+#[derive(Debug)]
+pub(crate) struct SharedMapping(Arc<SharedMemory>);
+
+impl SharedMapping {
+    pub(crate) fn new(shm: Arc<SharedMemory>) -> Self {
+        shm.maps.fetch_add(1, Ordering::SeqCst);
+        Self(shm)
+    }
+
+    pub(crate) fn shm(&self) -> &Arc<SharedMemory> {
+        &self.0
+    }
+}
+
+impl Drop for SharedMapping {
+    fn drop(&mut self) {
+        self.0.release(&self.0.maps);
+    }
+}
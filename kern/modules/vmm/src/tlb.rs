@@ -0,0 +1,150 @@
+//! TLB shootdown: a change to mappings that other CPUs may have cached is flushed on each of them
+//! through an IPI, which the CPU changing them waits for every other one to acknowledge.
+//!
+//! Requests are left in a mailbox per CPU before the IPI is sent, and are handled on the
+//! software interrupt path. A CPU waiting for acknowledgements handles its own mailbox meanwhile,
+//! so that two CPUs shooting down each other at once do not wait forever.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    mem,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+use jrinx_addr::VirtAddr;
+use jrinx_config::PAGE_SIZE;
+use jrinx_hal::{hal, Cpu, Hal, Interrupt, Vm};
+use jrinx_paging::TlbFlush;
+use spin::{Lazy, Mutex};
+
+/// Ranges of more pages than this are flushed as a whole.
+pub(crate) const MAX_PAGES: usize = 64;
+
+/// CPUs which have switched to the kernel page table, one bit each.
+static ONLINE: AtomicUsize = AtomicUsize::new(0);
+
+static MAILBOXES: Lazy<Vec<Mutex<Vec<Arc<Request>>>>> = Lazy::new(|| {
+    (0..hal!().cpu().nproc())
+        .map(|_| Mutex::new(Vec::new()))
+        .collect()
+});
+
+static HANDLED: AtomicU64 = AtomicU64::new(0);
+
+/// Translations to flush from the TLB.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Flush {
+    /// Every translation.
+    All,
+    /// The translations of the pages in `[start, end)`.
+    Range(VirtAddr, VirtAddr),
+    /// The translations of each page listed.
+    Pages(Vec<VirtAddr>),
+    /// The translations tagged with an ASID.
+    Asid(usize),
+}
+
+impl Flush {
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Self::All => false,
+            Self::Range(start, end) => start >= end,
+            Self::Pages(pages) => pages.is_empty(),
+            Self::Asid(_) => false,
+        }
+    }
+
+    fn apply(&self) {
+        match self {
+            Self::All => hal!().vm().sync_all(),
+            Self::Range(start, end) if (*end - *start) / PAGE_SIZE > MAX_PAGES => {
+                hal!().vm().sync_all()
+            }
+            Self::Range(start, end) => {
+                for page in (start.as_usize()..end.as_usize()).step_by(PAGE_SIZE) {
+                    hal!().vm().sync(VirtAddr::new(page));
+                }
+            }
+            Self::Pages(pages) if pages.len() > MAX_PAGES => hal!().vm().sync_all(),
+            Self::Pages(pages) => {
+                for &page in pages {
+                    hal!().vm().sync(page);
+                }
+            }
+            Self::Asid(asid) => hal!().vm().sync_asid(*asid),
+        }
+    }
+}
+
+impl From<TlbFlush> for Flush {
+    fn from(value: TlbFlush) -> Self {
+        if value.tables_freed {
+            Self::All
+        } else {
+            Self::Pages(value.addrs)
+        }
+    }
+}
+
+struct Request {
+    flush: Flush,
+    pending: AtomicUsize,
+}
+
+/// Marks the current CPU as one to be shot down, once it has switched to the kernel page table.
+pub(crate) fn online() {
+    ONLINE.fetch_or(1 << hal!().cpu().id(), Ordering::SeqCst);
+}
+
+/// Flushes `flush` from the TLB of every CPU, and returns once all of them have done so.
+pub fn shootdown(flush: Flush) {
+    if flush.is_empty() {
+        return;
+    }
+    flush.apply();
+
+    let this = hal!().cpu().id();
+    let targets = (0..hal!().cpu().nproc())
+        .filter(|&cpu| cpu != this && ONLINE.load(Ordering::SeqCst) & 1 << cpu != 0)
+        .collect::<Vec<_>>();
+    if targets.is_empty() {
+        return;
+    }
+
+    let request = Arc::new(Request {
+        flush,
+        pending: AtomicUsize::new(targets.len()),
+    });
+    for &cpu in &targets {
+        hal!()
+            .interrupt()
+            .with_saved_off(|| MAILBOXES[cpu].lock().push(request.clone()));
+    }
+    hal!().interrupt().send_ipi(&targets);
+
+    while request.pending.load(Ordering::Acquire) != 0 {
+        handle();
+        core::hint::spin_loop();
+    }
+}
+
+/// Handles the requests left for the current CPU, called on software interrupts.
+pub fn handle() {
+    let this = hal!().cpu().id();
+    if ONLINE.load(Ordering::SeqCst) & 1 << this == 0 {
+        return;
+    }
+    let requests = hal!()
+        .interrupt()
+        .with_saved_off(|| mem::take(&mut *MAILBOXES[this].lock()));
+    for request in requests {
+        request.flush.apply();
+        request.pending.fetch_sub(1, Ordering::Release);
+        HANDLED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Returns the number of requests handled by every CPU so far.
+pub fn count() -> u64 {
+    HANDLED.load(Ordering::Relaxed)
+}
@@ -1,6 +1,6 @@
 use cfg_if::cfg_if;
 use fdt::Fdt;
-use jrinx_addr::VirtAddr;
+use jrinx_addr::{PhysAddr, VirtAddr};
 
 cfg_if! {
     if #[cfg(any(target_arch = "riscv64", target_arch = "riscv32"))] {
@@ -19,4 +19,9 @@ impl BootInfo {
     pub fn fdt(&self) -> Fdt<'_> {
         unsafe { Fdt::from_ptr(self.fdt_addr.as_usize() as *const _).unwrap() }
     }
+
+    /// Returns the physical address and the size of the FDT blob.
+    pub fn fdt_region(&self) -> (PhysAddr, usize) {
+        (self.fdt_addr.to_phys(), self.fdt().total_size())
+    }
 }
@@ -71,6 +71,11 @@ unsafe extern "C" fn secondary_init() -> ! {
     crate::secondary_init();
 }
 
+/// Narrows the permissions of the kernel mappings, once the kernel heap is ready.
+pub fn protect_kernel() {
+    unsafe { BootPageTable.protect() };
+}
+
 pub fn secondary_boot(fdt: &Fdt) {
     cpus::start(fdt);
 }
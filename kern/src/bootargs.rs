@@ -1,6 +1,7 @@
 use alloc::{borrow::ToOwned, string::String, vec::Vec};
 use getargs::{Opt, Options};
 use jrinx_multitask::{spawn, yield_now};
+use jrinx_process::coredump::{self, CoreTarget};
 use spin::Once;
 
 static BOOTARGS: Once<String> = Once::new();
@@ -26,6 +27,8 @@ pub async fn execute() {
                 Opt::Short('h') | Opt::Long("help") => {
                     info!("boot arguments:");
                     info!("   -t, --test <test>    Run the specified test");
+                    info!("   -m, --maps           Dump the mappings of the kernel page table");
+                    info!("   -c, --core <dev:blk> Write core files to a block device");
                     info!("   -h, --help           Display this information");
                 }
 
@@ -54,6 +57,20 @@ pub async fn execute() {
                     }
                 }
 
+                Opt::Short('m') | Opt::Long("maps") => jrinx_vmm::dump_kern_maps(),
+
+                Opt::Short('c') | Opt::Long("core") => {
+                    let (dev, block) = opts
+                        .value()
+                        .ok()
+                        .and_then(|arg| arg.split_once(':'))
+                        .and_then(|(dev, block)| Some((dev.parse().ok()?, block.parse().ok()?)))
+                        .unwrap_or_else(|| {
+                            panic!("invalid argument for option: {opt}, expected <dev>:<block>")
+                        });
+                    coredump::set_target(CoreTarget::Block { dev, block });
+                }
+
                 Opt::Short(_) | Opt::Long(_) => panic!("unrecognized option: {}", opt),
             };
         }
@@ -46,15 +46,21 @@ fn boot_set_ready() {
 fn primary_init(boot_info: BootInfo) -> ! {
     jrinx_trap::init();
     jrinx_heap::init();
+    arch::protect_kernel();
     jrinx_logging::init();
 
     let fdt = &boot_info.fdt();
+    #[cfg(feature = "kaslr")]
+    jrinx_kaslr::init(fdt);
 
     arch::cpus::init(fdt);
 
     jrinx_percpu::init(hal!().cpu().nproc());
     jrinx_percpu::set_local_pointer(hal!().cpu().id());
+    jrinx_heap::slab::init();
 
+    let (fdt_addr, fdt_size) = boot_info.fdt_region();
+    jrinx_driver::mem::reserve("fdt", fdt_addr, fdt_size);
     jrinx_driver::probe_all(fdt);
     jrinx_driver::irq::irq_dispatch::init_strategy();
     if let Some(bootargs) = fdt.chosen().bootargs() {
@@ -86,6 +92,7 @@ fn secondary_init() -> ! {
     }
 
     jrinx_percpu::set_local_pointer(hal!().cpu().id());
+    jrinx_heap::slab::init();
 
     jrinx_vmm::init();
     runtime::init(secondary_task());
@@ -34,6 +34,53 @@ pub(super) mod phys {
     }
 }
 
+pub(super) mod frame {
+    use alloc::vec::Vec;
+
+    use jrinx_config::PAGE_SIZE;
+    use jrinx_phys_frame::frame_alloc::{self, FrameAllocator};
+    use jrinx_testdef::testdef;
+
+    const PAGES: usize = 4;
+    const ALIGN: usize = PAGE_SIZE * 16;
+
+    #[testdef]
+    fn test() {
+        let before = frame_alloc::stats();
+        assert_eq!(before.used() + before.free, before.total);
+
+        let addr = frame_alloc::alloc(PAGES, ALIGN).unwrap();
+        assert_eq!(addr.as_usize() % ALIGN, 0);
+        assert_eq!(frame_alloc::stats().free, before.free - PAGES);
+
+        // the frames are contiguous and all of them usable
+        let frames = unsafe {
+            core::slice::from_raw_parts_mut(addr.to_virt().as_usize() as *mut u8, PAGES * PAGE_SIZE)
+        };
+        frames.fill(0x5a);
+        assert!(frames.iter().all(|&b| b == 0x5a));
+
+        unsafe { frame_alloc::dealloc(addr, PAGES) };
+        assert_eq!(frame_alloc::stats(), before);
+
+        // freed frames are reused first
+        let again = frame_alloc::alloc(PAGES, ALIGN).unwrap();
+        assert_eq!(again, addr);
+        unsafe { frame_alloc::dealloc(again, PAGES) };
+
+        assert!(frame_alloc::alloc(0, PAGE_SIZE).is_err());
+        assert!(frame_alloc::alloc(1, PAGE_SIZE * 3).is_err());
+        assert!(frame_alloc::alloc(before.total + 1, PAGE_SIZE).is_err());
+
+        // nothing here allocates from the heap, which could otherwise grow by taking frames
+        let mut v: Vec<u8, _> = Vec::with_capacity_in(PAGE_SIZE * 2, FrameAllocator);
+        v.resize(PAGE_SIZE * 2, 1);
+        assert_eq!(frame_alloc::stats().free, before.free - 2);
+        drop(v);
+        assert_eq!(frame_alloc::stats(), before);
+    }
+}
+
 pub(super) mod virt {
     use core::mem;
 
@@ -107,3 +154,689 @@ pub(super) mod virt {
         unsafe { *dst }
     }
 }
+
+pub(super) mod huge {
+    use alloc::sync::Arc;
+
+    use jrinx_addr::VirtAddr;
+    use jrinx_config::PAGE_SIZE;
+    use jrinx_hal::{Hal, Vm};
+    use jrinx_paging::{common::PageTable, GenericPagePerm, GenericPageTable, PagePerm, PageSize};
+    use jrinx_phys_frame::PhysFrame;
+    use jrinx_testdef::testdef;
+    use jrinx_vmm::KERN_PAGE_TABLE;
+
+    #[cfg(target_arch = "riscv32")]
+    const SIZE: PageSize = PageSize::Size4M;
+    #[cfg(target_arch = "riscv64")]
+    const SIZE: PageSize = PageSize::Size2M;
+
+    const MAGIC: u64 = 0xdead_beef_cafe_f00d;
+
+    #[testdef]
+    fn test() {
+        let vaddr = VirtAddr::new(SIZE.size() * 4);
+        let frame = PhysFrame::alloc_block(SIZE.size()).unwrap();
+        let paddr = frame.addr();
+        assert_eq!(paddr.as_usize() % SIZE.size(), 0);
+
+        let mut page_table = KERN_PAGE_TABLE.write();
+        let perm = PagePerm::G | PagePerm::W | PagePerm::R;
+        assert!(page_table
+            .map_sized(vaddr + PAGE_SIZE, frame.clone(), SIZE, perm)
+            .is_err());
+        page_table
+            .map_sized(vaddr, frame.clone(), SIZE, perm)
+            .unwrap();
+
+        assert_eq!(page_table.leaf_size(vaddr + PAGE_SIZE).unwrap(), SIZE);
+        let (pa, _) = page_table.translate(vaddr + PAGE_SIZE * 3 + 8).unwrap();
+        assert_eq!(pa, paddr + PAGE_SIZE * 3 + 8);
+        let (looked_up, _) = page_table.lookup(vaddr + PAGE_SIZE * 5).unwrap();
+        assert_eq!(looked_up.addr(), paddr);
+        drop(looked_up);
+        hal!().vm().sync_all();
+
+        let last = vaddr + SIZE.size() - 8;
+        write(last, MAGIC);
+        assert_eq!(read((paddr + SIZE.size() - 8).to_virt()), MAGIC);
+
+        // re-protecting a page splits the huge page, the other pages keep their permission
+        page_table
+            .protect(vaddr + PAGE_SIZE, PagePerm::G | PagePerm::R)
+            .unwrap();
+        assert_eq!(
+            page_table.leaf_size(vaddr + PAGE_SIZE).unwrap(),
+            PageSize::Size4K
+        );
+        let (_, perm1) = page_table.translate(vaddr + PAGE_SIZE).unwrap();
+        assert!(!perm1.contains(PagePerm::W));
+        let (pa2, perm2) = page_table.translate(vaddr + PAGE_SIZE * 2).unwrap();
+        assert_eq!(pa2, paddr + PAGE_SIZE * 2);
+        assert!(perm2.contains(PagePerm::W));
+
+        // unmapping a page leaves the others mapped
+        page_table.unmap(vaddr + PAGE_SIZE * 2).unwrap();
+        assert!(page_table.translate(vaddr + PAGE_SIZE * 2).is_err());
+        let (pa, _) = page_table.translate(last).unwrap();
+        assert_eq!(pa, paddr + SIZE.size() - 8);
+        hal!().vm().sync_all();
+        assert_eq!(read(last), MAGIC);
+
+        for offset in (0..SIZE.size())
+            .step_by(PAGE_SIZE)
+            .filter(|&offset| offset != PAGE_SIZE * 2)
+        {
+            page_table.unmap(vaddr + offset).unwrap();
+        }
+        hal!().vm().sync_all();
+        drop(page_table);
+        assert_eq!(Arc::strong_count(&frame), 1);
+
+        // a copy-on-write page of a split huge page is copied only while the frame is shared
+        let mut page_table = PageTable::new().unwrap();
+        let perm = PagePerm::U | PagePerm::W | PagePerm::R;
+        let weak = Arc::downgrade(&frame);
+        page_table.map_sized(vaddr, frame, SIZE, perm).unwrap();
+        page_table
+            .protect(vaddr, PagePerm::U | PagePerm::R)
+            .unwrap();
+        let child = page_table.fork().unwrap();
+        page_table.resolve_cow(vaddr + PAGE_SIZE).unwrap();
+        let (pa1, perm1) = page_table.translate(vaddr + PAGE_SIZE).unwrap();
+        assert_ne!(pa1, paddr + PAGE_SIZE);
+        assert!(perm1.contains(PagePerm::W));
+        drop(child);
+        page_table.resolve_cow(vaddr + PAGE_SIZE * 2).unwrap();
+        let (pa2, perm2) = page_table.translate(vaddr + PAGE_SIZE * 2).unwrap();
+        assert_eq!(pa2, paddr + PAGE_SIZE * 2);
+        assert!(perm2.contains(PagePerm::W));
+        drop(page_table);
+        assert_eq!(weak.strong_count(), 0);
+    }
+
+    fn write(addr: VirtAddr, val: u64) {
+        unsafe { *(addr.as_usize() as *mut u64) = val }
+    }
+
+    fn read(addr: VirtAddr) -> u64 {
+        unsafe { *(addr.as_usize() as *const u64) }
+    }
+}
+
+pub(super) mod range {
+    use alloc::{sync::Arc, vec::Vec};
+
+    use jrinx_addr::VirtAddr;
+    use jrinx_config::PAGE_SIZE;
+    use jrinx_paging::{GenericPagePerm, GenericPageTable, PagePerm};
+    use jrinx_phys_frame::{frame_alloc, PhysFrame};
+    use jrinx_testdef::testdef;
+    use jrinx_vmm::KERN_PAGE_TABLE;
+
+    // an entry of the root of its own, so that every table below it is created and freed here
+    #[cfg(target_arch = "riscv32")]
+    const VADDR: usize = 8 << 22;
+    #[cfg(target_arch = "riscv64")]
+    const VADDR: usize = 2 << 30;
+
+    const PAGES: usize = 8;
+
+    #[testdef]
+    fn test() {
+        let vaddr = VirtAddr::new(VADDR);
+        let page = |i: usize| vaddr + i * PAGE_SIZE;
+        let perm = PagePerm::G | PagePerm::W | PagePerm::R;
+        let frames = (0..PAGES)
+            .map(|_| PhysFrame::alloc().unwrap())
+            .collect::<Vec<_>>();
+        let free = frame_alloc::stats().free;
+
+        let mut page_table = KERN_PAGE_TABLE.write();
+        let flush = page_table.map_range(vaddr, &frames, perm).unwrap();
+        assert!(flush.is_empty());
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(page_table.translate(page(i)).unwrap().0, frame.addr());
+        }
+        assert!(frame_alloc::stats().free < free);
+
+        // replaced pages are to be flushed
+        let swapped = [frames[1].clone(), frames[0].clone()];
+        let flush = page_table.map_range(vaddr, &swapped, perm).unwrap();
+        assert_eq!(flush.addrs, [page(0), page(1)]);
+        assert_eq!(page_table.translate(page(0)).unwrap().0, frames[1].addr());
+
+        // so are pages whose permission has changed, and only those
+        let flush = page_table
+            .protect_range(vaddr, PAGE_SIZE * 3, PagePerm::G | PagePerm::R)
+            .unwrap();
+        assert_eq!(flush.addrs, [page(0), page(1), page(2)]);
+        let flush = page_table
+            .protect_range(vaddr, PAGE_SIZE * 3, PagePerm::G | PagePerm::R)
+            .unwrap();
+        assert!(flush.is_empty());
+
+        // nothing is changed if a page is not mapped
+        assert!(page_table
+            .protect_range(vaddr, PAGE_SIZE * (PAGES + 1), perm)
+            .is_err());
+        let (_, perm0) = page_table.translate(page(0)).unwrap();
+        assert!(!perm0.contains(PagePerm::W));
+
+        // pages not mapped are skipped, tables still in use are kept
+        let flush = page_table.unmap_range(page(2), PAGE_SIZE * PAGES).unwrap();
+        assert_eq!(flush.addrs, (2..PAGES).map(page).collect::<Vec<_>>());
+        assert!(!flush.tables_freed);
+        assert!(page_table.translate(page(1)).is_ok());
+        assert!(page_table.translate(page(2)).is_err());
+
+        let flush = page_table.unmap_range(vaddr, PAGE_SIZE * 2).unwrap();
+        assert_eq!(flush.addrs, [page(0), page(1)]);
+        assert!(flush.tables_freed);
+        assert!(page_table.translate(page(0)).is_err());
+        drop(page_table);
+        drop(swapped);
+
+        assert!(frames.iter().all(|frame| Arc::strong_count(frame) == 1));
+        assert_eq!(frame_alloc::stats().free, free);
+    }
+}
+
+pub(super) mod shootdown {
+    use alloc::vec;
+
+    use jrinx_addr::VirtAddr;
+    use jrinx_config::PAGE_SIZE;
+    use jrinx_hal::{Cpu, Hal};
+    use jrinx_paging::{GenericPagePerm, GenericPageTable, PagePerm};
+    use jrinx_phys_frame::PhysFrame;
+    use jrinx_testdef::testdef;
+    use jrinx_vmm::{
+        tlb::{self, Flush},
+        KERN_PAGE_TABLE,
+    };
+
+    #[testdef]
+    fn test() {
+        let vaddr = VirtAddr::new(PAGE_SIZE * 3);
+        let others = hal!().cpu().nproc_valid() as u64 - 1;
+
+        // every other CPU acknowledges each request, the counter may also grow from others
+        for flush in [
+            Flush::All,
+            Flush::Range(vaddr, vaddr + PAGE_SIZE * 2),
+            Flush::Pages(vec![vaddr]),
+        ] {
+            let before = tlb::count();
+            tlb::shootdown(flush);
+            assert!(tlb::count() - before >= others);
+        }
+
+        let before = tlb::count();
+        tlb::shootdown(Flush::Pages(vec![]));
+        tlb::shootdown(Flush::Range(vaddr, vaddr));
+        assert_eq!(tlb::count(), before);
+
+        let frame = PhysFrame::alloc().unwrap();
+        let paddr = frame.addr();
+        jrinx_vmm::update_kern_page_table(|page_table| {
+            page_table.map_range(vaddr, &[frame], PagePerm::G | PagePerm::R | PagePerm::W)
+        })
+        .unwrap();
+        assert_eq!(KERN_PAGE_TABLE.read().translate(vaddr).unwrap().0, paddr);
+        jrinx_vmm::update_kern_page_table(|page_table| page_table.unmap_range(vaddr, PAGE_SIZE))
+            .unwrap();
+        assert!(KERN_PAGE_TABLE.read().translate(vaddr).is_err());
+    }
+}
+
+pub(super) mod asid {
+    use jrinx_addr::VirtAddr;
+    use jrinx_config::PAGE_SIZE;
+    use jrinx_hal::{Cpu, Hal, Interrupt};
+    use jrinx_paging::{GenericPagePerm, GenericPageTable, PagePerm};
+    use jrinx_phys_frame::PhysFrame;
+    use jrinx_testdef::testdef;
+    use jrinx_vmm::{
+        addr_space::AddrSpace,
+        asid,
+        tlb::{self, Flush},
+    };
+
+    #[testdef]
+    fn test() {
+        let vaddr = VirtAddr::new(PAGE_SIZE * 5);
+        let mut spaces = [AddrSpace::new().unwrap(), AddrSpace::new().unwrap()];
+        for space in spaces.iter_mut() {
+            let frame = PhysFrame::alloc().unwrap();
+            let perm = PagePerm::V | PagePerm::R | PagePerm::W;
+            space.page_table_mut().map(vaddr, frame, perm).unwrap();
+        }
+
+        // the same address is backed by another frame in each address space
+        let ptr = vaddr.as_usize() as *mut usize;
+        hal!().interrupt().with_saved_off(|| {
+            for (value, space) in spaces.iter_mut().enumerate() {
+                space.activate();
+                unsafe { ptr.write_volatile(value) };
+            }
+            for (value, space) in spaces.iter_mut().enumerate() {
+                space.activate();
+                assert_eq!(unsafe { ptr.read_volatile() }, value);
+            }
+
+            // a page table changed behind the TLB is seen once the address space is flushed
+            let [first, second] = &mut spaces;
+            let (frame, _) = second.page_table().lookup(vaddr).unwrap();
+            first
+                .page_table_mut()
+                .map(vaddr, frame, PagePerm::V | PagePerm::R)
+                .unwrap();
+            first.flush_tlb();
+            first.activate();
+            assert_eq!(unsafe { ptr.read_volatile() }, 1);
+
+            jrinx_vmm::activate_kernel();
+        });
+
+        if asid::bits() > 1 {
+            assert_ne!(spaces[0].asid(), asid::KERNEL);
+            assert_ne!(spaces[1].asid(), asid::KERNEL);
+            assert_ne!(spaces[0].asid(), spaces[1].asid());
+        }
+
+        let before = tlb::count();
+        tlb::shootdown(Flush::Asid(spaces[0].asid()));
+        assert!(tlb::count() - before >= hal!().cpu().nproc_valid() as u64 - 1);
+    }
+}
+
+pub(super) mod paging {
+    use alloc::vec::Vec;
+
+    use jrinx_addr::{PhysAddr, VirtAddr};
+    use jrinx_config::{PAGE_SIZE, PHYS_MEM_BASE, PHYS_MEM_LIMIT, PT_LEVELS, USER_STACK_TOP};
+    use jrinx_paging::{
+        boot, common::PageTable, GenericPagePerm, GenericPageTable, PagePerm, MODE,
+    };
+    use jrinx_phys_frame::PhysFrame;
+    use jrinx_testdef::testdef;
+    use jrinx_vmm::KERN_PAGE_TABLE;
+    use riscv::register::satp;
+
+    #[testdef]
+    fn test() {
+        let supported = boot::supported_modes().collect::<Vec<_>>();
+        info!("paging mode {:?}, supported {:?}", MODE, supported);
+        assert!(supported.contains(&MODE));
+        assert_eq!(satp::read().mode(), MODE);
+        assert_eq!(VirtAddr::new(0).indexes().len(), PT_LEVELS);
+
+        // memory is remapped up to the limit of the mode
+        let page_table = KERN_PAGE_TABLE.read();
+        for paddr in [PHYS_MEM_BASE, PHYS_MEM_LIMIT - PAGE_SIZE] {
+            let paddr = PhysAddr::new(paddr);
+            assert_eq!(page_table.translate(paddr.to_virt()).unwrap().0, paddr);
+        }
+        drop(page_table);
+
+        // the top of the user half takes every level of the mode
+        let vaddr = VirtAddr::new(USER_STACK_TOP - PAGE_SIZE);
+        let mut page_table = PageTable::new().unwrap();
+        let frame = PhysFrame::alloc().unwrap();
+        let paddr = frame.addr();
+        page_table
+            .map(vaddr, frame, PagePerm::U | PagePerm::R | PagePerm::V)
+            .unwrap();
+        assert_eq!(page_table.translate(vaddr).unwrap().0, paddr);
+    }
+}
+
+pub(super) mod slab {
+    use core::alloc::Layout;
+
+    use alloc::{
+        alloc::{alloc, dealloc},
+        boxed::Box,
+        collections::BTreeSet,
+        vec::Vec,
+    };
+    use jrinx_heap::slab;
+    use jrinx_testdef::testdef;
+
+    const COUNT: usize = 200;
+
+    #[testdef]
+    fn test() {
+        let class = slab::stats()
+            .iter()
+            .position(|stats| stats.size == 32)
+            .unwrap();
+        let before = slab::stats()[class];
+
+        let boxes = (0..COUNT)
+            .map(|i| Box::new([i as u64; 4]))
+            .collect::<Vec<_>>();
+        let addrs = boxes
+            .iter()
+            .map(|b| b.as_ptr() as usize)
+            .collect::<BTreeSet<_>>();
+        assert_eq!(addrs.len(), COUNT);
+        for (i, b) in boxes.iter().enumerate() {
+            assert_eq!(b.as_ptr() as usize % 32, 0);
+            assert!(b.iter().all(|&x| x == i as u64));
+        }
+        drop(boxes);
+
+        let after = slab::stats()[class];
+        assert!(after.allocs - before.allocs >= COUNT);
+        assert!(after.frees - before.frees >= COUNT);
+        assert!(after.slabs > 0);
+        info!("slab stats of class {}: {:?}", class, after);
+
+        // the class follows the alignment when it is larger than the size
+        let layout = Layout::from_size_align(8, 64).unwrap();
+        let ptrs = (0..COUNT)
+            .map(|_| unsafe { alloc(layout) })
+            .collect::<Vec<_>>();
+        for &ptr in ptrs.iter() {
+            assert!(!ptr.is_null());
+            assert_eq!(ptr as usize % 64, 0);
+        }
+        for ptr in ptrs {
+            unsafe { dealloc(ptr, layout) };
+        }
+    }
+}
+
+pub(super) mod reserved {
+    use jrinx_addr::{PhysAddr, VirtAddr};
+    use jrinx_config::{PAGE_SIZE, PHYS_MEM_BASE};
+    use jrinx_phys_frame::frame_alloc;
+    use jrinx_testdef::testdef;
+
+    #[testdef]
+    fn test() {
+        let reserved = jrinx_driver::mem::reserved();
+        assert!(reserved
+            .iter()
+            .any(|&(addr, len)| addr.as_usize() != 0 && len != 0));
+        let image_end = VirtAddr::new(jrinx_layout::_end()).to_phys().as_usize();
+
+        // every frame is chained through its first word, so that the heap is left alone
+        let mut head = None;
+        while let Ok(frame) = frame_alloc::alloc(1, PAGE_SIZE) {
+            let start = frame.as_usize();
+            let end = start + PAGE_SIZE;
+            assert!(end <= PHYS_MEM_BASE || start >= image_end);
+            for &(addr, len) in reserved.iter() {
+                assert!(end <= addr.as_usize() || start >= addr.as_usize() + len);
+            }
+            unsafe { *(frame.to_virt().as_usize() as *mut Option<PhysAddr>) = head };
+            head = Some(frame);
+        }
+
+        while let Some(frame) = head {
+            head = unsafe { *(frame.to_virt().as_usize() as *const Option<PhysAddr>) };
+            unsafe { frame_alloc::dealloc(frame, 1) };
+        }
+    }
+}
+
+pub(super) mod dma {
+    use core::ptr::NonNull;
+
+    use alloc::vec;
+    use jrinx_addr::VirtAddr;
+    use jrinx_config::PAGE_SIZE;
+    use jrinx_driver::dma::{self, Direction};
+    use jrinx_testdef::testdef;
+
+    const LEN: usize = 64;
+
+    #[testdef]
+    fn test() {
+        let before = dma::stats();
+
+        let (addr, ptr) = dma::alloc_coherent(2).unwrap();
+        assert_eq!(addr.as_usize() % PAGE_SIZE, 0);
+        assert_eq!(addr.to_virt().as_usize(), ptr.as_ptr() as usize);
+        let coherent = unsafe { core::slice::from_raw_parts_mut(ptr.as_ptr(), PAGE_SIZE * 2) };
+        assert!(coherent.iter().all(|&b| b == 0));
+        coherent.fill(0x5a);
+        assert_eq!(dma::stats().coherent, before.coherent + 2);
+        unsafe { dma::dealloc_coherent(addr, 2) };
+
+        // a buffer in the linear mapping is handed over in place
+        let mut heap = vec![0x5au8; LEN];
+        let buf = NonNull::from(heap.as_mut_slice());
+        let addr = unsafe { dma::map(buf, Direction::ToDevice) }.unwrap();
+        assert_eq!(addr, VirtAddr::new(heap.as_ptr() as usize).to_phys());
+        assert_eq!(dma::stats().bounced, before.bounced);
+        unsafe { dma::unmap(addr, buf, Direction::ToDevice) };
+
+        // one on the executor stack is bounced, the device is played through the bounce buffer
+        let mut stack = [0x5au8; LEN];
+        let buf = NonNull::from(&mut stack[..]);
+        let addr = unsafe { dma::map(buf, Direction::Bidirectional) }.unwrap();
+        assert_eq!(dma::stats().bounced, before.bounced + 1);
+        let bounce =
+            unsafe { core::slice::from_raw_parts_mut(addr.to_virt().as_usize() as *mut u8, LEN) };
+        assert!(bounce.iter().all(|&b| b == 0x5a));
+        bounce.fill(0xa5);
+        unsafe { dma::unmap(addr, buf, Direction::Bidirectional) };
+        assert!(stack.iter().all(|&b| b == 0xa5));
+
+        // nothing is copied towards a device only writing to the buffer
+        let addr = unsafe { dma::map(buf, Direction::FromDevice) }.unwrap();
+        let bounce =
+            unsafe { core::slice::from_raw_parts_mut(addr.to_virt().as_usize() as *mut u8, LEN) };
+        assert!(bounce.iter().all(|&b| b == 0));
+        bounce.fill(0x3c);
+        unsafe { dma::unmap(addr, buf, Direction::FromDevice) };
+        assert!(stack.iter().all(|&b| b == 0x3c));
+
+        // buffers beyond the address limit are bounced, and nothing is left to bounce them through
+        dma::set_addr_limit(0);
+        let buf = NonNull::from(heap.as_mut_slice());
+        assert!(unsafe { dma::map(buf, Direction::ToDevice) }.is_err());
+        assert!(dma::alloc_coherent(1).is_err());
+        dma::set_addr_limit(usize::MAX);
+
+        assert_eq!(dma::stats(), before);
+    }
+}
+
+pub(super) mod wx {
+    use alloc::boxed::Box;
+    use jrinx_addr::VirtAddr;
+    use jrinx_paging::{GenericPagePerm, GenericPageTable, PagePerm};
+    use jrinx_testdef::testdef;
+    use jrinx_trap::uaccess;
+    use jrinx_vmm::KERN_PAGE_TABLE;
+
+    #[testdef]
+    fn test() {
+        let text = jrinx_layout::_stext();
+        let rodata = jrinx_layout::_srodata();
+        let bss = jrinx_layout::_sbss();
+        let heap = Box::new(0u8);
+        let stack = 0u8;
+
+        assert_eq!(perm(text), PagePerm::R | PagePerm::X);
+        assert_eq!(perm(rodata), PagePerm::R);
+        assert_eq!(perm(bss), PagePerm::R | PagePerm::W);
+        assert_eq!(
+            perm(&*heap as *const u8 as usize),
+            PagePerm::R | PagePerm::W
+        );
+        assert_eq!(
+            perm(&stack as *const u8 as usize),
+            PagePerm::R | PagePerm::W
+        );
+
+        // writing to the text faults, the fault is turned into an error by the copy routine
+        let mut byte = 0u8;
+        unsafe {
+            assert!(uaccess::copy(&mut byte, text as *const u8, 1).is_ok());
+            assert!(uaccess::copy(text as *mut u8, &!byte, 1).is_err());
+            assert_eq!(*(text as *const u8), byte);
+            assert!(uaccess::copy(rodata as *mut u8, &byte, 1).is_err());
+        }
+    }
+
+    fn perm(addr: usize) -> PagePerm {
+        let (_, perm) = KERN_PAGE_TABLE
+            .read()
+            .translate(VirtAddr::new(addr))
+            .unwrap();
+        perm & (PagePerm::R | PagePerm::W | PagePerm::X)
+    }
+}
+
+pub(super) mod kaslr {
+    use jrinx_config::{
+        EXECUTOR_STACK_REGION, EXECUTOR_STACK_SIZE, EXTERNAL_DEVICE_REGION, PAGE_SIZE,
+    };
+    use jrinx_phys_frame::frame_alloc;
+    use jrinx_testdef::testdef;
+
+    #[testdef]
+    fn test() {
+        let stacks = jrinx_kaslr::executor_stack_region();
+        let stacks_end = EXECUTOR_STACK_REGION.addr + EXECUTOR_STACK_REGION.len;
+        assert_eq!(
+            (stacks.addr - EXECUTOR_STACK_REGION.addr) % EXECUTOR_STACK_SIZE,
+            0
+        );
+        assert!(stacks.addr + stacks.len <= stacks_end);
+
+        // tests run on an executor stack
+        let local = 0u8;
+        let local = &local as *const u8 as usize;
+        assert!((stacks.addr..stacks.addr + stacks.len).contains(&local));
+
+        let devices = jrinx_kaslr::external_device_region();
+        assert_eq!((devices.addr - EXTERNAL_DEVICE_REGION.addr) % PAGE_SIZE, 0);
+        assert_eq!(
+            devices.addr + devices.len,
+            EXTERNAL_DEVICE_REGION.addr + EXTERNAL_DEVICE_REGION.len
+        );
+
+        // frames are still found below the page the heap prefers
+        let before = frame_alloc::stats();
+        let addr = frame_alloc::alloc_from(1, PAGE_SIZE, jrinx_kaslr::heap_page()).unwrap();
+        unsafe { frame_alloc::dealloc(addr, 1) };
+        let addr = frame_alloc::alloc_from(1, PAGE_SIZE, usize::MAX).unwrap();
+        unsafe { frame_alloc::dealloc(addr, 1) };
+        assert_eq!(frame_alloc::stats(), before);
+    }
+}
+
+pub(super) mod maps {
+    use alloc::vec::Vec;
+
+    use jrinx_addr::VirtAddr;
+    use jrinx_config::PAGE_SIZE;
+    use jrinx_paging::{
+        common::{Mapping, PageTable},
+        GenericPagePerm, GenericPageTable, PagePerm, PageSize,
+    };
+    use jrinx_phys_frame::PhysFrame;
+    use jrinx_testdef::testdef;
+    use jrinx_vmm::KERN_PAGE_TABLE;
+
+    #[cfg(target_arch = "riscv32")]
+    const SIZE: PageSize = PageSize::Size4M;
+    #[cfg(target_arch = "riscv64")]
+    const SIZE: PageSize = PageSize::Size2M;
+
+    #[testdef]
+    fn test() {
+        let vaddr = VirtAddr::new(SIZE.size() * 4);
+        let frame = PhysFrame::alloc_block(SIZE.size()).unwrap();
+        let paddr = frame.addr();
+        let rw = PagePerm::V | PagePerm::R | PagePerm::W | PagePerm::U;
+        let ro = PagePerm::V | PagePerm::R | PagePerm::U;
+
+        // the half of user memory of a new page table is empty
+        let mut page_table = PageTable::new().unwrap();
+        assert!(user_mappings(&page_table).is_empty());
+
+        page_table.map_sized(vaddr, frame, SIZE, rw).unwrap();
+        assert_eq!(
+            user_mappings(&page_table),
+            [Mapping {
+                addr: vaddr,
+                len: SIZE.size(),
+                phys_addr: paddr,
+                size: SIZE,
+                perm: rw,
+            }]
+        );
+
+        // the pages of the split huge page are merged around the one protected
+        page_table.protect(vaddr + PAGE_SIZE, ro).unwrap();
+        let page = |i: usize, len: usize, perm: PagePerm| Mapping {
+            addr: vaddr + i * PAGE_SIZE,
+            len: len * PAGE_SIZE,
+            phys_addr: paddr + i * PAGE_SIZE,
+            size: PageSize::Size4K,
+            perm,
+        };
+        let pages = SIZE.size() / PAGE_SIZE;
+        assert_eq!(
+            user_mappings(&page_table),
+            [page(0, 1, rw), page(1, 1, ro), page(2, pages - 2, rw)]
+        );
+
+        // the mappings of the kernel are in order, and its text is found among them
+        let kernel = KERN_PAGE_TABLE.read().mappings().collect::<Vec<_>>();
+        assert!(kernel
+            .windows(2)
+            .all(|pair| pair[0].addr.as_usize() + pair[0].len <= pair[1].addr.as_usize()));
+        let text = jrinx_layout::_stext();
+        let mapping = kernel
+            .iter()
+            .find(|mapping| {
+                (mapping.addr.as_usize()..mapping.addr.as_usize() + mapping.len).contains(&text)
+            })
+            .unwrap();
+        assert!(mapping.perm.contains(PagePerm::X));
+        jrinx_vmm::dump_kern_maps();
+    }
+
+    fn user_mappings(page_table: &PageTable) -> Vec<Mapping> {
+        page_table
+            .mappings()
+            .take_while(|mapping| mapping.addr.as_usize() < SIZE.size() * 8)
+            .collect()
+    }
+}
+
+pub(super) mod cow {
+    use jrinx_paging::PagePerm;
+    use jrinx_testdef::testdef;
+    use jrinx_vmm::addr_space::AddrSpace;
+
+    #[testdef]
+    fn test() {
+        let rw = PagePerm::R | PagePerm::W;
+        let mut parent = AddrSpace::new().unwrap();
+        let addr = parent.map(None, 1, rw).unwrap();
+        parent.write_bytes(addr, &[1]).unwrap();
+        parent.protect(addr, 1, PagePerm::R).unwrap();
+
+        // making a read-only page of the child writable still copies it on write
+        let mut child = parent.fork().unwrap();
+        parent.flush_tlb();
+        child.protect(addr, 1, rw).unwrap();
+        child.write_bytes(addr, &[2]).unwrap();
+
+        let mut byte = [0];
+        parent.read_bytes(addr, &mut byte).unwrap();
+        assert_eq!(byte, [1]);
+        child.read_bytes(addr, &mut byte).unwrap();
+        assert_eq!(byte, [2]);
+    }
+}
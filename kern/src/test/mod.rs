@@ -1,5 +1,6 @@
 mod heap;
 mod mm;
+mod process;
 mod task;
 mod time;
 mod trap;
@@ -0,0 +1,781 @@
+pub(super) mod fork {
+    use jrinx_addr::VirtAddr;
+    use jrinx_multitask::{spawn, TaskPriority};
+    use jrinx_paging::GenericPageTable;
+    use jrinx_process::{Personality, Process, ProcessId};
+    use jrinx_testdef::testdef;
+    use jrinx_trap::{GenericContext, TrapReason};
+
+    const PARENT_MAGIC: usize = 0xBEEF;
+    const CHILD_MAGIC: usize = 0xCAFE;
+
+    #[testdef]
+    fn test() {
+        let cow_forker = jrinx_uprog::find("test/cow-forker").unwrap();
+        let parent = Process::create(&cow_forker, Personality::Native).unwrap();
+        parent.with_context(|ctx| ctx.disable_int());
+
+        spawn!(pri := TaskPriority::MAX / 2 => async move {
+            assert_eq!(parent.run().await, TrapReason::SystemCall);
+            let [value_addr, child_id, ..] = parent.with_context(|ctx| {
+                assert_eq!(ctx.syscall_num(), 0xC0DE);
+                ctx.syscall_args()
+            });
+
+            let child = Process::find(ProcessId::from(child_id)).unwrap();
+            assert_eq!(child.parent(), Some(parent.id()));
+            assert_eq!(child.run().await, TrapReason::SystemCall);
+            let [child_value_addr, child_ret, ..] = child.with_context(|ctx| {
+                assert_eq!(ctx.syscall_num(), 0xC0DE);
+                ctx.syscall_args()
+            });
+            assert_eq!(child_ret, 0);
+            assert_eq!(child_value_addr, value_addr);
+
+            let value_addr = VirtAddr::new(value_addr);
+            let (parent_paddr, _) = parent
+                .with_addr_space(|addr_space| addr_space.page_table().translate(value_addr))
+                .unwrap();
+            let (child_paddr, _) = child
+                .with_addr_space(|addr_space| addr_space.page_table().translate(value_addr))
+                .unwrap();
+            assert_ne!(parent_paddr, child_paddr);
+
+            unsafe {
+                assert_eq!(
+                    *(parent_paddr.to_virt().as_usize() as *const usize),
+                    PARENT_MAGIC
+                );
+                assert_eq!(
+                    *(child_paddr.to_virt().as_usize() as *const usize),
+                    CHILD_MAGIC
+                );
+            }
+
+            child.exit().unwrap();
+            parent.exit().unwrap();
+            assert!(Process::find(child.id()).is_err());
+        });
+    }
+}
+
+pub(super) mod mmap {
+    use jrinx_addr::VirtAddr;
+    use jrinx_config::PAGE_SIZE;
+    use jrinx_multitask::{spawn, TaskPriority};
+    use jrinx_paging::{GenericPagePerm, GenericPageTable, PagePerm};
+    use jrinx_process::{Personality, Process};
+    use jrinx_testdef::testdef;
+    use jrinx_trap::{GenericContext, TrapReason};
+
+    #[testdef]
+    fn test() {
+        let mem_mapper = jrinx_uprog::find("test/mem-mapper").unwrap();
+        let process = Process::create(&mem_mapper, Personality::Native).unwrap();
+        process.with_context(|ctx| ctx.disable_int());
+
+        spawn!(pri := TaskPriority::MAX / 2 => async move {
+            assert_eq!(process.run().await, TrapReason::SystemCall);
+            let [heap, area, ..] = process.with_context(|ctx| {
+                assert_eq!(ctx.syscall_num(), 0xC0DE);
+                ctx.syscall_args()
+            });
+            let (heap, area) = (VirtAddr::new(heap), VirtAddr::new(area));
+
+            process.with_addr_space(|addr_space| {
+                assert_eq!(addr_space.brk(), heap + PAGE_SIZE * 3);
+                let heap_area = addr_space.area(heap).unwrap();
+                assert!(heap_area.end() >= heap + PAGE_SIZE * 3);
+                assert_eq!(heap_area.perm(), PagePerm::R | PagePerm::W);
+                for addr in (heap.as_usize()..heap.as_usize() + PAGE_SIZE * 3).step_by(PAGE_SIZE) {
+                    let (paddr, _) = addr_space
+                        .page_table()
+                        .translate(VirtAddr::new(addr))
+                        .unwrap();
+                    assert_eq!(
+                        unsafe { *(paddr.to_virt().as_usize() as *const usize) },
+                        addr
+                    );
+                }
+
+                let mapped = addr_space.area(area).unwrap();
+                assert_eq!(mapped.start(), area);
+                assert_eq!(mapped.end(), area + PAGE_SIZE);
+                assert_eq!(mapped.perm(), PagePerm::R);
+                assert!(addr_space.area(area + PAGE_SIZE).is_none());
+                assert!(addr_space.page_table().lookup(area + PAGE_SIZE).is_err());
+            });
+
+            assert_eq!(
+                process.run().await,
+                TrapReason::PageFault {
+                    addr: area,
+                    perm: PagePerm::W,
+                }
+            );
+
+            process.with_addr_space(|addr_space| {
+                let rw = PagePerm::R | PagePerm::W;
+                let (old, _) = addr_space.page_table().translate(area).unwrap();
+                assert!(addr_space.map_fixed(area + 1, PAGE_SIZE, rw).is_err());
+                assert_eq!(addr_space.page_table().translate(area).unwrap().0, old);
+                assert_eq!(addr_space.area(area).unwrap().perm(), PagePerm::R);
+
+                assert_eq!(addr_space.map_fixed(area, PAGE_SIZE, rw).unwrap(), area);
+                assert_eq!(addr_space.area(area).unwrap().perm(), rw);
+            });
+
+            process.exit().unwrap();
+        });
+    }
+}
+
+pub(super) mod linux {
+    use jrinx_multitask::{spawn, TaskPriority};
+    use jrinx_process::{Personality, Process};
+    use jrinx_testdef::testdef;
+    use jrinx_trap::{GenericContext, TrapReason};
+
+    #[testdef]
+    fn test() {
+        let linux_abi = jrinx_uprog::find("test/linux-abi").unwrap();
+        let process = Process::create(&linux_abi, Personality::Linux).unwrap();
+        assert_eq!(process.personality(), Personality::Linux);
+        process.with_context(|ctx| ctx.disable_int());
+
+        spawn!(pri := TaskPriority::MAX / 2 => async move {
+            assert_eq!(process.run().await, TrapReason::SystemCall);
+            assert_eq!(process.exit_code(), Some(0));
+
+            process.exit().unwrap();
+        });
+    }
+}
+
+pub(super) mod ipc {
+    use alloc::{boxed::Box, sync::Arc, task::Wake};
+    use core::{
+        future::Future,
+        task::{Context, Poll, Waker},
+    };
+
+    use jrinx_addr::VirtAddr;
+    use jrinx_multitask::{spawn, TaskPriority};
+    use jrinx_process::{
+        cap::{Handle, KernelObject, Rights},
+        ipc::{Endpoint, Message},
+        Personality, Process,
+    };
+    use jrinx_testdef::testdef;
+    use jrinx_trap::{GenericContext, TrapReason};
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    #[testdef]
+    fn test() {
+        // a receiver that stops waiting leaves nothing behind for a sender to hand its message to
+        let abandoned = Endpoint::create();
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+        let mut recv = Box::pin(abandoned.recv());
+        assert!(recv.as_mut().poll(&mut cx).is_pending());
+        drop(recv);
+        let mut send = Box::pin(abandoned.send(Message::default()));
+        assert!(send.as_mut().poll(&mut cx).is_pending());
+        drop(send);
+        assert!(Box::pin(abandoned.recv())
+            .as_mut()
+            .poll(&mut cx)
+            .is_pending());
+
+        // a message handed to a receiver that stops waiting before taking it goes to the next one
+        let mut recv = Box::pin(abandoned.recv());
+        assert!(recv.as_mut().poll(&mut cx).is_pending());
+        let msg = Message {
+            words: [7, 0],
+            ..Default::default()
+        };
+        assert!(Box::pin(abandoned.send(msg))
+            .as_mut()
+            .poll(&mut cx)
+            .is_ready());
+        drop(recv);
+        let Poll::Ready((msg, None)) = Box::pin(abandoned.recv()).as_mut().poll(&mut cx) else {
+            panic!("the message handed to the dropped receiver is lost");
+        };
+        assert_eq!(msg.words, [7, 0]);
+
+        // a call fails once the right to reply to it is dropped
+        let mut call = Box::pin(abandoned.call(Message::default()));
+        assert!(call.as_mut().poll(&mut cx).is_pending());
+        let Poll::Ready((_, Some(reply))) = Box::pin(abandoned.recv()).as_mut().poll(&mut cx)
+        else {
+            panic!("the call is not received");
+        };
+        drop(reply);
+        assert!(matches!(call.as_mut().poll(&mut cx), Poll::Ready(Err(_))));
+        drop(call);
+
+        // the endpoint leaves the registry with its last reference
+        let id = abandoned.id();
+        drop(abandoned);
+        assert!(Endpoint::find(id).is_err());
+
+        let endpoint = Endpoint::create();
+
+        let ipc_server = jrinx_uprog::find("test/ipc-server").unwrap();
+        let server = Process::create(&ipc_server, Personality::Native).unwrap();
+        let ipc_client = jrinx_uprog::find("test/ipc-client").unwrap();
+        let client = Process::create(&ipc_client, Personality::Native).unwrap();
+        for process in [&server, &client] {
+            let handle = process.with_handles(|handles| {
+                handles.insert(Handle::new(
+                    KernelObject::Endpoint(endpoint.clone()),
+                    Rights::all(),
+                ))
+            });
+            process.with_context(|ctx| {
+                ctx.disable_int();
+                ctx.set_syscall_args([handle, 0, 0, 0, 0, 0]);
+            });
+        }
+
+        let client_id: usize = client.id().into();
+        let server_id: usize = server.id().into();
+
+        // the client runs first and parks on its call until the server picks it up
+        spawn!(pri := TaskPriority::MAX / 2 => async move {
+            assert_eq!(client.run().await, TrapReason::SystemCall);
+            let [len, sum, badge, buf, ..] = client.with_context(|ctx| {
+                assert_eq!(ctx.syscall_num(), 0xC0DE);
+                ctx.syscall_args()
+            });
+            assert_eq!(len, 10);
+            assert_eq!(sum, 42);
+            assert_eq!(badge, client_id);
+
+            let mut data = [0u8; 10];
+            client
+                .with_addr_space(|addr_space| addr_space.read_bytes(VirtAddr::new(buf), &mut data))
+                .unwrap();
+            assert_eq!(&data, b"cpi ,olleh");
+
+            client.exit().unwrap();
+            endpoint.destroy().unwrap();
+        });
+
+        spawn!(pri := TaskPriority::MAX / 3 => async move {
+            assert_eq!(server.run().await, TrapReason::SystemCall);
+            let [len, badge, expects_reply, ..] = server.with_context(|ctx| {
+                assert_eq!(ctx.syscall_num(), 0xC0DE);
+                ctx.syscall_args()
+            });
+            assert_eq!(len, 10);
+            assert_eq!(badge, client_id);
+            assert_eq!(expects_reply, 1);
+            assert_ne!(badge, server_id);
+
+            server.exit().unwrap();
+        });
+    }
+}
+
+pub(super) mod shm {
+    use jrinx_addr::VirtAddr;
+    use jrinx_config::PAGE_SIZE;
+    use jrinx_multitask::{spawn, TaskPriority};
+    use jrinx_paging::{GenericPagePerm, GenericPageTable, PagePerm};
+    use jrinx_process::{Personality, Process};
+    use jrinx_testdef::testdef;
+    use jrinx_trap::{GenericContext, TrapReason};
+    use jrinx_vmm::shm::SharedMemory;
+
+    const SHM_NAME: &str = "test-shm";
+    const SHM_UNMAPPED_NAME: &str = "test-shm-unmapped";
+    const SHM_MAGIC: usize = 0x5A5A;
+
+    #[testdef]
+    fn test() {
+        // an object never mapped is released with its last reference
+        let unmapped = SharedMemory::create(SHM_UNMAPPED_NAME, PAGE_SIZE, PagePerm::R).unwrap();
+        let opened = SharedMemory::open(SHM_UNMAPPED_NAME).unwrap();
+        assert_eq!(unmapped.shm().refs(), 2);
+        drop(unmapped);
+        assert!(SharedMemory::find(SHM_UNMAPPED_NAME).is_ok());
+        drop(opened);
+        assert!(SharedMemory::find(SHM_UNMAPPED_NAME).is_err());
+
+        let shm_user = jrinx_uprog::find("test/shm-user").unwrap();
+        let creator = Process::create(&shm_user, Personality::Native).unwrap();
+        let reader = Process::create(&shm_user, Personality::Native).unwrap();
+        for (role, process) in [&creator, &reader].into_iter().enumerate() {
+            process.with_context(|ctx| {
+                ctx.disable_int();
+                ctx.set_syscall_args([role, 0, 0, 0, 0, 0]);
+            });
+        }
+
+        spawn!(pri := TaskPriority::MAX / 2 => async move {
+            assert_eq!(creator.run().await, TrapReason::SystemCall);
+            let [creator_addr, ..] = creator.with_context(|ctx| ctx.syscall_args());
+            let shm = SharedMemory::find(SHM_NAME).unwrap();
+            assert_eq!(shm.len(), PAGE_SIZE * 2);
+            assert_eq!(shm.maps(), 1);
+            assert_eq!(shm.shared_perm(), PagePerm::R);
+
+            assert_eq!(reader.run().await, TrapReason::SystemCall);
+            let [reader_addr, value, ..] = reader.with_context(|ctx| {
+                assert_eq!(ctx.syscall_num(), 0xC0DE);
+                ctx.syscall_args()
+            });
+            assert_eq!(value, SHM_MAGIC * 2 + 1);
+
+            let creator_addr = VirtAddr::new(creator_addr);
+            let reader_addr = VirtAddr::new(reader_addr);
+            let (creator_paddr, creator_perm) = creator
+                .with_addr_space(|addr_space| addr_space.page_table().translate(creator_addr))
+                .unwrap();
+            let (reader_paddr, reader_perm) = reader
+                .with_addr_space(|addr_space| addr_space.page_table().translate(reader_addr))
+                .unwrap();
+            assert_eq!(creator_paddr, reader_paddr);
+            assert!(creator_perm.contains(PagePerm::W));
+            assert!(!reader_perm.contains(PagePerm::W));
+            drop(shm);
+
+            assert_eq!(
+                reader.run().await,
+                TrapReason::PageFault {
+                    addr: reader_addr,
+                    perm: PagePerm::W,
+                }
+            );
+
+            creator.exit().unwrap();
+            drop(creator);
+            assert!(SharedMemory::find(SHM_NAME).is_ok());
+            reader.exit().unwrap();
+            drop(reader);
+            assert!(SharedMemory::find(SHM_NAME).is_err());
+        });
+    }
+}
+
+pub(super) mod pipe {
+    use jrinx_addr::VirtAddr;
+    use jrinx_multitask::{spawn, TaskPriority};
+    use jrinx_process::{Personality, Process, ProcessId};
+    use jrinx_testdef::testdef;
+    use jrinx_trap::{GenericContext, TrapReason};
+
+    const MSG: &[u8] = b"hello, pipe";
+
+    #[testdef]
+    fn test() {
+        let pipe_user = jrinx_uprog::find("test/pipe-user").unwrap();
+        let parent = Process::create(&pipe_user, Personality::Native).unwrap();
+        parent.with_context(|ctx| ctx.disable_int());
+
+        spawn!(pri := TaskPriority::MAX / 2 => async move {
+            assert_eq!(parent.run().await, TrapReason::SystemCall);
+            let [child_id, ..] = parent.with_context(|ctx| ctx.syscall_args());
+            let child = Process::find(ProcessId::from(child_id)).unwrap();
+
+            // the child only runs once the parent parks on the empty pipe
+            spawn!(pri := TaskPriority::MAX / 3 => async move {
+                assert_eq!(child.run().await, TrapReason::SystemCall);
+                let [written, closed, ..] = child.with_context(|ctx| {
+                    assert_eq!(ctx.syscall_num(), 0xC0DE);
+                    ctx.syscall_args()
+                });
+                assert_eq!(written, MSG.len());
+                assert_eq!(closed, 0);
+
+                child.exit().unwrap();
+            });
+
+            assert_eq!(parent.run().await, TrapReason::SystemCall);
+            let [len, eof, nonblock, stdout, written, buf] = parent.with_context(|ctx| {
+                assert_eq!(ctx.syscall_num(), 0xC0DE);
+                ctx.syscall_args()
+            });
+            assert_eq!(len, MSG.len());
+            assert_eq!(eof, 0);
+            assert_eq!(nonblock, usize::MAX);
+            assert_eq!(stdout, 6);
+            assert_eq!(written, 8);
+
+            let mut data = [0u8; MSG.len()];
+            parent
+                .with_addr_space(|addr_space| addr_space.read_bytes(VirtAddr::new(buf), &mut data))
+                .unwrap();
+            assert_eq!(&data, MSG);
+
+            parent.exit().unwrap();
+        });
+    }
+}
+
+pub(super) mod cap {
+    use jrinx_multitask::{spawn, TaskPriority};
+    use jrinx_process::{
+        cap::{Handle, KernelObject, Rights},
+        ipc::Endpoint,
+        Personality, Process,
+    };
+    use jrinx_testdef::testdef;
+    use jrinx_trap::{GenericContext, TrapReason};
+
+    const SHM_MAGIC: usize = 0xCA9;
+
+    #[testdef]
+    fn test() {
+        let endpoint = Endpoint::create();
+
+        let cap_user = jrinx_uprog::find("test/cap-user").unwrap();
+        let sender = Process::create(&cap_user, Personality::Native).unwrap();
+        let receiver = Process::create(&cap_user, Personality::Native).unwrap();
+        for (role, process, rights) in [(0, &sender, Rights::WRITE), (1, &receiver, Rights::READ)] {
+            let handle = process.with_handles(|handles| {
+                handles.insert(Handle::new(
+                    KernelObject::Endpoint(endpoint.clone()),
+                    rights,
+                ))
+            });
+            process.with_context(|ctx| {
+                ctx.disable_int();
+                ctx.set_syscall_args([role, handle, 0, 0, 0, 0]);
+            });
+        }
+
+        // the receiver runs first and parks on its receive until the sender shows up
+        spawn!(pri := TaskPriority::MAX / 2 => async move {
+            assert_eq!(receiver.run().await, TrapReason::SystemCall);
+            let [value, writable, handle, ..] = receiver.with_context(|ctx| {
+                assert_eq!(ctx.syscall_num(), 0xC0DE);
+                ctx.syscall_args()
+            });
+            assert_eq!(value, SHM_MAGIC);
+            assert_eq!(writable, usize::MAX);
+            let rights = receiver.with_handles(|handles| handles.get(handle).unwrap().rights());
+            assert_eq!(rights, Rights::READ | Rights::MAP);
+
+            receiver.exit().unwrap();
+        });
+
+        spawn!(pri := TaskPriority::MAX / 3 => async move {
+            assert_eq!(sender.run().await, TrapReason::SystemCall);
+            let [escalated, closed, received, ..] = sender.with_context(|ctx| {
+                assert_eq!(ctx.syscall_num(), 0xC0DE);
+                ctx.syscall_args()
+            });
+            assert_eq!(escalated, usize::MAX);
+            assert_eq!(closed, usize::MAX);
+            assert_eq!(received, usize::MAX);
+
+            sender.exit().unwrap();
+            endpoint.destroy().unwrap();
+        });
+    }
+}
+
+pub(super) mod signal {
+    use jrinx_multitask::{spawn, TaskPriority};
+    use jrinx_process::{
+        signal::{SIGALRM, SIGKILL, SIGTERM, SIG_EXIT_BASE},
+        Personality, Process,
+    };
+    use jrinx_testdef::testdef;
+    use jrinx_trap::{GenericContext, TrapReason};
+
+    const MAGIC: usize = 0x5167;
+
+    #[testdef]
+    fn test() {
+        let signal_user = jrinx_uprog::find("test/signal-user").unwrap();
+        let process = Process::create(&signal_user, Personality::Native).unwrap();
+        process.with_context(|ctx| ctx.disable_int());
+        // the timer interrupt lets the kernel deliver the alarm to a process that never traps
+        let computer = Process::create(&signal_user, Personality::Native).unwrap();
+        computer.with_context(|ctx| {
+            ctx.enable_int();
+            ctx.set_syscall_args([1, 0, 0, 0, 0, 0]);
+        });
+
+        spawn!(pri := TaskPriority::MAX / 2 => async move {
+            assert_eq!(process.run().await, TrapReason::SystemCall);
+            let [value, fault_addr, addr, ..] = process.with_context(|ctx| {
+                assert_eq!(ctx.syscall_num(), 0xC0DE);
+                ctx.syscall_args()
+            });
+            assert_eq!(value, MAGIC);
+            assert_eq!(fault_addr, addr);
+
+            process.kill(SIGTERM).unwrap();
+            assert_eq!(process.run().await, TrapReason::SystemCall);
+            let [terms, ..] = process.with_context(|ctx| {
+                assert_eq!(ctx.syscall_num(), 0xC0DE);
+                ctx.syscall_args()
+            });
+            assert_eq!(terms, 1);
+
+            assert_eq!(process.run().await, TrapReason::SystemCall);
+            let [waited, alrms, ..] = process.with_context(|ctx| {
+                assert_eq!(ctx.syscall_num(), 0xC0DE);
+                ctx.syscall_args()
+            });
+            assert_eq!(waited, usize::MAX);
+            assert_eq!(alrms, 1);
+
+            // the process blocks again, which a kill interrupts
+            process.kill(SIGKILL).unwrap();
+            assert_eq!(process.run().await, TrapReason::SystemCall);
+            assert_eq!(process.exit_code(), Some(SIG_EXIT_BASE + SIGKILL));
+
+            process.exit().unwrap();
+
+            computer.run().await;
+            assert_eq!(computer.exit_code(), Some(SIG_EXIT_BASE + SIGALRM));
+            computer.exit().unwrap();
+        });
+    }
+}
+
+pub(super) mod udrv {
+    use jrinx_driver::{device, irq::user_irq::IrqLine};
+    use jrinx_multitask::{spawn, TaskPriority};
+    use jrinx_process::{Personality, Process};
+    use jrinx_testdef::testdef;
+    use jrinx_trap::{GenericContext, TrapReason};
+
+    #[testdef]
+    fn test() {
+        // the test is only run on boards with a goldfish RTC, see its yml
+        let rtc = device::find("google,goldfish-rtc").expect("no goldfish RTC to drive");
+        let (addr, _) = rtc.regs()[0];
+        let irq = rtc.irqs()[0];
+
+        let udrv_user = jrinx_uprog::find("test/udrv-user").unwrap();
+        let process = Process::create(&udrv_user, Personality::Native).unwrap();
+        process.with_context(|ctx| {
+            ctx.disable_int();
+            ctx.set_syscall_args([addr.as_usize(), irq, 0, 0, 0, 0]);
+        });
+
+        spawn!(pri := TaskPriority::MAX / 2 => async move {
+            assert_eq!(process.run().await, TrapReason::SystemCall);
+            let [claimed_twice, beyond, waited, acked, on_time, executable] =
+                process.with_context(|ctx| {
+                    assert_eq!(ctx.syscall_num(), 0xC0DE);
+                    ctx.syscall_args()
+                });
+            assert_eq!(claimed_twice, usize::MAX);
+            assert_eq!(beyond, usize::MAX);
+            assert_eq!(waited, 0);
+            assert_eq!(acked, 0);
+            assert_eq!(on_time, 1);
+            assert_eq!(executable, usize::MAX);
+
+            // the line is released along with the handles of the process
+            process.exit().unwrap();
+            drop(process);
+            drop(IrqLine::claim(irq).unwrap());
+        });
+    }
+}
+
+pub(super) mod uaccess {
+    use jrinx_addr::VirtAddr;
+    use jrinx_config::PAGE_SIZE;
+    use jrinx_paging::{GenericPagePerm, PagePerm};
+    use jrinx_process::{
+        uaccess::{copy_from_user, copy_to_user, strncpy_from_user},
+        Personality, Process,
+    };
+    use jrinx_testdef::testdef;
+
+    const GREETING: &[u8] = b"hello\0";
+
+    #[testdef]
+    fn test() {
+        let pipe_user = jrinx_uprog::find("test/pipe-user").unwrap();
+        let process = Process::create(&pipe_user, Personality::Native).unwrap();
+        let (rw, ro) = process.with_addr_space(|addr_space| {
+            let rw = addr_space
+                .map(None, PAGE_SIZE * 2, PagePerm::R | PagePerm::W)
+                .unwrap();
+            let ro = addr_space.map(None, PAGE_SIZE, PagePerm::R).unwrap();
+            (rw, ro)
+        });
+
+        // the string straddles the two writable pages
+        let addr = rw + (PAGE_SIZE - 2);
+        copy_to_user(&process, addr, GREETING).unwrap();
+        let mut buf = [0u8; GREETING.len()];
+        copy_from_user(&process, addr, &mut buf).unwrap();
+        assert_eq!(buf, GREETING);
+
+        let mut buf = [0xffu8; 16];
+        assert_eq!(strncpy_from_user(&process, addr, &mut buf).unwrap(), 5);
+        assert_eq!(&buf[..6], GREETING);
+        let mut buf = [0u8; 3];
+        assert_eq!(strncpy_from_user(&process, addr, &mut buf).unwrap(), 3);
+        assert_eq!(&buf, b"hel");
+
+        assert!(copy_to_user(&process, ro, GREETING).is_err());
+        assert!(copy_from_user(&process, ro + (PAGE_SIZE - 1), &mut [0u8; 2]).is_err());
+        assert!(copy_from_user(&process, VirtAddr::new(usize::MAX), &mut [0u8; 2]).is_err());
+
+        // without the checks, the fault is recovered through the exception table
+        let mut byte = 0u8;
+        let result = unsafe { jrinx_trap::uaccess::copy(&mut byte, PAGE_SIZE as *const u8, 1) };
+        assert!(result.is_err());
+
+        process.exit().unwrap();
+    }
+}
+
+pub(super) mod coredump {
+    use alloc::vec;
+    use core::mem::size_of;
+
+    use elf::{
+        abi::{EM_RISCV, ET_CORE, PT_LOAD, PT_NOTE},
+        endian::AnyEndian,
+        note::Note,
+        ElfBytes,
+    };
+    use jrinx_addr::VirtAddr;
+    use jrinx_config::PAGE_SIZE;
+    use jrinx_driver::BlockDevice;
+    use jrinx_multitask::{spawn, TaskPriority};
+    use jrinx_paging::{GenericPagePerm, PagePerm};
+    use jrinx_process::{
+        coredump::{self, CoreTarget, NT_PRSTATUS, NT_SIGINFO},
+        signal::SIGSEGV,
+        Personality, Process,
+    };
+    use jrinx_testdef::testdef;
+    use jrinx_trap::{GenericContext, TrapReason};
+
+    const WORD_SIZE: usize = size_of::<usize>();
+    const UNTOUCHED_LEN: usize = 64 * PAGE_SIZE;
+
+    fn word(desc: &[u8], offset: usize) -> usize {
+        usize::from_le_bytes(desc[offset..][..WORD_SIZE].try_into().unwrap())
+    }
+
+    #[testdef]
+    fn test() {
+        let nullptr_reader = jrinx_uprog::find("test/nullptr-reader").unwrap();
+        let process = Process::create(&nullptr_reader, Personality::Native).unwrap();
+        process.with_context(|ctx| ctx.disable_int());
+
+        spawn!(pri := TaskPriority::MAX / 2 => async move {
+            let reason = process.run().await;
+            assert_eq!(
+                reason,
+                TrapReason::PageFault {
+                    addr: VirtAddr::new(0),
+                    perm: PagePerm::R,
+                }
+            );
+            let ctx = process.with_context(|ctx| *ctx);
+            // an area whose pages are never touched
+            let untouched = process.with_addr_space(|addr_space| {
+                let start = (addr_space.brk() + PAGE_SIZE).align_page_up();
+                addr_space
+                    .insert(start, start + UNTOUCHED_LEN, PagePerm::R | PagePerm::W)
+                    .unwrap();
+                start.as_usize() as u64
+            });
+            let core = coredump::build(&process, &ctx, reason).unwrap();
+
+            let elf = ElfBytes::<AnyEndian>::minimal_parse(&core).unwrap();
+            assert_eq!(elf.ehdr.e_type, ET_CORE);
+            assert_eq!(elf.ehdr.e_machine, EM_RISCV);
+            let segments = elf.segments().unwrap();
+            let loads = segments.iter().filter(|phdr| phdr.p_type == PT_LOAD).count();
+            assert!(loads >= process.with_addr_space(|addr_space| addr_space.areas().count()));
+            let untouched = segments
+                .iter()
+                .find(|phdr| phdr.p_type == PT_LOAD && phdr.p_vaddr == untouched)
+                .unwrap();
+            assert_eq!(untouched.p_filesz, 0);
+            assert_eq!(untouched.p_memsz, UNTOUCHED_LEN as u64);
+
+            let notes = segments.iter().find(|phdr| phdr.p_type == PT_NOTE).unwrap();
+            let mut found = 0;
+            for note in elf.segment_data_as_notes(&notes).unwrap() {
+                let Note::Unknown(note) = note else {
+                    continue;
+                };
+                match note.n_type as u32 {
+                    NT_PRSTATUS => {
+                        // pr_reg follows 32 bytes of 32-bit fields and 10 words
+                        let pr_reg = 32 + 10 * WORD_SIZE;
+                        assert_eq!(word(note.desc, pr_reg), ctx.pc());
+                        assert_eq!(word(note.desc, pr_reg + 2 * WORD_SIZE), ctx.sp());
+                        found += 1;
+                    }
+                    NT_SIGINFO => {
+                        assert_eq!(note.desc[0] as usize, SIGSEGV);
+                        assert_eq!(word(note.desc, 12usize.next_multiple_of(WORD_SIZE)), 0);
+                        found += 1;
+                    }
+                    _ => {}
+                }
+            }
+            assert_eq!(found, 2);
+
+            // the faulting instruction is dumped as it is in memory
+            let pc = ctx.pc() as u64;
+            let text = segments
+                .iter()
+                .find(|phdr| {
+                    phdr.p_type == PT_LOAD
+                        && (phdr.p_vaddr..phdr.p_vaddr + phdr.p_filesz).contains(&pc)
+                })
+                .unwrap();
+            let offset = (text.p_offset + pc - text.p_vaddr) as usize;
+            let mut insn = [0u8; 2];
+            process
+                .with_addr_space(|addr_space| {
+                    addr_space.read_bytes(VirtAddr::new(ctx.pc()), &mut insn)
+                })
+                .unwrap();
+            assert_eq!(core[offset..offset + 2], insn);
+
+            // the core goes to the console unless a block device is chosen, whose blocks from the
+            // one chosen on then hold it as is
+            assert_eq!(
+                coredump::dump(&process, &ctx, reason).unwrap(),
+                CoreTarget::Console
+            );
+            let dev = jrinx_driver::blk::first();
+            let block = dev.as_ref().and_then(|dev| {
+                dev.num_blocks()
+                    .checked_sub(core.len().div_ceil(dev.block_size()))
+            });
+            match dev.zip(block) {
+                Some((dev, block)) => {
+                    let target = CoreTarget::Block { dev: 0, block };
+                    coredump::set_target(target);
+                    assert_eq!(coredump::dump(&process, &ctx, reason).unwrap(), target);
+                    coredump::set_target(CoreTarget::Console);
+
+                    let mut blocks = vec![0; core.len().next_multiple_of(dev.block_size())];
+                    dev.read_blocks(block, &mut blocks).unwrap();
+                    assert_eq!(blocks[..core.len()], core);
+                }
+                None => warn!("no block device to write a core to, skipped"),
+            }
+
+            process.exit().unwrap();
+        });
+    }
+}
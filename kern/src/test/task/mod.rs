@@ -109,4 +109,65 @@ pub(super) mod inspector {
     }
 }
 
+pub(super) mod leak {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use jrinx_hal::{Cpu, Hal};
+    use jrinx_multitask::{spawn, yield_now, TaskPriority};
+    use jrinx_testdef::testdef;
+
+    const TASKS: usize = 1000;
+
+    static DONE: AtomicUsize = AtomicUsize::new(0);
+
+    #[testdef]
+    fn test() {
+        spawn!(pri := TaskPriority::MAX / 3 => async {
+            // the first round lets the executor grow to the size it needs
+            run().await;
+
+            let before = jrinx_heap::stats();
+            #[cfg(feature = "heap-trace")]
+            let (mark, dropped) = (jrinx_heap::trace::mark(), jrinx_heap::trace::dropped());
+            run().await;
+            let after = jrinx_heap::stats();
+            info!("heap stats before {:?}, after {:?}", before, after);
+
+            #[cfg(feature = "heap-trace")]
+            check(mark, dropped);
+            // other CPUs allocate meanwhile, the counters tell about this test only on a single one
+            if hal!().cpu().nproc() == 1 {
+                assert_eq!(after.used, before.used);
+            }
+            assert!(after.peak >= after.used);
+            assert!(after.total >= after.used + after.largest_free);
+        });
+    }
+
+    /// Asserts that the allocations made on this CPU since `mark` have all been freed, none of them
+    /// having gone unrecorded.
+    #[cfg(feature = "heap-trace")]
+    fn check(mark: u64, dropped: usize) {
+        assert_eq!(jrinx_heap::trace::dropped(), dropped);
+        let leaks = jrinx_heap::trace::leaks(mark);
+        let own = leaks.iter().filter(|leak| leak.cpu == hal!().cpu().id());
+        for leak in own.clone() {
+            error!("leaked {:?}", leak);
+        }
+        assert_eq!(own.count(), 0);
+    }
+
+    async fn run() {
+        DONE.store(0, Ordering::SeqCst);
+        for _ in 0..TASKS {
+            spawn!(pri := TaskPriority::MAX / 2 => async {
+                DONE.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        while DONE.load(Ordering::SeqCst) < TASKS {
+            yield_now!();
+        }
+    }
+}
+
 pub(super) mod runtime;
@@ -191,3 +191,51 @@ pub(super) mod sched_table {
         Runtime::switch_yield();
     }
 }
+
+pub(super) mod oom {
+    use alloc::vec;
+    use jrinx_error::InternalError;
+    use jrinx_hal::{Hal, Interrupt};
+    use jrinx_multitask::{
+        executor::{Executor, ExecutorPriority},
+        inspector::Inspector,
+        runtime::{self, Runtime},
+        Task, TaskPriority,
+    };
+    use jrinx_testdef::testdef;
+
+    const BALLAST_SIZE: usize = 64 * 1024;
+
+    #[testdef]
+    fn test() {
+        let ballast = vec![0u8; BALLAST_SIZE];
+        let inspector = Inspector::new(Executor::new(
+            ExecutorPriority::new(0),
+            Task::new(
+                async move {
+                    drop(ballast);
+                },
+                TaskPriority::default(),
+            ),
+        ));
+        let shed_id = inspector.id();
+        Runtime::with_current(|rt| rt.register(inspector).unwrap());
+        runtime::set_oom_shed_below(Some(ExecutorPriority::new(1)));
+
+        let task = Task::new(async {}, TaskPriority::default());
+        let result = hal!().interrupt().with_saved_off(|| {
+            // this CPU runs out of memory at once, while the heap is left to the others
+            jrinx_heap::set_quota(Some(0));
+            let result = Executor::try_new(ExecutorPriority::default(), task).map(|_| ());
+            jrinx_heap::set_quota(None);
+            result
+        });
+        runtime::set_oom_shed_below(None);
+
+        assert!(matches!(result, Err(InternalError::NotEnoughMem)));
+        assert!(matches!(
+            Runtime::with_current(|rt| rt.with_inspector(shed_id, |_| ())),
+            Err(InternalError::InvalidInspectorId)
+        ));
+    }
+}
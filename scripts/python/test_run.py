@@ -9,6 +9,12 @@ import subprocess
 from typing import Callable, Sequence
 
 import pathos.multiprocessing as mp
+import yaml
+
+try:
+    from yaml import CLoader as Loader
+except ImportError:
+    from yaml import Loader
 
 from util import *
 
@@ -18,6 +24,12 @@ TESTS_DIR = dir_ancestor_find(
 ).parent / 'tests'
 
 
+def test_boards(file: pathlib.Path) -> list[str] | None:
+    with file.open('r', encoding='utf-8') as f:
+        conf = yaml.load(f, Loader=Loader)
+    return conf.get('boards')
+
+
 def run_test(file: pathlib.Path,
              include_dirs: list[pathlib.Path],
              board: str,
@@ -25,7 +37,12 @@ def run_test(file: pathlib.Path,
              verbose: bool = False,
              pre_run: Callable[[], None],
              on_success: Callable[[], None],
-             on_failure: Callable[[], None]) -> int:
+             on_failure: Callable[[], None],
+             on_skip: Callable[[], None]) -> int:
+    # tests relying on devices only some boards have list those boards
+    if (boards := test_boards(file)) is not None and board not in boards:
+        on_skip()
+        return 0
     pre_run()
     cmd = [
         dir_ancestor_find(pathlib.Path(__file__), 'scripts') / 'judge',
@@ -71,6 +88,7 @@ def run_testset(testset: tuple[pathlib.Path],
             pre_run=lambda: info(f'On {board:<14} Run    {slug}'),
             on_success=lambda: info(f'On {board:<14} Passed {slug}'),
             on_failure=lambda: fatal(f'On {board:<14} Failed {slug}'),
+            on_skip=lambda: info(f'On {board:<14} Skip   {slug}'),
         )
 
     comb = tuple(itertools.product(testset, board))
@@ -119,7 +137,7 @@ def run_testset_rich(testset: tuple[pathlib.Path],
 
     def gen_table():
         table = Table('Test', 'Board',
-                      'Status (waiting|running|passed|failed)')
+                      'Status (waiting|running|passed|failed|skipped)')
         time_cost = datetime.datetime.now() - start_time
         table.title = Text(
             f'Run testset on {os.environ["ARCH"]} in {os.environ["BUILD_MODE"]} mode (cost {time_cost})',
@@ -133,7 +151,8 @@ def run_testset_rich(testset: tuple[pathlib.Path],
                     status,
                     style='white' if status == 'waiting'
                     else 'yellow' if status == 'running'
-                    else 'green' if status == 'passed' else 'red',
+                    else 'green' if status == 'passed'
+                    else 'blue' if status == 'skipped' else 'red',
                 )),
             )
         return Align.center(table)
@@ -162,6 +181,10 @@ def run_testset_rich(testset: tuple[pathlib.Path],
                 test_status[slug] = 'failed'
                 live.update(gen_table())
 
+            def on_skip():
+                test_status[slug] = 'skipped'
+                live.update(gen_table())
+
             return run_test(
                 file,
                 include_dirs,
@@ -170,6 +193,7 @@ def run_testset_rich(testset: tuple[pathlib.Path],
                 pre_run=pre_run,
                 on_success=on_success,
                 on_failure=on_failure,
+                on_skip=on_skip,
             )
 
         result = mp.ThreadingPool(
@@ -184,6 +208,7 @@ def run_testset_rich(testset: tuple[pathlib.Path],
 def main():
     args = argparse.ArgumentParser()
     args.add_argument('-f', '--file', type=file_path)
+    args.add_argument('-s', '--suite', type=str)
     args.add_argument('-I', '--include-dir',
                       type=dir_path,
                       action='append',
@@ -205,9 +230,10 @@ def main():
     if args.file:
         testset = tuple((args.file.resolve(),))
     else:
+        suite_dir = TESTS_DIR / 'kern' / (args.suite or '')
         testset = tuple(itertools.chain(
-            (TESTS_DIR / 'kern').rglob('*.yml'),
-            (TESTS_DIR / 'kern').rglob('*.yaml'),
+            suite_dir.rglob('*.yml'),
+            suite_dir.rglob('*.yaml'),
         ))
 
     if not args.no_build:
@@ -0,0 +1,92 @@
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+
+const SYS_SEND: usize = 0x07;
+const SYS_RECV: usize = 0x08;
+const SYS_SHM_CREATE: usize = 0x0B;
+const SYS_HANDLE_DUP: usize = 0x12;
+const SYS_HANDLE_CLOSE: usize = 0x13;
+const SYS_MEM_MAP: usize = 0x14;
+
+const PROT_READ: usize = 0x1;
+const PROT_WRITE: usize = 0x2;
+
+const RIGHT_READ: usize = 1 << 0;
+const RIGHT_WRITE: usize = 1 << 1;
+const RIGHT_MAP: usize = 1 << 2;
+
+const PAGE_SIZE: usize = 4096;
+
+const SHM_NAME: &str = "cap-shm";
+const SHM_MAGIC: usize = 0xCA9;
+
+const ROLE_SENDER: usize = 0;
+
+fn syscall(num: usize, args: [usize; 6]) -> [usize; 6] {
+    let mut regs = args;
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") regs[0],
+            inlateout("a1") regs[1],
+            inlateout("a2") regs[2],
+            inlateout("a3") regs[3],
+            inlateout("a4") regs[4],
+            inlateout("a5") regs[5],
+            in("a7") num,
+        );
+    }
+    regs
+}
+
+fn call(num: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
+    syscall(num, [arg0, arg1, arg2, 0, 0, 0])[0]
+}
+
+#[no_mangle]
+extern "C" fn _start(role: usize, endpoint: usize) -> ! {
+    if role == ROLE_SENDER {
+        let shm = call(
+            SYS_SHM_CREATE,
+            SHM_NAME.as_ptr() as usize,
+            SHM_NAME.len(),
+            PAGE_SIZE,
+        );
+        assert_ne!(shm, usize::MAX);
+        let addr = call(SYS_MEM_MAP, shm, 0, PROT_READ | PROT_WRITE);
+        assert_ne!(addr, usize::MAX);
+        unsafe { *(addr as *mut usize) = SHM_MAGIC };
+
+        // rights can only be dropped, never regained
+        let readonly = call(SYS_HANDLE_DUP, shm, RIGHT_READ | RIGHT_MAP, 0);
+        assert_ne!(readonly, usize::MAX);
+        let all = RIGHT_READ | RIGHT_WRITE | RIGHT_MAP;
+        let escalated = call(SYS_HANDLE_DUP, readonly, all, 0);
+
+        syscall(SYS_SEND, [endpoint, 0, 0, 0, 0, readonly]);
+        let closed = call(SYS_HANDLE_CLOSE, readonly, 0, 0);
+
+        // the endpoint handle of the sender lacks the right to receive
+        let received = call(SYS_RECV, endpoint, 0, 0);
+
+        syscall(0xC0DE, [escalated, closed, received, 0, 0, 0]);
+    } else {
+        let [_, _, _, _, _, shm] = syscall(SYS_RECV, [endpoint, 0, 0, 0, 0, 0]);
+        let addr = call(SYS_MEM_MAP, shm, 0, PROT_READ);
+        assert_ne!(addr, usize::MAX);
+        let value = unsafe { *(addr as *const usize) };
+        let writable = call(SYS_MEM_MAP, shm, 0, PROT_READ | PROT_WRITE);
+
+        syscall(0xC0DE, [value, writable, shm, 0, 0, 0]);
+    }
+
+    loop {}
+}
+
+#[panic_handler]
+fn panic(_: &PanicInfo) -> ! {
+    unreachable!();
+}
@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+
+const SYS_FORK: usize = 0x01;
+
+const PARENT_MAGIC: usize = 0xBEEF;
+const CHILD_MAGIC: usize = 0xCAFE;
+
+static mut VALUE: usize = 0;
+
+fn syscall(num: usize, arg0: usize, arg1: usize) -> usize {
+    let mut ret = arg0;
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    unsafe {
+        core::arch::asm!("ecall", inlateout("a0") ret, in("a1") arg1, in("a7") num);
+    }
+    ret
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    let pid = syscall(SYS_FORK, 0, 0);
+    unsafe {
+        VALUE = if pid == 0 { CHILD_MAGIC } else { PARENT_MAGIC };
+        syscall(0xC0DE, core::ptr::addr_of!(VALUE) as usize, pid);
+    }
+    loop {}
+}
+
+#[panic_handler]
+fn panic(_: &PanicInfo) -> ! {
+    unreachable!();
+}
@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+
+const SYS_CALL: usize = 0x09;
+
+fn syscall(num: usize, args: [usize; 6]) -> [usize; 6] {
+    let mut regs = args;
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") regs[0],
+            inlateout("a1") regs[1],
+            inlateout("a2") regs[2],
+            inlateout("a3") regs[3],
+            inlateout("a4") regs[4],
+            inlateout("a5") regs[5],
+            in("a7") num,
+        );
+    }
+    regs
+}
+
+#[no_mangle]
+extern "C" fn _start(endpoint: usize) -> ! {
+    let mut buf = *b"hello, ipc";
+    let [len, _, _, sum, badge, _] = syscall(
+        SYS_CALL,
+        [endpoint, buf.as_mut_ptr() as usize, buf.len(), 20, 22, 0],
+    );
+
+    syscall(0xC0DE, [len, sum, badge, buf.as_ptr() as usize, 0, 0]);
+
+    loop {}
+}
+
+#[panic_handler]
+fn panic(_: &PanicInfo) -> ! {
+    unreachable!();
+}
@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+
+const SYS_RECV: usize = 0x08;
+const SYS_REPLY: usize = 0x0A;
+
+fn syscall(num: usize, args: [usize; 6]) -> [usize; 6] {
+    let mut regs = args;
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") regs[0],
+            inlateout("a1") regs[1],
+            inlateout("a2") regs[2],
+            inlateout("a3") regs[3],
+            inlateout("a4") regs[4],
+            inlateout("a5") regs[5],
+            in("a7") num,
+        );
+    }
+    regs
+}
+
+#[no_mangle]
+extern "C" fn _start(endpoint: usize) -> ! {
+    let mut buf = [0u8; 64];
+    let [len, badge, expects_reply, lhs, rhs, _] = syscall(
+        SYS_RECV,
+        [endpoint, buf.as_mut_ptr() as usize, buf.len(), 0, 0, 0],
+    );
+
+    buf[..len].reverse();
+    if expects_reply != 0 {
+        syscall(
+            SYS_REPLY,
+            [0, buf.as_ptr() as usize, len, lhs + rhs, badge, 0],
+        );
+    }
+
+    syscall(0xC0DE, [len, badge, expects_reply, 0, 0, 0]);
+
+    loop {}
+}
+
+#[panic_handler]
+fn panic(_: &PanicInfo) -> ! {
+    unreachable!();
+}
@@ -0,0 +1,118 @@
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+
+const SYS_IOCTL: usize = 29;
+const SYS_WRITE: usize = 64;
+const SYS_EXIT_GROUP: usize = 94;
+const SYS_SET_TID_ADDRESS: usize = 96;
+const SYS_CLOCK_GETTIME: usize = 113;
+const SYS_UNAME: usize = 160;
+const SYS_GETPID: usize = 172;
+const SYS_BRK: usize = 214;
+const SYS_MMAP: usize = 222;
+
+const EINVAL: usize = 22;
+const ENOSYS: usize = 38;
+
+const CLOCK_REALTIME: usize = 0;
+const CLOCK_MONOTONIC: usize = 1;
+
+const PROT_READ: usize = 0x1;
+const PROT_WRITE: usize = 0x2;
+const MAP_PRIVATE: usize = 0x02;
+const MAP_ANONYMOUS: usize = 0x20;
+
+const PAGE_SIZE: usize = 4096;
+
+fn syscall(num: usize, args: [usize; 6]) -> usize {
+    let mut ret = args[0];
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") ret,
+            in("a1") args[1],
+            in("a2") args[2],
+            in("a3") args[3],
+            in("a4") args[4],
+            in("a5") args[5],
+            in("a7") num,
+        );
+    }
+    ret
+}
+
+fn exit_group(code: usize) -> ! {
+    syscall(SYS_EXIT_GROUP, [code, 0, 0, 0, 0, 0]);
+    unreachable!();
+}
+
+fn check(step: usize, ok: bool) {
+    if !ok {
+        exit_group(step);
+    }
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    let msg = b"hello from linux personality\n";
+    let ret = syscall(SYS_WRITE, [1, msg.as_ptr() as usize, msg.len(), 0, 0, 0]);
+    check(1, ret == msg.len());
+
+    let pid = syscall(SYS_GETPID, [0; 6]);
+    check(2, pid != 0 && (pid as isize) > 0);
+    // cleared by the kernel once the process exits
+    let mut tid_cell = u32::MAX;
+    let tid = syscall(
+        SYS_SET_TID_ADDRESS,
+        [&mut tid_cell as *mut u32 as usize, 0, 0, 0, 0, 0],
+    );
+    check(3, tid == pid);
+
+    let mut utsname = [0u8; 65 * 6];
+    let ret = syscall(SYS_UNAME, [utsname.as_mut_ptr() as usize, 0, 0, 0, 0, 0]);
+    check(4, ret == 0 && &utsname[..6] == b"Jrinx\0");
+
+    let mut timespec = [0u64; 2];
+    let ret = syscall(
+        SYS_CLOCK_GETTIME,
+        [CLOCK_MONOTONIC, timespec.as_mut_ptr() as usize, 0, 0, 0, 0],
+    );
+    check(5, ret == 0 && timespec[1] < 1_000_000_000);
+    let ret = syscall(
+        SYS_CLOCK_GETTIME,
+        [CLOCK_REALTIME, timespec.as_mut_ptr() as usize, 0, 0, 0, 0],
+    );
+    check(5, ret == EINVAL.wrapping_neg());
+
+    let heap = syscall(SYS_BRK, [0; 6]);
+    let brk = syscall(SYS_BRK, [heap + PAGE_SIZE, 0, 0, 0, 0, 0]);
+    check(6, brk == heap + PAGE_SIZE);
+    unsafe { *(heap as *mut usize) = heap };
+
+    let area = syscall(
+        SYS_MMAP,
+        [
+            0,
+            PAGE_SIZE,
+            PROT_READ | PROT_WRITE,
+            MAP_PRIVATE | MAP_ANONYMOUS,
+            usize::MAX,
+            0,
+        ],
+    );
+    check(7, (area as isize) > 0);
+    unsafe { *(area as *mut usize) = area };
+
+    let ret = syscall(SYS_IOCTL, [1, 0, 0, 0, 0, 0]);
+    check(8, ret == ENOSYS.wrapping_neg());
+
+    exit_group(0);
+}
+
+#[panic_handler]
+fn panic(_: &PanicInfo) -> ! {
+    exit_group(usize::MAX);
+}
@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+
+const SYS_BRK: usize = 0x02;
+const SYS_MMAP: usize = 0x03;
+const SYS_MUNMAP: usize = 0x04;
+const SYS_MPROTECT: usize = 0x05;
+
+const PROT_READ: usize = 0x1;
+const PROT_WRITE: usize = 0x2;
+
+const PAGE_SIZE: usize = 4096;
+
+fn syscall(num: usize, arg0: usize, arg1: usize, arg2: usize, arg3: usize) -> usize {
+    let mut ret = arg0;
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") ret,
+            in("a1") arg1,
+            in("a2") arg2,
+            in("a3") arg3,
+            in("a7") num,
+        );
+    }
+    ret
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    let heap = syscall(SYS_BRK, 0, 0, 0, 0);
+    let brk = syscall(SYS_BRK, heap + PAGE_SIZE * 3, 0, 0, 0);
+    assert_eq!(brk, heap + PAGE_SIZE * 3);
+    for addr in (heap..brk).step_by(PAGE_SIZE) {
+        unsafe { *(addr as *mut usize) = addr };
+    }
+
+    let area = syscall(SYS_MMAP, 0, PAGE_SIZE * 2, PROT_READ | PROT_WRITE, 0);
+    assert_ne!(area, usize::MAX);
+    unsafe { *(area as *mut usize) = area };
+
+    assert_eq!(syscall(SYS_MPROTECT, area, PAGE_SIZE, PROT_READ, 0), 0);
+    assert_eq!(syscall(SYS_MUNMAP, area + PAGE_SIZE, PAGE_SIZE, 0, 0), 0);
+
+    syscall(0xC0DE, heap, area, 0, 0);
+
+    // the first page of the area is read-only by now
+    unsafe { *(area as *mut usize) = 0 };
+
+    loop {}
+}
+
+#[panic_handler]
+fn panic(_: &PanicInfo) -> ! {
+    unreachable!();
+}
@@ -0,0 +1,87 @@
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+
+const SYS_FORK: usize = 0x01;
+const SYS_READ: usize = 0x0D;
+const SYS_WRITE: usize = 0x0E;
+const SYS_CLOSE: usize = 0x0F;
+const SYS_DUP: usize = 0x10;
+const SYS_PIPE: usize = 0x11;
+
+const O_NONBLOCK: usize = 0x800;
+
+const STDOUT: usize = 1;
+
+fn syscall(num: usize, args: [usize; 6]) -> [usize; 6] {
+    let mut regs = args;
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") regs[0],
+            inlateout("a1") regs[1],
+            inlateout("a2") regs[2],
+            inlateout("a3") regs[3],
+            inlateout("a4") regs[4],
+            inlateout("a5") regs[5],
+            in("a7") num,
+        );
+    }
+    regs
+}
+
+fn call(num: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
+    syscall(num, [arg0, arg1, arg2, 0, 0, 0])[0]
+}
+
+fn pipe(flags: usize) -> (usize, usize) {
+    let mut fds = [0u32; 2];
+    assert_eq!(call(SYS_PIPE, fds.as_mut_ptr() as usize, flags, 0), 0);
+    (fds[0] as usize, fds[1] as usize)
+}
+
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    let (read_fd, write_fd) = pipe(0);
+
+    let child = call(SYS_FORK, 0, 0, 0);
+    if child == 0 {
+        call(SYS_CLOSE, read_fd, 0, 0);
+        let msg = b"hello, pipe";
+        let written = call(SYS_WRITE, write_fd, msg.as_ptr() as usize, msg.len());
+        let closed = call(SYS_CLOSE, write_fd, 0, 0);
+
+        syscall(0xC0DE, [written, closed, 0, 0, 0, 0]);
+        loop {}
+    }
+
+    syscall(0xC0DE, [child, 0, 0, 0, 0, 0]);
+
+    // blocks until the child writes, then reads the end of file once the child closes its end
+    call(SYS_CLOSE, write_fd, 0, 0);
+    let mut buf = [0u8; 64];
+    let len = call(SYS_READ, read_fd, buf.as_mut_ptr() as usize, buf.len());
+    let mut rest = [0u8; 8];
+    let eof = call(SYS_READ, read_fd, rest.as_mut_ptr() as usize, rest.len());
+
+    let (read_fd, _) = pipe(O_NONBLOCK);
+    let nonblock = call(SYS_READ, read_fd, rest.as_mut_ptr() as usize, rest.len());
+
+    let stdout = call(SYS_DUP, STDOUT, 0, 0);
+    let msg = b"pipe ok\n";
+    let written = call(SYS_WRITE, stdout, msg.as_ptr() as usize, msg.len());
+
+    syscall(
+        0xC0DE,
+        [len, eof, nonblock, stdout, written, buf.as_ptr() as usize],
+    );
+
+    loop {}
+}
+
+#[panic_handler]
+fn panic(_: &PanicInfo) -> ! {
+    unreachable!();
+}
@@ -0,0 +1,86 @@
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+
+const SYS_MPROTECT: usize = 0x05;
+const SYS_SHM_CREATE: usize = 0x0B;
+const SYS_SHM_OPEN: usize = 0x0C;
+const SYS_MEM_MAP: usize = 0x14;
+
+const PROT_READ: usize = 0x1;
+const PROT_WRITE: usize = 0x2;
+
+const RIGHT_READ: usize = 1 << 0;
+const RIGHT_WRITE: usize = 1 << 1;
+const RIGHT_MAP: usize = 1 << 2;
+
+const PAGE_SIZE: usize = 4096;
+
+const SHM_NAME: &str = "test-shm";
+const SHM_MAGIC: usize = 0x5A5A;
+
+const ROLE_CREATOR: usize = 0;
+
+fn syscall(num: usize, arg0: usize, arg1: usize, arg2: usize, arg3: usize) -> usize {
+    let mut ret = arg0;
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") ret,
+            in("a1") arg1,
+            in("a2") arg2,
+            in("a3") arg3,
+            in("a7") num,
+        );
+    }
+    ret
+}
+
+#[no_mangle]
+extern "C" fn _start(role: usize) -> ! {
+    let name = SHM_NAME.as_ptr() as usize;
+    let name_len = SHM_NAME.len();
+
+    if role == ROLE_CREATOR {
+        // others may only read the object
+        let handle = syscall(SYS_SHM_CREATE, name, name_len, PAGE_SIZE * 2, PROT_READ);
+        assert_ne!(handle, usize::MAX);
+        let addr = syscall(SYS_MEM_MAP, handle, 0, PROT_READ | PROT_WRITE, 0);
+        assert_ne!(addr, usize::MAX);
+        unsafe {
+            *(addr as *mut usize) = SHM_MAGIC;
+            *((addr + PAGE_SIZE) as *mut usize) = SHM_MAGIC + 1;
+        }
+
+        syscall(0xC0DE, addr, 0, 0, 0);
+    } else {
+        let rights = RIGHT_READ | RIGHT_WRITE | RIGHT_MAP;
+        assert_eq!(syscall(SYS_SHM_OPEN, name, name_len, rights, 0), usize::MAX);
+        let handle = syscall(SYS_SHM_OPEN, name, name_len, RIGHT_READ | RIGHT_MAP, 0);
+        assert_ne!(handle, usize::MAX);
+        let prot = PROT_READ | PROT_WRITE;
+        assert_eq!(syscall(SYS_MEM_MAP, handle, 0, prot, 0), usize::MAX);
+        let addr = syscall(SYS_MEM_MAP, handle, 0, PROT_READ, 0);
+        assert_ne!(addr, usize::MAX);
+        // the handle does not allow making the mapping writable later on either
+        assert_eq!(
+            syscall(SYS_MPROTECT, addr, PAGE_SIZE * 2, prot, 0),
+            usize::MAX
+        );
+        let value = unsafe { *(addr as *const usize) + *((addr + PAGE_SIZE) as *const usize) };
+
+        syscall(0xC0DE, addr, value, 0, 0);
+
+        // the mapping of the reader is read-only
+        unsafe { *(addr as *mut usize) = 0 };
+    }
+
+    loop {}
+}
+
+#[panic_handler]
+fn panic(_: &PanicInfo) -> ! {
+    unreachable!();
+}
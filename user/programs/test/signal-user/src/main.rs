@@ -0,0 +1,126 @@
+#![no_std]
+#![no_main]
+
+use core::{
+    panic::PanicInfo,
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+const SYS_BRK: usize = 0x02;
+const SYS_MMAP: usize = 0x03;
+const SYS_MUNMAP: usize = 0x04;
+const SYS_TIMER_CREATE: usize = 0x15;
+const SYS_TIMER_WAIT: usize = 0x17;
+const SYS_SIGACTION: usize = 0x18;
+const SYS_ALARM: usize = 0x1B;
+
+const PROT_READ: usize = 0x1;
+const PROT_WRITE: usize = 0x2;
+const MAP_FIXED: usize = 0x10;
+
+const SIGSEGV: usize = 11;
+const SIGALRM: usize = 14;
+const SIGTERM: usize = 15;
+
+const PAGE_SIZE: usize = 4096;
+
+const MAGIC: usize = 0x5167;
+
+const ROLE_HANDLER: usize = 0;
+
+/// The alarm timeout in microseconds.
+const ALARM_TIMEOUT: usize = 1000;
+
+static FAULT_ADDR: AtomicUsize = AtomicUsize::new(0);
+static TERM_COUNT: AtomicUsize = AtomicUsize::new(0);
+static ALRM_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+core::arch::global_asm!(
+    ".global sigreturn_trampoline",
+    "sigreturn_trampoline:",
+    "li a7, 0x19",
+    "ecall",
+);
+
+extern "C" {
+    fn sigreturn_trampoline();
+}
+
+fn syscall(num: usize, arg0: usize, arg1: usize, arg2: usize, arg3: usize) -> usize {
+    let mut ret = arg0;
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") ret,
+            in("a1") arg1,
+            in("a2") arg2,
+            in("a3") arg3,
+            in("a7") num,
+        );
+    }
+    ret
+}
+
+fn sigaction(sig: usize, handler: extern "C" fn(usize, usize, usize)) {
+    let restorer = sigreturn_trampoline as usize;
+    syscall(SYS_SIGACTION, sig, handler as usize, restorer, 0);
+}
+
+/// Maps the faulting page, so that the faulting store succeeds once it is retried.
+extern "C" fn on_segv(_sig: usize, addr: usize, _frame: usize) {
+    FAULT_ADDR.store(addr, Ordering::SeqCst);
+    let page = addr & !(PAGE_SIZE - 1);
+    syscall(SYS_MMAP, page, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_FIXED);
+}
+
+extern "C" fn on_term(_sig: usize, _info: usize, _frame: usize) {
+    TERM_COUNT.fetch_add(1, Ordering::SeqCst);
+}
+
+extern "C" fn on_alrm(_sig: usize, _info: usize, _frame: usize) {
+    ALRM_COUNT.fetch_add(1, Ordering::SeqCst);
+}
+
+#[no_mangle]
+extern "C" fn _start(role: usize) -> ! {
+    if role != ROLE_HANDLER {
+        // the default action of the alarm terminates the process while it computes
+        syscall(SYS_ALARM, ALARM_TIMEOUT, 0, 0, 0);
+        loop {}
+    }
+
+    sigaction(SIGSEGV, on_segv);
+    sigaction(SIGTERM, on_term);
+
+    let addr = syscall(SYS_MMAP, 0, PAGE_SIZE, PROT_READ | PROT_WRITE, 0);
+    syscall(SYS_MUNMAP, addr, PAGE_SIZE, 0, 0);
+    unsafe { ptr::write_volatile(addr as *mut usize, MAGIC) };
+    let value = unsafe { ptr::read_volatile(addr as *const usize) };
+
+    syscall(0xC0DE, value, FAULT_ADDR.load(Ordering::SeqCst), addr, 0);
+
+    // a signal sent meanwhile arrives with the next system call
+    syscall(SYS_BRK, 0, 0, 0, 0);
+
+    syscall(0xC0DE, TERM_COUNT.load(Ordering::SeqCst), 0, 0, 0);
+
+    // the alarm interrupts waiting on a timer that is never armed
+    sigaction(SIGALRM, on_alrm);
+    let timer = syscall(SYS_TIMER_CREATE, 0, 0, 0, 0);
+    syscall(SYS_ALARM, ALARM_TIMEOUT, 0, 0, 0);
+    let waited = syscall(SYS_TIMER_WAIT, timer, 0, 0, 0);
+
+    syscall(0xC0DE, waited, ALRM_COUNT.load(Ordering::SeqCst), 0, 0);
+
+    loop {
+        syscall(SYS_TIMER_WAIT, timer, 0, 0, 0);
+    }
+}
+
+#[panic_handler]
+fn panic(_: &PanicInfo) -> ! {
+    unreachable!();
+}
@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+
+const SYS_MPROTECT: usize = 0x05;
+const SYS_MMIO_MAP: usize = 0x1C;
+const SYS_IRQ_CLAIM: usize = 0x1D;
+const SYS_IRQ_WAIT: usize = 0x1E;
+const SYS_IRQ_ACK: usize = 0x1F;
+
+const PROT_READ: usize = 0x1;
+const PROT_WRITE: usize = 0x2;
+const PROT_EXEC: usize = 0x4;
+
+const PAGE_SIZE: usize = 4096;
+
+// registers of the goldfish RTC, which counts nanoseconds
+const RTC_TIME_LOW: usize = 0x00;
+const RTC_TIME_HIGH: usize = 0x04;
+const RTC_ALARM_LOW: usize = 0x08;
+const RTC_ALARM_HIGH: usize = 0x0C;
+const RTC_IRQ_ENABLED: usize = 0x10;
+const RTC_CLEAR_INTERRUPT: usize = 0x1C;
+
+const ALARM_DELAY_NS: u64 = 1_000_000;
+
+fn syscall(num: usize, args: [usize; 6]) -> [usize; 6] {
+    let mut regs = args;
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") regs[0],
+            inlateout("a1") regs[1],
+            inlateout("a2") regs[2],
+            inlateout("a3") regs[3],
+            inlateout("a4") regs[4],
+            inlateout("a5") regs[5],
+            in("a7") num,
+        );
+    }
+    regs
+}
+
+fn call(num: usize, arg0: usize, arg1: usize, arg2: usize, arg3: usize) -> usize {
+    syscall(num, [arg0, arg1, arg2, arg3, 0, 0])[0]
+}
+
+fn read_reg(base: usize, offset: usize) -> u32 {
+    unsafe { core::ptr::read_volatile((base + offset) as *const u32) }
+}
+
+fn write_reg(base: usize, offset: usize, value: u32) {
+    unsafe { core::ptr::write_volatile((base + offset) as *mut u32, value) }
+}
+
+#[no_mangle]
+extern "C" fn _start(rtc: usize, irq: usize) -> ! {
+    let line = call(SYS_IRQ_CLAIM, irq, 0, 0, 0);
+    assert_ne!(line, usize::MAX);
+    let claimed_twice = call(SYS_IRQ_CLAIM, irq, 0, 0, 0);
+
+    let base = call(SYS_MMIO_MAP, rtc, PAGE_SIZE, 0, PROT_READ | PROT_WRITE);
+    assert_ne!(base, usize::MAX);
+    // the page right after the RTC does not belong to it
+    let beyond = call(SYS_MMIO_MAP, rtc + PAGE_SIZE, PAGE_SIZE, 0, PROT_READ);
+    // the registers can never be made executable
+    let executable = call(SYS_MPROTECT, base, PAGE_SIZE, PROT_READ | PROT_EXEC, 0);
+
+    // reading the low half latches the high half
+    let low = read_reg(base, RTC_TIME_LOW) as u64;
+    let now = (read_reg(base, RTC_TIME_HIGH) as u64) << 32 | low;
+
+    let alarm = now + ALARM_DELAY_NS;
+    write_reg(base, RTC_IRQ_ENABLED, 1);
+    write_reg(base, RTC_ALARM_HIGH, (alarm >> 32) as u32);
+    write_reg(base, RTC_ALARM_LOW, alarm as u32);
+
+    let waited = call(SYS_IRQ_WAIT, line, 0, 0, 0);
+    let low = read_reg(base, RTC_TIME_LOW) as u64;
+    let fired_at = (read_reg(base, RTC_TIME_HIGH) as u64) << 32 | low;
+    write_reg(base, RTC_CLEAR_INTERRUPT, 1);
+    let acked = call(SYS_IRQ_ACK, line, 0, 0, 0);
+
+    let on_time = (fired_at >= alarm) as usize;
+    syscall(
+        0xC0DE,
+        [claimed_twice, beyond, waited, acked, on_time, executable],
+    );
+
+    loop {}
+}
+
+#[panic_handler]
+fn panic(_: &PanicInfo) -> ! {
+    unreachable!();
+}
@@ -38,6 +38,50 @@ impl Arch {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Paging {
+    Sv32,
+    Sv39,
+    Sv48,
+    Sv57,
+}
+
+impl FromStr for Paging {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sv32" => Ok(Self::Sv32),
+            "sv39" => Ok(Self::Sv39),
+            "sv48" => Ok(Self::Sv48),
+            "sv57" => Ok(Self::Sv57),
+            _ => Err("Unknown paging mode".to_string()),
+        }
+    }
+}
+
+impl Display for Paging {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Paging::Sv32 => write!(f, "sv32"),
+            Paging::Sv39 => write!(f, "sv39"),
+            Paging::Sv48 => write!(f, "sv48"),
+            Paging::Sv57 => write!(f, "sv57"),
+        }
+    }
+}
+
+impl Paging {
+    /// Returns the kernel feature selecting the mode, if it is not the default one of its arch.
+    pub fn feature(&self) -> Option<&str> {
+        match self {
+            Paging::Sv32 | Paging::Sv39 => None,
+            Paging::Sv48 => Some("sv48"),
+            Paging::Sv57 => Some("sv57"),
+        }
+    }
+}
+
 #[derive(Debug, Args, Clone, Copy)]
 pub struct ArchArg {
     #[clap(long, short = 'a', env = "ARCH")]
@@ -6,7 +6,7 @@ use std::{
 use clap::Args;
 
 use crate::{
-    arch::ArchArg,
+    arch::{ArchArg, Paging},
     envs,
     util::{cargo::Cargo, CmdOptional},
 };
@@ -19,6 +19,10 @@ pub struct MakeArg {
     #[clap(flatten)]
     pub arch: ArchArg,
 
+    /// Paging mode, the default one of the arch if not given.
+    #[clap(long, env = "PAGING")]
+    pub paging: Option<Paging>,
+
     #[clap(long, env = "LOGLEVEL")]
     pub log_level: Option<String>,
 
@@ -92,6 +96,7 @@ pub fn run(arg: &MakeArg) -> Option<ExitStatus> {
 pub fn kernel(cmd: &mut Cargo, arg: &MakeArg) {
     let MakeArg {
         arch,
+        paging,
         feat,
         no_default_feat,
         log_level,
@@ -100,6 +105,12 @@ pub fn kernel(cmd: &mut Cargo, arg: &MakeArg) {
 
     let ArchArg { arch, .. } = arch;
 
+    let feat = feat
+        .into_iter()
+        .chain(paging.and_then(|paging| paging.feature().map(String::from)))
+        .collect::<Vec<_>>();
+    let heap_trace = feat.iter().any(|feat| feat == "heap-trace");
+
     cmd.package("jrinx")
         .target(
             fs::canonicalize(
@@ -123,5 +134,13 @@ pub fn kernel(cmd: &mut Cargo, arg: &MakeArg) {
         )
         .optional(log_level.is_some(), |cargo| {
             cargo.env("LOGLEVEL", log_level.unwrap())
+        })
+        .optional(heap_trace, |cargo| {
+            // call sites of heap allocations are found through the frame pointers
+            let rustflags = env::var("RUSTFLAGS").unwrap_or_default();
+            cargo.env(
+                "RUSTFLAGS",
+                format!("{rustflags} -C force-frame-pointers=yes"),
+            )
         });
 }
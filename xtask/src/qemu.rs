@@ -25,6 +25,10 @@ pub struct QemuArg {
     #[clap(long, env = "BOOTARGS")]
     pub bootargs: Option<String>,
 
+    /// Raw image attached as a virtio block device, e.g. to receive core dumps.
+    #[clap(long, env = "DISK")]
+    pub disk: Option<String>,
+
     #[clap(long, short = 'n')]
     pub no_build: bool,
 
@@ -40,6 +44,7 @@ pub fn run(arg: &QemuArg) -> Option<ExitStatus> {
         smp,
         memory,
         bootargs,
+        disk,
         no_build,
         make_arg,
     } = arg.clone();
@@ -69,6 +74,9 @@ pub fn run(arg: &QemuArg) -> Option<ExitStatus> {
             qemu.bootargs(bootargs.unwrap().as_str())
         })
         .optional(gdb, |qemu| qemu.gdb_server())
+        .optional(disk.is_some(), |qemu| {
+            qemu.block_device(disk.unwrap().as_str())
+        })
         .args(["-device","virtio-net-device,netdev=net0","-netdev","user,id=net0,hostfwd=tcp::5555-:5555,hostfwd=udp::5555-:5555","-object","filter-dump,id=f1,netdev=net0,file=dump.dat"])
         // .args(["-icount","shift=auto,align=off,sleep=off"])
         .status()
@@ -55,6 +55,16 @@ impl Qemu {
         self.arg("-no-reboot");
         self
     }
+
+    pub fn block_device(&mut self, image: &str) -> &mut Self {
+        self.args([
+            "-drive",
+            format!("file={image},if=none,format=raw,id=blk0").as_str(),
+            "-device",
+            "virtio-blk-device,drive=blk0",
+        ]);
+        self
+    }
 }
 
 impl CmdOptional for Qemu {}